@@ -0,0 +1,90 @@
+//! Pipeline benchmarks: parse, compile and PCRE2 emit measured separately
+//! over the patterns in `benches/fixtures`.
+//!
+//! Each stage is fed the output of the previous one, computed up front, so
+//! its numbers cover that stage alone. The `batch` group compiles many
+//! similar patterns with one compiler, with and without literal interning,
+//! and the `allocations` group counts the heap allocations of the whole
+//! pipeline instead of timing it.
+
+mod support;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use strling::core::compiler::Compiler;
+use strling::core::parser::Parser;
+use strling::emitters::pcre2::PCRE2Emitter;
+use support::allocations::Allocations;
+
+fn compile_to_pcre2(src: &str) -> String {
+    let (flags, ast) = Parser::new(src.to_string()).parse().unwrap();
+    let ir = Compiler::new().with_flags(flags.clone()).compile(&ast);
+    PCRE2Emitter::new(flags).emit(&ir)
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for fixture in support::corpus() {
+        group.bench_function(&fixture.name, |b| {
+            b.iter(|| Parser::new(black_box(fixture.source.clone())).parse().unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_compile(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compile");
+    for fixture in support::corpus() {
+        let (flags, ast) = strling::parse(&fixture.source).unwrap();
+        group.bench_function(&fixture.name, |b| {
+            b.iter(|| Compiler::new().with_flags(flags.clone()).compile(black_box(&ast)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_batch(c: &mut Criterion) {
+    let asts: Vec<_> = (0..1000)
+        .map(|i| {
+            let source = format!("(?:GET|POST|PUT) /api/v{}/items/[0-9]+", i % 3);
+            strling::parse(&source).unwrap().1
+        })
+        .collect();
+    let mut group = c.benchmark_group("batch");
+    for (name, intern) in [("plain", false), ("interned", true)] {
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut compiler = Compiler::new().with_interner(intern);
+                asts.iter().map(|ast| compiler.compile(black_box(ast))).collect::<Vec<_>>()
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_emit(c: &mut Criterion) {
+    let mut group = c.benchmark_group("emit");
+    for fixture in support::corpus() {
+        let (flags, ast) = strling::parse(&fixture.source).unwrap();
+        let ir = Compiler::new().with_flags(flags.clone()).compile(&ast);
+        let emitter = PCRE2Emitter::new(flags);
+        group.bench_function(&fixture.name, |b| b.iter(|| emitter.emit(black_box(&ir))));
+    }
+    group.finish();
+}
+
+fn bench_allocations(c: &mut Criterion<Allocations>) {
+    let mut group = c.benchmark_group("allocations");
+    for fixture in support::corpus() {
+        group.bench_function(&fixture.name, |b| b.iter(|| compile_to_pcre2(black_box(&fixture.source))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_compile, bench_batch, bench_emit);
+criterion_group! {
+    name = allocations;
+    // Counts do not vary between samples, which the plots cannot draw.
+    config = Criterion::default().with_measurement(Allocations).without_plots();
+    targets = bench_allocations
+}
+criterion_main!(benches, allocations);
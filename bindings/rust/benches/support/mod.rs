@@ -0,0 +1,60 @@
+//! Fixture loading and measurements shared by the benchmarks.
+
+pub mod allocations;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A STRling pattern read from `benches/fixtures`.
+pub struct Fixture {
+    /// File stem, used as the benchmark id.
+    pub name: String,
+    pub source: String,
+}
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("benches").join("fixtures")
+}
+
+/// Large generated patterns, one per shape the parser has to handle in
+/// bulk: a few kilobytes of plain text like a generated allow-list entry,
+/// runs of quantified atoms, and a long alternation
+fn generated() -> Vec<Fixture> {
+    let words: Vec<_> = (0..500).map(|i| format!("word{}", i)).collect();
+    [
+        ("literal_heavy", "the quick brown fox jumps over the lazy dog ".repeat(100)),
+        ("quantifier_heavy", "a*b+c?d{2,5}e*?f++".repeat(100)),
+        ("alternation_heavy", words.join("|")),
+    ]
+    .into_iter()
+    .map(|(name, source)| Fixture { name: name.to_string(), source })
+    .collect()
+}
+
+/// Every `*.pattern` file in `benches/fixtures` plus the generated
+/// patterns, sorted by name so that reports line up between runs
+///
+/// Panics if the directory is missing or a fixture does not parse, since a
+/// broken fixture would otherwise only show up as a suspiciously fast
+/// benchmark.
+pub fn corpus() -> Vec<Fixture> {
+    let dir = fixtures_dir();
+    let mut fixtures: Vec<Fixture> = fs::read_dir(&dir)
+        .unwrap_or_else(|err| panic!("cannot read {}: {}", dir.display(), err))
+        .map(|entry| entry.expect("fixture directory entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "pattern"))
+        .map(|path| Fixture {
+            name: path.file_stem().unwrap().to_string_lossy().into_owned(),
+            source: fs::read_to_string(&path)
+                .unwrap_or_else(|err| panic!("cannot read {}: {}", path.display(), err)),
+        })
+        .chain(generated())
+        .collect();
+    fixtures.sort_by(|a, b| a.name.cmp(&b.name));
+    for fixture in &fixtures {
+        if let Err(err) = strling::parse(&fixture.source) {
+            panic!("fixture {} does not parse: {}", fixture.name, err);
+        }
+    }
+    fixtures
+}
@@ -0,0 +1,41 @@
+//! Fuzz the full pipeline: arbitrary DSL source through parse, compile and
+//! every emitter. Errors are expected; panics and stack overflows are bugs.
+//!
+//! Run with `cargo +nightly fuzz run parse_compile_emit` from `bindings/rust`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use strling::core::compiler::Compiler;
+use strling::core::parser::Parser;
+use strling::emitters::dot::to_dot;
+use strling::emitters::dotnet::DotNetEmitter;
+use strling::emitters::pcre2::PCRE2Emitter;
+use strling::emitters::php::PhpEmitter;
+use strling::emitters::regex_automata::RegexAutomataEmitter;
+use strling::emitters::rust_regex::RustRegexEmitter;
+
+fuzz_target!(|src: &str| {
+    let mut parser = Parser::new(src.to_string());
+    let _ = parser.tokens().count();
+    let _ = parser.clone().parse_recoverable();
+
+    let Ok((flags, ast)) = parser.parse() else {
+        return;
+    };
+    let _ = Compiler::new()
+        .with_flags(flags.clone())
+        .with_expand_bounded_repetition(true)
+        .try_compile(&ast);
+
+    let ir = Compiler::new().with_flags(flags.clone()).compile(&ast);
+    let _ = PCRE2Emitter::new(flags.clone()).emit(&ir);
+    let _ = RustRegexEmitter::new(flags.clone()).emit(&ir);
+    let _ = RegexAutomataEmitter::new(flags.clone()).emit(&ir);
+    let _ = DotNetEmitter::new(flags.clone()).emit(&ir);
+    let _ = PhpEmitter::new(flags.clone()).emit(&ir);
+    if let Ok(emitter) = PhpEmitter::new(flags).with_delimiter('#') {
+        let _ = emitter.emit(&ir);
+    }
+    let _ = to_dot(&ir);
+});
@@ -0,0 +1,1051 @@
+//! STRling Analyses - Queries and normal forms over AST and IR
+//!
+//! This module hosts analyses that inspect trees without changing them, for
+//! use by tooling and tests rather than by the compile pipeline itself.
+
+use crate::core::errors::Span;
+use crate::core::ir::*;
+use crate::core::nodes::*;
+use crate::emitters::pcre2::PCRE2Emitter;
+
+/// Rewrite an AST into a canonical form
+///
+/// Trivially equivalent shapes normalize to the same tree, so patterns that
+/// differ only incidentally compare equal afterwards:
+/// - single-part sequences and single-branch alternations unwrap
+/// - nested sequences flatten and adjacent literals merge
+/// - `{1,1}` quantifiers become their bare child, unless possessive (which
+///   makes them atomic)
+/// - plain non-capturing groups become their body
+/// - comments are dropped
+pub fn canonicalize(node: &Node) -> Node {
+    match node {
+        Node::Sequence(seq) => {
+            let mut parts: Vec<Node> = Vec::new();
+            for part in seq.parts.iter().map(canonicalize) {
+                let flattened = match part {
+                    Node::Sequence(inner) => inner.parts,
+                    other => vec![other],
+                };
+                for part in flattened {
+                    match (parts.last_mut(), part) {
+                        (_, Node::Literal(lit)) if lit.value.is_empty() => {}
+                        (_, Node::Comment(_)) => {}
+                        (Some(Node::Literal(prev)), Node::Literal(lit)) => prev.value.push_str(&lit.value),
+                        (_, part) => parts.push(part),
+                    }
+                }
+            }
+            match parts.len() {
+                0 => Node::Literal(Literal { value: String::new(), span: Default::default() }),
+                1 => parts.pop().unwrap(),
+                _ => Node::Sequence(Sequence { parts, span: Default::default() }),
+            }
+        }
+        Node::Alternation(alt) => {
+            let mut branches: Vec<Node> = alt.branches.iter().map(canonicalize).collect();
+            if branches.len() == 1 {
+                branches.pop().unwrap()
+            } else {
+                Node::Alternation(Alternation { branches, span: Default::default() })
+            }
+        }
+        Node::Quantifier(q) if q.min == 1 && q.max == MaxBound::Finite(1) && !q.possessive => {
+            canonicalize(&q.target.child)
+        }
+        Node::Quantifier(q) => Node::Quantifier(Quantifier {
+            target: QuantifierTarget {
+                child: Box::new(canonicalize(&q.target.child)),
+            },
+            ..q.clone()
+        }),
+        Node::Group(group) if !group.capturing && group.atomic != Some(true) && !group.body.sets_flags() => {
+            canonicalize(&group.body)
+        }
+        Node::Group(group) => Node::Group(Group {
+            body: Box::new(canonicalize(&group.body)),
+            atomic: Some(group.atomic.unwrap_or(false)),
+            ..group.clone()
+        }),
+        Node::BranchReset(reset) => Node::BranchReset(BranchReset {
+            branches: reset.branches.iter().map(canonicalize).collect(),
+            span: reset.span,
+        }),
+        Node::BalancingGroup(group) => Node::BalancingGroup(BalancingGroup {
+            body: Box::new(canonicalize(&group.body)),
+            ..group.clone()
+        }),
+        Node::Comment(_) => Node::Literal(Literal { value: String::new(), span: Default::default() }),
+        Node::Lookahead(look) => Node::Lookahead(canonical_look(look)),
+        Node::NegativeLookahead(look) => Node::NegativeLookahead(canonical_look(look)),
+        Node::Lookbehind(look) => Node::Lookbehind(canonical_look(look)),
+        Node::NegativeLookbehind(look) => Node::NegativeLookbehind(canonical_look(look)),
+        other => other.clone(),
+    }
+}
+
+fn canonical_look(look: &LookaroundBody) -> LookaroundBody {
+    LookaroundBody {
+        body: Box::new(canonicalize(&look.body)),
+        span: look.span,
+    }
+}
+
+/// Compare two IR trees, ignoring differences that cannot affect matching
+///
+/// Unlike `PartialEq`, which stays exact, this treats the following as equal:
+/// - literal runs split differently across `Lit` nodes (`abc` vs `a`,`b`,`c`)
+/// - nested or single-part sequences and single-branch alternations
+/// - `{1,1}` quantifiers and their bare child, unless possessive (which
+///   makes them atomic)
+/// - plain non-capturing groups and their body
+pub fn structurally_equal(a: &IROp, b: &IROp) -> bool {
+    canonical(a) == canonical(b)
+}
+
+/// Rewrite `op` into a canonical shape for `structurally_equal`
+fn canonical(op: &IROp) -> IROp {
+    match op {
+        IROp::Seq(seq) => {
+            let mut parts: Vec<IROp> = Vec::new();
+            for part in seq.parts.iter().map(canonical) {
+                let flattened = match part {
+                    IROp::Seq(inner) => inner.parts,
+                    other => vec![other],
+                };
+                for part in flattened {
+                    match (parts.last_mut(), part) {
+                        (_, IROp::Lit(lit)) if lit.value.is_empty() => {}
+                        (Some(IROp::Lit(prev)), IROp::Lit(lit)) => {
+                            prev.value = [&*prev.value, &*lit.value].concat().into()
+                        }
+                        (_, part) => parts.push(part),
+                    }
+                }
+            }
+            match parts.len() {
+                0 => IROp::Lit(IRLit { value: "".into(), span: Default::default() }),
+                1 => parts.pop().unwrap(),
+                _ => IROp::Seq(IRSeq { parts, span: Default::default() }),
+            }
+        }
+        IROp::Alt(alt) => {
+            let mut branches: Vec<IROp> = alt.branches.iter().map(canonical).collect();
+            if branches.len() == 1 {
+                branches.pop().unwrap()
+            } else {
+                IROp::Alt(IRAlt { branches, span: Default::default() })
+            }
+        }
+        IROp::Quant(quant) if quant.min == 1 && quant.max == IRMaxBound::Finite(1) && quant.mode != QuantMode::Possessive => {
+            canonical(&quant.child)
+        }
+        IROp::Quant(quant) => IROp::Quant(IRQuant {
+            child: Box::new(canonical(&quant.child)),
+            ..quant.clone()
+        }),
+        IROp::Group(group) if !group.capturing && !group.atomic && !group.body.sets_flags() => {
+            canonical(&group.body)
+        }
+        IROp::Group(group) => IROp::Group(IRGroup {
+            body: Box::new(canonical(&group.body)),
+            ..group.clone()
+        }),
+        IROp::BranchReset(reset) => IROp::BranchReset(IRBranchReset {
+            branches: reset.branches.iter().map(canonical).collect(),
+            span: reset.span,
+        }),
+        IROp::BalancingGroup(group) => IROp::BalancingGroup(IRBalancingGroup {
+            body: Box::new(canonical(&group.body)),
+            ..group.clone()
+        }),
+        IROp::Look(look) => IROp::Look(IRLook {
+            body: Box::new(canonical(&look.body)),
+            ..look.clone()
+        }),
+        other => other.clone(),
+    }
+}
+
+/// One place where two IR trees differ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IrDiff {
+    /// Steps from the root to the differing op, e.g. `["branch 2 of
+    /// alternation", "child of quantifier"]`; empty for the root itself
+    pub path: Vec<String>,
+    /// How the two ops differ, e.g. `literal "x" vs "y"`
+    pub detail: String,
+}
+
+impl std::fmt::Display for IrDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = if self.path.is_empty() { "root".to_string() } else { self.path.join(" > ") };
+        write!(f, "{} differs: {}", path, self.detail)
+    }
+}
+
+/// Compare two IR trees exactly and report where they diverge
+///
+/// Matching ops are descended into side by side, so each difference is
+/// reported at the deepest op that differs by itself; an empty result means
+/// the trees are equal. Differences are listed in pre-order.
+pub fn diff(a: &IROp, b: &IROp) -> Vec<IrDiff> {
+    let mut diffs = Vec::new();
+    diff_into(a, b, &mut Vec::new(), &mut diffs);
+    diffs
+}
+
+fn diff_into(a: &IROp, b: &IROp, path: &mut Vec<String>, diffs: &mut Vec<IrDiff>) {
+    if a == b {
+        return;
+    }
+    let mut report = |detail: String| diffs.push(IrDiff { path: path.clone(), detail });
+    match (a, b) {
+        (IROp::Seq(x), IROp::Seq(y)) if x.parts.len() == y.parts.len() => {
+            diff_children(&x.parts, &y.parts, "part", "sequence", path, diffs);
+        }
+        (IROp::Alt(x), IROp::Alt(y)) if x.branches.len() == y.branches.len() => {
+            diff_children(&x.branches, &y.branches, "branch", "alternation", path, diffs);
+        }
+        (IROp::BranchReset(x), IROp::BranchReset(y)) if x.branches.len() == y.branches.len() => {
+            diff_children(&x.branches, &y.branches, "branch", "branch reset", path, diffs);
+        }
+        (IROp::Quant(x), IROp::Quant(y)) => {
+            if (x.min, &x.max, x.mode) != (y.min, &y.max, y.mode) {
+                report(format!("quantifier {} vs {}", quantifier(x), quantifier(y)));
+            }
+            diff_child(&x.child, &y.child, "child of quantifier", path, diffs);
+        }
+        (IROp::Group(x), IROp::Group(y)) => {
+            if (x.capturing, &x.name, x.atomic, x.index) != (y.capturing, &y.name, y.atomic, y.index) {
+                report(format!("{} vs {}", describe(a), describe(b)));
+            }
+            diff_child(&x.body, &y.body, "body of group", path, diffs);
+        }
+        (IROp::BalancingGroup(x), IROp::BalancingGroup(y)) => {
+            if (&x.push, &x.pop) != (&y.push, &y.pop) {
+                report(format!("{} vs {}", describe(a), describe(b)));
+            }
+            diff_child(&x.body, &y.body, "body of balancing group", path, diffs);
+        }
+        (IROp::Look(x), IROp::Look(y)) => {
+            if (x.dir, x.neg) != (y.dir, y.neg) {
+                report(format!("{} vs {}", describe(a), describe(b)));
+            }
+            diff_child(&x.body, &y.body, "body of lookaround", path, diffs);
+        }
+        (IROp::Lit(x), IROp::Lit(y)) => report(format!("literal {:?} vs {:?}", x.value, y.value)),
+        _ => report(format!("{} vs {}", describe(a), describe(b))),
+    }
+}
+
+fn diff_children(
+    a: &[IROp],
+    b: &[IROp],
+    item: &str,
+    container: &str,
+    path: &mut Vec<String>,
+    diffs: &mut Vec<IrDiff>,
+) {
+    for (i, (x, y)) in a.iter().zip(b).enumerate() {
+        diff_child(x, y, &format!("{} {} of {}", item, i + 1, container), path, diffs);
+    }
+}
+
+fn diff_child(a: &IROp, b: &IROp, step: &str, path: &mut Vec<String>, diffs: &mut Vec<IrDiff>) {
+    path.push(step.to_string());
+    diff_into(a, b, path, diffs);
+    path.pop();
+}
+
+/// A short name for `op` and its own attributes, e.g. "capturing group #1"
+fn describe(op: &IROp) -> String {
+    match op {
+        IROp::Alt(alt) => format!("alternation of {} branches", alt.branches.len()),
+        IROp::Seq(seq) => format!("sequence of {} parts", seq.parts.len()),
+        IROp::BranchReset(reset) => format!("branch reset of {} branches", reset.branches.len()),
+        IROp::Lit(lit) => format!("literal {:?}", lit.value),
+        IROp::Dot(dot) if dot.dotall => "dot (dotall)".to_string(),
+        IROp::Dot(_) => "dot".to_string(),
+        IROp::Anchor(anchor) => format!("anchor {}", anchor.at),
+        IROp::CharClass(cc) => format!("class {}", PCRE2Emitter::new(Flags::default()).emit(&IROp::CharClass(cc.clone()))),
+        IROp::Quant(quant) => format!("quantifier {}", quantifier(quant)),
+        IROp::Group(group) if group.atomic => "atomic group".to_string(),
+        IROp::Group(group) if !group.capturing => "non-capturing group".to_string(),
+        IROp::Group(group) => match &group.name {
+            Some(name) => format!("capturing group #{} <{}>", group.index, name),
+            None => format!("capturing group #{}", group.index),
+        },
+        IROp::BalancingGroup(group) => balancing_group(&group.push, &group.pop),
+        IROp::Backref(backref) => match (&backref.by_name, backref.by_index) {
+            (Some(name), _) => format!("backreference <{}>", name),
+            (None, Some(index)) => format!("backreference #{}", index),
+            (None, None) => "backreference".to_string(),
+        },
+        IROp::Look(look) => format!("{} look{}", if look.neg { "negative" } else { "positive" }, look.dir.as_str().to_lowercase()),
+        IROp::SetFlags(set) => format!("flag setting {}", set.to_inline()),
+        IROp::Verb(verb) => format!("verb {}", verb.to_inline()),
+    }
+}
+
+/// A short name for a balancing group, e.g. "balancing group <close-open>"
+fn balancing_group(push: &Option<String>, pop: &str) -> String {
+    format!("balancing group <{}-{}>", push.as_deref().unwrap_or(""), pop)
+}
+
+/// A quantifier's bounds and mode in brace form, e.g. `{1,}?`
+fn quantifier(quant: &IRQuant) -> String {
+    let bounds = match &quant.max {
+        IRMaxBound::Finite(max) if *max == quant.min => format!("{{{}}}", max),
+        IRMaxBound::Finite(max) => format!("{{{},{}}}", quant.min, max),
+        IRMaxBound::Infinite(_) => format!("{{{},}}", quant.min),
+    };
+    let mode = match quant.mode {
+        QuantMode::Greedy => "",
+        QuantMode::Lazy => "?",
+        QuantMode::Possessive => "+",
+    };
+    bounds + mode
+}
+
+/// How a node differs between two ASTs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AstChange {
+    /// The node is only in the second tree.
+    Added,
+    /// The node is only in the first tree.
+    Removed,
+    /// The node is in both trees with different contents of its own.
+    Changed,
+}
+
+/// One place where two ASTs differ
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AstDiff {
+    pub change: AstChange,
+    /// Span of the node in the first tree; `None` when added or unparsed
+    pub old_span: Option<Span>,
+    /// Span of the node in the second tree; `None` when removed or unparsed
+    pub new_span: Option<Span>,
+    /// What differs, e.g. `literal "c" vs "d"`
+    pub detail: String,
+}
+
+impl std::fmt::Display for AstDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let at = |span: Option<Span>| span.map_or("?".to_string(), |(start, end)| format!("{}..{}", start, end));
+        match self.change {
+            AstChange::Added => write!(f, "added at {}: {}", at(self.new_span), self.detail),
+            AstChange::Removed => write!(f, "removed at {}: {}", at(self.old_span), self.detail),
+            AstChange::Changed => {
+                write!(f, "changed at {} -> {}: {}", at(self.old_span), at(self.new_span), self.detail)
+            }
+        }
+    }
+}
+
+/// Compare two ASTs, e.g. two versions of a pattern, and report where
+/// they diverge
+///
+/// Like `diff` for IR, this is a recursive structural comparison rather
+/// than a minimal edit script: sequences and alternations are compared
+/// item by item, with any surplus items reported as added or removed.
+/// Spans are ignored when comparing but reported for each difference.
+pub fn diff_ast(a: &Node, b: &Node) -> Vec<AstDiff> {
+    let mut diffs = Vec::new();
+    diff_ast_into(a, b, &mut diffs);
+    diffs
+}
+
+fn diff_ast_into(a: &Node, b: &Node, diffs: &mut Vec<AstDiff>) {
+    if a == b {
+        return;
+    }
+    let mut report = |detail: String| {
+        diffs.push(AstDiff { change: AstChange::Changed, old_span: a.span(), new_span: b.span(), detail })
+    };
+    match (a, b) {
+        (Node::Sequence(x), Node::Sequence(y)) => diff_ast_items(&x.parts, &y.parts, diffs),
+        (Node::Alternation(x), Node::Alternation(y)) => diff_ast_items(&x.branches, &y.branches, diffs),
+        (Node::BranchReset(x), Node::BranchReset(y)) => diff_ast_items(&x.branches, &y.branches, diffs),
+        (Node::Quantifier(x), Node::Quantifier(y)) => {
+            if (x.min, &x.max, &x.mode) != (y.min, &y.max, &y.mode) {
+                report(format!("{} vs {}", describe_node(a), describe_node(b)));
+            }
+            diff_ast_into(&x.target.child, &y.target.child, diffs);
+        }
+        (Node::Group(x), Node::Group(y)) => {
+            if (x.capturing, &x.name, x.atomic) != (y.capturing, &y.name, y.atomic) {
+                report(format!("{} vs {}", describe_node(a), describe_node(b)));
+            }
+            diff_ast_into(&x.body, &y.body, diffs);
+        }
+        (Node::BalancingGroup(x), Node::BalancingGroup(y)) => {
+            if (&x.push, &x.pop) != (&y.push, &y.pop) {
+                report(format!("{} vs {}", describe_node(a), describe_node(b)));
+            }
+            diff_ast_into(&x.body, &y.body, diffs);
+        }
+        (Node::Lookahead(x), Node::Lookahead(y))
+        | (Node::NegativeLookahead(x), Node::NegativeLookahead(y))
+        | (Node::Lookbehind(x), Node::Lookbehind(y))
+        | (Node::NegativeLookbehind(x), Node::NegativeLookbehind(y)) => diff_ast_into(&x.body, &y.body, diffs),
+        (Node::Literal(x), Node::Literal(y)) => report(format!("literal {:?} vs {:?}", x.value, y.value)),
+        _ => report(format!("{} vs {}", describe_node(a), describe_node(b))),
+    }
+}
+
+fn diff_ast_items(a: &[Node], b: &[Node], diffs: &mut Vec<AstDiff>) {
+    for (x, y) in a.iter().zip(b) {
+        diff_ast_into(x, y, diffs);
+    }
+    for x in a.iter().skip(b.len()) {
+        diffs.push(AstDiff { change: AstChange::Removed, old_span: x.span(), new_span: None, detail: describe_node(x) });
+    }
+    for y in b.iter().skip(a.len()) {
+        diffs.push(AstDiff { change: AstChange::Added, old_span: None, new_span: y.span(), detail: describe_node(y) });
+    }
+}
+
+/// A short name for `node` and its own attributes, e.g. "capturing group <x>"
+fn describe_node(node: &Node) -> String {
+    match node {
+        Node::Alternation(alt) => format!("alternation of {} branches", alt.branches.len()),
+        Node::Sequence(seq) => format!("sequence of {} parts", seq.parts.len()),
+        Node::BranchReset(reset) => format!("branch reset of {} branches", reset.branches.len()),
+        Node::Literal(lit) => format!("literal {:?}", lit.value),
+        Node::Dot(_) => "dot".to_string(),
+        Node::Anchor(anchor) => format!("anchor {}", anchor.at),
+        Node::CharacterClass(cc) => format!("{}class of {} items", if cc.negated { "negated " } else { "" }, cc.items.len()),
+        Node::Quantifier(quant) => {
+            let max = match &quant.max {
+                MaxBound::Finite(max) => max.to_string(),
+                MaxBound::Infinite(_) | MaxBound::Null => String::new(),
+            };
+            format!("quantifier {{{},{}}} {}", quant.min, max, quant.mode)
+        }
+        Node::Group(group) if group.atomic == Some(true) => "atomic group".to_string(),
+        Node::Group(group) if !group.capturing => "non-capturing group".to_string(),
+        Node::Group(group) => match &group.name {
+            Some(name) => format!("capturing group <{}>", name),
+            None => "capturing group".to_string(),
+        },
+        Node::BalancingGroup(group) => balancing_group(&group.push, &group.pop),
+        Node::Backreference(backref) => match (&backref.by_name, backref.by_index) {
+            (Some(name), _) => format!("backreference <{}>", name),
+            (None, Some(index)) => format!("backreference #{}", index),
+            (None, None) => "backreference".to_string(),
+        },
+        Node::Lookahead(_) => "positive lookahead".to_string(),
+        Node::NegativeLookahead(_) => "negative lookahead".to_string(),
+        Node::Lookbehind(_) => "positive lookbehind".to_string(),
+        Node::NegativeLookbehind(_) => "negative lookbehind".to_string(),
+        Node::SetFlags(set) => {
+            format!("flag setting on {:?} off {:?}", set.on.to_flag_string(), set.off.to_flag_string())
+        }
+        Node::Verb(verb) => match &verb.arg {
+            Some(arg) => format!("verb {} {:?}", verb.name, arg),
+            None => format!("verb {}", verb.name),
+        },
+        Node::Comment(comment) => format!("comment {:?}", comment.text),
+        Node::Error(_) => "error".to_string(),
+    }
+}
+
+/// The capturing groups of a compiled pattern, by number and name
+///
+/// Numbers are the 1-based `IRGroup::index` values the compiler assigns.
+/// In a branch reset several groups share a number; the number is named if
+/// any of them is.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CaptureMap {
+    /// Name of each group number, at position `number - 1`
+    names: Vec<Option<String>>,
+}
+
+impl CaptureMap {
+    /// Collect the capturing groups of `ir`
+    pub fn from_ir(ir: &IROp) -> Self {
+        let mut map = CaptureMap::default();
+        map.collect(ir);
+        map
+    }
+
+    fn collect(&mut self, op: &IROp) {
+        match op {
+            IROp::Group(group) => {
+                if group.capturing {
+                    if self.names.len() < group.index {
+                        self.names.resize(group.index, None);
+                    }
+                    if let Some(name) = &group.name {
+                        self.names[group.index - 1] = Some(name.clone());
+                    }
+                }
+                self.collect(&group.body);
+            }
+            IROp::Seq(seq) => seq.parts.iter().for_each(|p| self.collect(p)),
+            IROp::Alt(alt) => alt.branches.iter().for_each(|b| self.collect(b)),
+            IROp::BranchReset(reset) => reset.branches.iter().for_each(|b| self.collect(b)),
+            IROp::BalancingGroup(group) => self.collect(&group.body),
+            IROp::Quant(quant) => self.collect(&quant.child),
+            IROp::Look(look) => self.collect(&look.body),
+            _ => {}
+        }
+    }
+
+    /// Number of capture groups, i.e. the highest group number
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Whether the pattern has no capturing groups
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The name of group `number`, if it is named
+    pub fn name(&self, number: usize) -> Option<&str> {
+        self.names.get(number.checked_sub(1)?)?.as_deref()
+    }
+
+    /// The number of the group called `name`
+    pub fn number(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n.as_deref() == Some(name)).map(|i| i + 1)
+    }
+
+    /// Every group number with its name, in order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, Option<&str>)> {
+        self.names.iter().enumerate().map(|(i, name)| (i + 1, name.as_deref()))
+    }
+}
+
+/// A plain-English description of one step of a pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    /// The PCRE2 text of the step
+    pub snippet: String,
+    /// What the step matches or asserts, e.g. "match a digit, repeated
+    /// exactly 3 times"
+    pub description: String,
+}
+
+/// Describe an IR tree step by step, in pre-order
+///
+/// Sequences contribute only their parts. A quantifier over a single
+/// character-level op is described together with it as one step; over a
+/// group or other compound op it gets its own step, followed by the steps
+/// of its body.
+pub fn explain(ir: &IROp) -> Vec<Explanation> {
+    let mut steps = Vec::new();
+    explain_into(ir, &mut steps);
+    steps
+}
+
+fn explain_into(op: &IROp, steps: &mut Vec<Explanation>) {
+    let mut step = |description: String| {
+        steps.push(Explanation {
+            snippet: PCRE2Emitter::new(Flags::default()).emit(op),
+            description,
+        });
+    };
+    match op {
+        IROp::Seq(seq) => {
+            for part in &seq.parts {
+                explain_into(part, steps);
+            }
+        }
+        IROp::Alt(alt) => {
+            step(format!("match one of {} alternatives", alt.branches.len()));
+            for branch in &alt.branches {
+                explain_into(branch, steps);
+            }
+        }
+        IROp::BranchReset(reset) => {
+            step(format!(
+                "match one of {} alternatives, each numbering its captures from the same index",
+                reset.branches.len()
+            ));
+            for branch in &reset.branches {
+                explain_into(branch, steps);
+            }
+        }
+        IROp::Quant(quant) => {
+            let times = repetition(quant);
+            match describe_leaf(&quant.child) {
+                Some(child) => step(format!("{}, {}", child, times)),
+                None => {
+                    step(format!("repeat the following {}", times));
+                    explain_into(&quant.child, steps);
+                }
+            }
+        }
+        IROp::Group(group) => {
+            step(if group.atomic {
+                "atomic group: once matched, never backtrack into it".to_string()
+            } else if !group.capturing {
+                "group without capturing".to_string()
+            } else if let Some(name) = &group.name {
+                format!("capture group #{} named \"{}\"", group.index, name)
+            } else {
+                format!("capture group #{}", group.index)
+            });
+            explain_into(&group.body, steps);
+        }
+        IROp::BalancingGroup(group) => {
+            step(match &group.push {
+                Some(push) => format!(
+                    "balancing group: pop a capture of \"{}\" and capture what lies between under \"{}\"",
+                    group.pop, push
+                ),
+                None => format!("balancing group: pop a capture of \"{}\"", group.pop),
+            });
+            explain_into(&group.body, steps);
+        }
+        IROp::Look(look) => {
+            let side = if look.dir == LookDir::Behind { "precedes" } else { "follows" };
+            let outcome = if look.neg { "does not match" } else { "matches" };
+            step(format!("assert that what {} {}", side, outcome));
+            explain_into(&look.body, steps);
+        }
+        leaf => step(describe_leaf(leaf).expect("all compound ops handled above")),
+    }
+}
+
+/// Describe an op that matches or asserts at a single position, or `None`
+/// for compound ops
+fn describe_leaf(op: &IROp) -> Option<String> {
+    Some(match op {
+        IROp::Lit(lit) => format!("match {:?}", lit.value),
+        IROp::Dot(dot) if dot.dotall => "match any character".to_string(),
+        IROp::Dot(_) => "match any character except a newline".to_string(),
+        IROp::Anchor(anchor) => format!("assert {}", match anchor.at {
+            AnchorKind::Start => "the start of the line",
+            AnchorKind::End => "the end of the line",
+            AnchorKind::WordBoundary => "a word boundary",
+            AnchorKind::NotWordBoundary => "a position that is not a word boundary",
+            AnchorKind::AbsoluteStart => "the start of the string",
+            AnchorKind::AbsoluteEnd => "the end of the string",
+            AnchorKind::EndBeforeFinalNewline => "the end of the string, before any final newline",
+            AnchorKind::PreviousMatchEnd => "the position where the previous match ended",
+            AnchorKind::WordStart => "the start of a word",
+            AnchorKind::WordEnd => "the end of a word",
+        }),
+        IROp::CharClass(cc) => format!("match {}", describe_class(cc)),
+        IROp::Backref(backref) => match (&backref.by_name, backref.by_index) {
+            (Some(name), _) => format!("match the same text as group \"{}\"", name),
+            (None, Some(index)) => format!("match the same text as group #{}", index),
+            (None, None) => "match the same text as a group".to_string(),
+        },
+        IROp::SetFlags(set) => {
+            let names = |flags: &Flags| {
+                flags.active().map(|kind| format!("{:?}", kind)).collect::<Vec<_>>().join(", ")
+            };
+            match (set.on.active().next(), set.off.active().next()) {
+                (Some(_), None) => format!("turn on {} for the rest of the group", names(&set.on)),
+                (None, _) => format!("turn off {} for the rest of the group", names(&set.off)),
+                (Some(_), Some(_)) => format!(
+                    "turn on {} and off {} for the rest of the group",
+                    names(&set.on),
+                    names(&set.off)
+                ),
+            }
+        }
+        IROp::Verb(verb) => format!("apply the backtracking control verb {}", verb.to_inline()),
+        _ => return None,
+    })
+}
+
+fn describe_class(cc: &IRCharClass) -> String {
+    if cc.items.is_empty() {
+        return if cc.negated { "any character" } else { "nothing" }.to_string();
+    }
+    if let [IRClassItem::Esc(esc)] = cc.items.as_slice() {
+        let property = esc.property.as_deref().unwrap_or("");
+        let single = match esc.escape_type.as_str() {
+            "d" => Some("a digit".to_string()),
+            "D" => Some("a non-digit".to_string()),
+            "w" => Some("a word character".to_string()),
+            "W" => Some("a non-word character".to_string()),
+            "s" => Some("a whitespace character".to_string()),
+            "S" => Some("a non-whitespace character".to_string()),
+            "p" => Some(format!("a character with property {}", property)),
+            "P" => Some(format!("a character without property {}", property)),
+            _ => None,
+        };
+        if let (Some(single), false) = (single, cc.negated) {
+            return single;
+        }
+    }
+    let class = PCRE2Emitter::new(Flags::default()).emit(&IROp::CharClass(cc.clone()));
+    if cc.negated {
+        format!("any character not in {}", class)
+    } else {
+        format!("one character from {}", class)
+    }
+}
+
+fn repetition(quant: &IRQuant) -> String {
+    let times = match (quant.min, &quant.max) {
+        (0, IRMaxBound::Infinite(_)) => "repeated zero or more times".to_string(),
+        (1, IRMaxBound::Infinite(_)) => "repeated one or more times".to_string(),
+        (min, IRMaxBound::Infinite(_)) => format!("repeated at least {} times", min),
+        (0, IRMaxBound::Finite(1)) => "optionally".to_string(),
+        (min, IRMaxBound::Finite(max)) if min == *max => format!("repeated exactly {} times", min),
+        (min, IRMaxBound::Finite(max)) => format!("repeated between {} and {} times", min, max),
+    };
+    match quant.mode {
+        QuantMode::Greedy => times,
+        QuantMode::Lazy => format!("{} (lazy: as few as possible)", times),
+        QuantMode::Possessive => format!("{} (possessive: never giving any back)", times),
+    }
+}
+
+/// A lookaround assertion found in a pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LookaroundInfo {
+    /// Which side of the position the assertion inspects
+    pub dir: LookDir,
+    /// Whether the assertion is negative (`(?!..)`, `(?<!..)`)
+    pub neg: bool,
+    /// Whether the body can match strings of different lengths
+    ///
+    /// Many engines accept only fixed-length lookbehind.
+    pub variable_length: bool,
+}
+
+/// Every lookaround assertion in `ir`, in pre-order
+pub fn lookarounds(ir: &IROp) -> Vec<LookaroundInfo> {
+    let mut found = Vec::new();
+    collect_lookarounds(ir, &mut found);
+    found
+}
+
+fn collect_lookarounds(op: &IROp, found: &mut Vec<LookaroundInfo>) {
+    match op {
+        IROp::Look(look) => {
+            found.push(LookaroundInfo {
+                dir: look.dir,
+                neg: look.neg,
+                variable_length: lookbehind_is_fixed_length(&look.body).is_none(),
+            });
+            collect_lookarounds(&look.body, found);
+        }
+        IROp::Seq(seq) => seq.parts.iter().for_each(|p| collect_lookarounds(p, found)),
+        IROp::Alt(alt) => alt.branches.iter().for_each(|b| collect_lookarounds(b, found)),
+        IROp::BranchReset(reset) => reset.branches.iter().for_each(|b| collect_lookarounds(b, found)),
+        IROp::Quant(quant) => collect_lookarounds(&quant.child, found),
+        IROp::Group(group) => collect_lookarounds(&group.body, found),
+        IROp::BalancingGroup(group) => collect_lookarounds(&group.body, found),
+        _ => {}
+    }
+}
+
+/// The fewest and most characters `ir` can match; `None` means unbounded
+///
+/// Backreferences are treated as matching any length, since what they
+/// match depends on the subject. Backtracking control verbs count as
+/// matching nothing, although `(*ACCEPT)` can end a match early.
+pub fn match_len(ir: &IROp) -> (usize, Option<usize>) {
+    match ir {
+        IROp::Lit(lit) => {
+            let len = lit.value.chars().count();
+            (len, Some(len))
+        }
+        IROp::Dot(_) | IROp::CharClass(_) => (1, Some(1)),
+        IROp::Anchor(_) | IROp::Look(_) | IROp::SetFlags(_) | IROp::Verb(_) => (0, Some(0)),
+        IROp::Backref(_) => (0, None),
+        IROp::Group(group) => match_len(&group.body),
+        IROp::BalancingGroup(group) => match_len(&group.body),
+        IROp::Seq(seq) => seq.parts.iter().map(match_len).fold((0, Some(0)), |(min, max), (lo, hi)| {
+            (min.saturating_add(lo), max.zip(hi).map(|(max, hi)| max.saturating_add(hi)))
+        }),
+        IROp::Alt(IRAlt { branches, .. }) | IROp::BranchReset(IRBranchReset { branches, .. }) => {
+            let lengths: Vec<_> = branches.iter().map(match_len).collect();
+            let min = lengths.iter().map(|&(lo, _)| lo).min().unwrap_or(0);
+            let max = lengths.iter().try_fold(0, |max, &(_, hi)| hi.map(|hi| max.max(hi)));
+            (min, max)
+        }
+        IROp::Quant(quant) => {
+            let (lo, hi) = match_len(&quant.child);
+            let times = |n: i32| usize::try_from(n).unwrap_or(0);
+            let max = match (&quant.max, hi) {
+                (_, Some(0)) => Some(0),
+                (IRMaxBound::Finite(n), Some(hi)) => Some(hi.saturating_mul(times(*n))),
+                _ => None,
+            };
+            (lo.saturating_mul(times(quant.min)), max)
+        }
+    }
+}
+
+/// Whether `ir` can match the empty string
+///
+/// Iterating matches of a nullable pattern has to step past empty matches
+/// by hand, or it never advances. Backreferences count as nullable, since
+/// the group they refer to may have matched nothing.
+pub fn is_nullable(ir: &IROp) -> bool {
+    match_len(ir).0 == 0
+}
+
+/// The length in characters of every match of a lookbehind's body, or
+/// `None` if matches can differ in length
+///
+/// Engines such as JavaScript before ES2018 and RE2 reject variable-length
+/// lookbehind, and others only accept it within limits. `look` may be the
+/// lookaround itself or just its body.
+pub fn lookbehind_is_fixed_length(look: &IROp) -> Option<usize> {
+    let body = match look {
+        IROp::Look(look) => &look.body,
+        other => other,
+    };
+    match match_len(body) {
+        (min, Some(max)) if min == max => Some(min),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::parser::parse;
+
+    fn canonical_ast(src: &str) -> Node {
+        canonicalize(&parse(src).unwrap().1)
+    }
+
+    #[test]
+    fn test_canonicalize_equivalent_patterns() {
+        assert_eq!(canonical_ast("(?:a)"), canonical_ast("a"));
+        assert_eq!(canonical_ast("a{1}bc"), canonical_ast("abc"));
+        assert_eq!(canonical_ast("(?:ab)(?:c)"), Node::Literal(Literal { value: "abc".to_string(), span: Default::default() }));
+        assert_ne!(canonical_ast("(a)"), canonical_ast("a"));
+        assert_ne!(canonical_ast("a|b"), canonical_ast("ab"));
+        // `{1}+` is atomic: `(?:a|ab){1}+c` does not match "abc".
+        assert_ne!(canonical_ast("(?:a|ab){1}+c"), canonical_ast("(?:a|ab)c"));
+    }
+
+    fn lit(value: &str) -> IROp {
+        IROp::Lit(IRLit { value: value.into(), span: Default::default() })
+    }
+
+    #[test]
+    fn test_literal_run_equals_literal_sequence() {
+        let split = IROp::Seq(IRSeq { parts: vec![lit("a"), lit("b"), lit("c")], span: Default::default() });
+        assert!(structurally_equal(&lit("abc"), &split));
+        assert_ne!(lit("abc"), split);
+    }
+
+    #[test]
+    fn test_single_repetition_equals_child() {
+        let quant = IROp::Quant(IRQuant {
+            child: Box::new(lit("a")),
+            min: 1,
+            max: IRMaxBound::Finite(1),
+            mode: QuantMode::Greedy,
+            span: Default::default(),
+        });
+        assert!(structurally_equal(&quant, &lit("a")));
+    }
+
+    #[test]
+    fn test_possessive_single_repetition_kept() {
+        let compile = |src: &str| crate::core::compiler::Compiler::new().compile(&parse(src).unwrap().1);
+        // `{1}+` is atomic: `(?:a|ab){1}+c` does not match "abc".
+        assert!(!structurally_equal(&compile("(?:a|ab){1}+c"), &compile("(?:a|ab)c")));
+        assert!(structurally_equal(&compile("(?:a|ab){1}c"), &compile("(?:a|ab)c")));
+    }
+
+    #[test]
+    fn test_differences_that_matter() {
+        let group = IROp::Group(IRGroup {
+            capturing: true,
+            body: Box::new(lit("a")),
+            name: None,
+            atomic: false,
+            index: 1,
+            span: Default::default(),
+        });
+        assert!(!structurally_equal(&group, &lit("a")));
+        assert!(!structurally_equal(&lit("ab"), &lit("ba")));
+    }
+
+    fn explained(src: &str) -> Vec<(String, String)> {
+        let (flags, ast) = parse(src).unwrap();
+        let ir = crate::core::compiler::Compiler::new().with_flags(flags).compile(&ast);
+        explain(&ir).into_iter().map(|e| (e.snippet, e.description)).collect()
+    }
+
+    fn steps(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(s, d)| (s.to_string(), d.to_string())).collect()
+    }
+
+    #[test]
+    fn test_explain_quantified_shorthand() {
+        assert_eq!(
+            explained("\\d{3}"),
+            steps(&[("[\\d]{3}", "match a digit, repeated exactly 3 times")])
+        );
+        assert_eq!(
+            explained("[^a-z]+?"),
+            steps(&[(
+                "[^a-z]+?",
+                "match any character not in [^a-z], repeated one or more times (lazy: as few as possible)",
+            )])
+        );
+    }
+
+    #[test]
+    fn test_explain_groups_and_anchors() {
+        assert_eq!(
+            explained("^(?<year>\\d{4})(?:-x)?$"),
+            steps(&[
+                ("^", "assert the start of the line"),
+                ("(?<year>[\\d]{4})", "capture group #1 named \"year\""),
+                ("[\\d]{4}", "match a digit, repeated exactly 4 times"),
+                ("(?:-x)?", "repeat the following optionally"),
+                ("(?:-x)", "group without capturing"),
+                ("-x", "match \"-x\""),
+                ("$", "assert the end of the line"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_explain_lookaround_and_alternation() {
+        assert_eq!(
+            explained("(?<!a)b|."),
+            steps(&[
+                ("(?<!a)b|.", "match one of 2 alternatives"),
+                ("(?<!a)", "assert that what precedes does not match"),
+                ("a", "match \"a\""),
+                ("b", "match \"b\""),
+                (".", "match any character except a newline"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_capture_map() {
+        let (_, ast) = parse("(a)(?:b)(?<year>\\d+)(?|(?<x>c)|(d)(e))").unwrap();
+        let map = CaptureMap::from_ir(&crate::core::compiler::Compiler::new().compile(&ast));
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.name(2), Some("year"));
+        assert_eq!(map.number("x"), Some(3));
+        assert_eq!((map.name(1), map.name(0), map.name(5)), (None, None, None));
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            vec![(1, None), (2, Some("year")), (3, Some("x")), (4, None)]
+        );
+        assert!(CaptureMap::from_ir(&lit("a")).is_empty());
+    }
+
+    fn looks(src: &str) -> Vec<LookaroundInfo> {
+        lookarounds(&crate::core::compiler::Compiler::new().compile(&parse(src).unwrap().1))
+    }
+
+    #[test]
+    fn test_lookbehind_length() {
+        assert_eq!(
+            looks("(?<=ab)c"),
+            vec![LookaroundInfo { dir: LookDir::Behind, neg: false, variable_length: false }]
+        );
+        assert!(looks("(?<=a+)c")[0].variable_length);
+        assert!(looks("(?<!a|bc)")[0].variable_length);
+        assert!(!looks("(?<![a-z]{3}|\\d.x)")[0].variable_length);
+    }
+
+    #[test]
+    fn test_lookarounds_found_in_order() {
+        let found = looks("(?=a(?<!b))x|(?!c\\1?)");
+        let summary: Vec<_> = found.iter().map(|l| (l.dir, l.neg, l.variable_length)).collect();
+        assert_eq!(summary, [(LookDir::Ahead, false, false), (LookDir::Behind, true, false), (LookDir::Ahead, true, true)]);
+    }
+
+    fn len_of(src: &str) -> (usize, Option<usize>) {
+        match_len(&crate::core::compiler::Compiler::new().compile(&parse(src).unwrap().1))
+    }
+
+    #[test]
+    fn test_match_len() {
+        assert_eq!(len_of("a{2,4}"), (2, Some(4)));
+        assert_eq!(len_of("a*"), (0, None));
+        assert_eq!(len_of("^ab(?=xyz)|[0-9]\\b"), (1, Some(2)));
+        assert_eq!(len_of("(?:ab|c){3}é"), (4, Some(7)));
+        assert_eq!(len_of("(?<x>a)\\k<x>"), (1, None));
+    }
+
+    #[test]
+    fn test_lookbehind_is_fixed_length() {
+        assert_eq!(lookbehind_is_fixed_length(&ir("(?<=ab)")), Some(2));
+        assert_eq!(lookbehind_is_fixed_length(&ir("(?<![a-c]\\d|xy)")), Some(2));
+        assert_eq!(lookbehind_is_fixed_length(&ir("(?<=a+)")), None);
+        assert_eq!(lookbehind_is_fixed_length(&ir("(?<=ab|c)")), None);
+        assert_eq!(lookbehind_is_fixed_length(&ir("a{3}")), Some(3));
+    }
+
+    #[test]
+    fn test_is_nullable() {
+        for src in ["(?:a)?", "\\b", "a*", "a{0,3}", "a|", "(?:a|)", "(?=a)", "^$", "(?<x>a*)\\k<x>"] {
+            assert!(is_nullable(&ir(src)), "{:?}", src);
+        }
+        for src in ["\\d+", "a+", "a", "a|b*c", "(?=a)a", "a{2,}", "(?:a|b)(?:|c)"] {
+            assert!(!is_nullable(&ir(src)), "{:?}", src);
+        }
+    }
+
+    fn ir(src: &str) -> IROp {
+        crate::core::compiler::Compiler::new().compile(&parse(src).unwrap().1)
+    }
+
+    #[test]
+    fn test_diff_pinpoints_quantifier() {
+        let diffs = diff(&ir("ab|(x\\d{2,3})"), &ir("ab|(x\\d{2,4})"));
+        assert_eq!(diffs.len(), 1);
+        assert_eq!(
+            diffs[0].to_string(),
+            "branch 2 of alternation > body of group > part 2 of sequence differs: quantifier {2,3} vs {2,4}"
+        );
+        assert!(diff(&ir("a(b|c)+"), &ir("a(b|c)+")).is_empty());
+    }
+
+    #[test]
+    fn test_diff_ast() {
+        let ast = |src: &str| parse(src).unwrap().1;
+        let diffs = diff_ast(&ast("abc"), &ast("abd"));
+        assert_eq!(
+            diffs,
+            [AstDiff {
+                change: AstChange::Changed,
+                old_span: Some((2, 3)),
+                new_span: Some((2, 3)),
+                detail: "literal \"c\" vs \"d\"".to_string(),
+            }]
+        );
+        assert_eq!(diffs[0].to_string(), "changed at 2..3 -> 2..3: literal \"c\" vs \"d\"");
+
+        let diffs: Vec<String> = diff_ast(&ast("(?<x>a)+|b"), &ast("(a){2}|b|c")).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            diffs,
+            [
+                "changed at 0..8 -> 0..6: quantifier {1,} Greedy vs quantifier {2,2} Greedy",
+                "changed at 0..7 -> 0..3: capturing group <x> vs capturing group",
+                "added at 9..10: literal \"c\"",
+            ]
+        );
+        assert!(diff_ast(&ast("a(b|c)"), &ast("a(b|c)")).is_empty());
+    }
+
+    #[test]
+    fn test_diff_reports_each_divergence() {
+        let diffs: Vec<String> = diff(&ir("x|y|z"), &ir("x|w|[z]")).iter().map(|d| d.to_string()).collect();
+        assert_eq!(
+            diffs,
+            [
+                "branch 2 of alternation differs: literal \"y\" vs \"w\"",
+                "branch 3 of alternation differs: literal \"z\" vs class [z]",
+            ]
+        );
+        assert_eq!(diff(&ir("ab"), &ir("a|b"))[0].to_string(), "root differs: literal \"ab\" vs alternation of 2 branches");
+    }
+}
@@ -12,33 +12,152 @@
 //! The IR is designed to be easily consumed by target emitters (e.g., PCRE2)
 //! while maintaining semantic accuracy and enabling optimizations.
 
+use crate::core::errors::{CompileError, STRlingWarning, SourceSpan, Span};
 use crate::core::ir::*;
 use crate::core::nodes::*;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 /// Compiler for transforming AST nodes into optimized IR.
 ///
 /// The Compiler class handles the complete transformation pipeline from parsed
 /// AST to normalized IR, including feature detection for metadata generation.
+///
+/// A compiler is cheap to create, but one instance can also be kept and
+/// reused: each compile starts from a clean slate (see `reset`), so results
+/// never depend on earlier calls. Only the flags persist.
+#[derive(Debug, Clone)]
 pub struct Compiler {
     features_used: HashSet<String>,
+    group_count: usize,
+    flags: Flags,
+    expand_bounded_repetition: bool,
+    max_expanded_nodes: usize,
+    max_capture_groups: usize,
+    warnings: Vec<STRlingWarning>,
+    expansion_error: Option<CompileError>,
+    interner: Option<Interner>,
+}
+
+/// Literal values seen by a compiler, so equal literals share one
+/// allocation
+#[derive(Debug, Clone, Default)]
+struct Interner {
+    values: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    fn intern(&mut self, value: &str) -> Arc<str> {
+        if let Some(shared) = self.values.get(value) {
+            return Arc::clone(shared);
+        }
+        let shared: Arc<str> = value.into();
+        self.values.insert(Arc::clone(&shared));
+        shared
+    }
 }
 
+/// Default for `Compiler::with_max_expanded_nodes`
+pub const DEFAULT_MAX_EXPANDED_NODES: usize = 1000;
+
+/// Default for `Compiler::with_max_capture_groups`, the most capturing
+/// groups PCRE2 accepts
+pub const DEFAULT_MAX_CAPTURE_GROUPS: usize = 65535;
+
 impl Compiler {
     /// Create a new compiler instance
     pub fn new() -> Self {
         Self {
             features_used: HashSet::new(),
+            group_count: 0,
+            flags: Flags::default(),
+            expand_bounded_repetition: false,
+            max_expanded_nodes: DEFAULT_MAX_EXPANDED_NODES,
+            max_capture_groups: DEFAULT_MAX_CAPTURE_GROUPS,
+            warnings: Vec::new(),
+            expansion_error: None,
+            interner: None,
         }
     }
 
+    /// Compile under the given flags, so flag-dependent nodes such as `.`
+    /// record their effective semantics in the IR
+    pub fn with_flags(mut self, flags: Flags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Rewrite counted repetition such as `a{2,4}` into explicit copies
+    /// (`aaa?a?`), for target engines without `{n,m}`
+    ///
+    /// `*`, `+` and `?` are kept. A repeated capturing group is expanded
+    /// only when the count is fixed and nonzero, so the group still captures the last
+    /// iteration; otherwise the repetition is kept and a
+    /// `STRlingWarning::UnexpandedRepetition` is recorded.
+    pub fn with_expand_bounded_repetition(mut self, expand: bool) -> Self {
+        self.expand_bounded_repetition = expand;
+        self
+    }
+
+    /// Cap the IR nodes a single expanded repetition may produce (default
+    /// `DEFAULT_MAX_EXPANDED_NODES`)
+    ///
+    /// A repetition over the cap is left unexpanded, and `try_compile`
+    /// fails with `CompileError::RepetitionTooLarge` instead of allocating
+    /// the copies.
+    pub fn with_max_expanded_nodes(mut self, max: usize) -> Self {
+        self.max_expanded_nodes = max;
+        self
+    }
+
+    /// Cap the number of capturing groups `try_compile` accepts (default
+    /// `DEFAULT_MAX_CAPTURE_GROUPS`), for engines with a lower limit
+    ///
+    /// Groups in different branches of a branch reset share a number and
+    /// count once.
+    pub fn with_max_capture_groups(mut self, max: usize) -> Self {
+        self.max_capture_groups = max;
+        self
+    }
+
+    /// Share storage between equal literal values in the IR
+    ///
+    /// Worthwhile when one compiler compiles many similar patterns. The
+    /// interned values are kept across compiles (`reset` does not clear
+    /// them), so the interner grows with the distinct literals seen.
+    pub fn with_interner(mut self, intern: bool) -> Self {
+        self.interner = intern.then(Interner::default);
+        self
+    }
+
+    /// Warnings recorded by the last compile
+    pub fn warnings(&self) -> &[STRlingWarning] {
+        &self.warnings
+    }
+
+    /// Clear per-compile state (group numbering, detected features,
+    /// warnings, expansion errors)
+    ///
+    /// Called at the start of every compile; options are kept.
+    pub fn reset(&mut self) {
+        self.features_used.clear();
+        self.group_count = 0;
+        self.warnings.clear();
+        self.expansion_error = None;
+    }
+
     /// Compile an AST node and return IR with metadata
     ///
     /// This is the main entry point for compilation with full metadata tracking.
     /// It performs lowering, normalization, and feature analysis.
     pub fn compile_with_metadata(&mut self, root_node: &Node) -> CompileResult {
-        let ir_root = self.lower(root_node);
+        self.reset();
+        let ir_root = self.lower_scoped(root_node);
+        let ir_root = self.expand_repetition(ir_root);
         let ir_root = self.normalize(ir_root);
+        self.check_ignore_case(&ir_root);
+        self.check_quantified_assertions(&ir_root);
+        self.check_empty_branches(&ir_root);
         
         self.analyze_features(&ir_root);
         
@@ -52,32 +171,142 @@ impl Compiler {
 
     /// Compile an AST node to IR without metadata
     pub fn compile(&mut self, root: &Node) -> IROp {
-        let ir = self.lower(root);
-        self.normalize(ir)
+        self.reset();
+        let ir = self.lower_scoped(root);
+        let ir = self.expand_repetition(ir);
+        let ir = self.normalize(ir);
+        self.check_ignore_case(&ir);
+        self.check_quantified_assertions(&ir);
+        self.check_empty_branches(&ir);
+        ir
+    }
+
+    /// Warn if the `i` flag is set but `ir` has nothing it could apply to
+    fn check_ignore_case(&mut self, ir: &IROp) {
+        if self.flags.ignore_case && !ir.iter().any(is_case_sensitive) {
+            self.warnings.push(STRlingWarning::UselessIgnoreCase);
+        }
+    }
+
+    /// Warn about quantifiers directly on an anchor or lookaround, which
+    /// repeating cannot change; they stay in the IR so intentional uses
+    /// still compile
+    fn check_quantified_assertions(&mut self, ir: &IROp) {
+        for op in ir.iter() {
+            if let IROp::Quant(quant) = op {
+                if matches!(*quant.child, IROp::Anchor(_) | IROp::Look(_)) {
+                    self.warnings.push(STRlingWarning::QuantifiedAssertion {
+                        span: quant.span.0.unwrap_or_default(),
+                    });
+                }
+            }
+        }
+    }
+
+    /// Warn about alternation branches that can only match the empty
+    /// string, such as the middle one of `a||b`
+    fn check_empty_branches(&mut self, ir: &IROp) {
+        for op in ir.iter() {
+            if let IROp::Alt(alt) = op {
+                for (branch, op) in alt.branches.iter().enumerate() {
+                    let empty = match op {
+                        IROp::Lit(lit) => lit.value.is_empty(),
+                        IROp::Seq(seq) => seq.parts.is_empty(),
+                        _ => false,
+                    };
+                    if empty {
+                        self.warnings.push(STRlingWarning::EmptyAlternationBranch {
+                            branch,
+                            span: op.span().unwrap_or_default(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// Compile an AST node to IR, rejecting trees no engine could run
+    ///
+    /// # Errors
+    ///
+    /// Returns `CompileError::DuplicateGroupName` if two capturing groups
+    /// with different numbers share a name. Groups in different branches of
+    /// a branch reset may share one, since they share the number too.
+    ///
+    /// Returns `CompileError::RepetitionTooLarge` if bounded repetition
+    /// expansion is enabled and a repetition would exceed
+    /// `with_max_expanded_nodes`.
+    ///
+    /// Returns `CompileError::TooManyCaptureGroups` if the pattern has more
+    /// capturing groups than `with_max_capture_groups` allows.
+    pub fn try_compile(&mut self, root: &Node) -> Result<IROp, CompileError> {
+        let ir = self.compile(root);
+        if let Some(err) = self.expansion_error.take() {
+            return Err(err);
+        }
+        if self.group_count > self.max_capture_groups {
+            return Err(CompileError::TooManyCaptureGroups {
+                count: self.group_count,
+                limit: self.max_capture_groups,
+            });
+        }
+        check_group_names(&ir, &mut HashMap::new())?;
+        Ok(ir)
     }
 
     /// Lower AST node to IR
-    fn lower(&self, node: &Node) -> IROp {
+    ///
+    /// Capturing groups are numbered here, in pre-order, so that a group's
+    /// index reflects the position of its opening parenthesis. Each op keeps
+    /// the source span of the node it was lowered from.
+    fn lower(&mut self, node: &Node) -> IROp {
+        let mut op = match node {
+            Node::Group(_)
+            | Node::BranchReset(_)
+            | Node::BalancingGroup(_)
+            | Node::Lookahead(_)
+            | Node::NegativeLookahead(_)
+            | Node::Lookbehind(_)
+            | Node::NegativeLookbehind(_) => self.lower_scoped(node),
+            _ => self.lower_node(node),
+        };
+        if let Some(span) = node.span() {
+            op.set_span(span);
+        }
+        op
+    }
+
+    /// Lower `node` as a scope for inline flags: a `(?i)` inside it
+    /// applies up to its end
+    fn lower_scoped(&mut self, node: &Node) -> IROp {
+        let flags = self.flags.clone();
+        let op = self.lower_node(node);
+        self.flags = flags;
+        op
+    }
+
+    fn lower_node(&mut self, node: &Node) -> IROp {
         match node {
             Node::Literal(lit) => IROp::Lit(IRLit {
-                value: lit.value.clone(),
+                value: self.literal(&lit.value),
+                span: Default::default(),
+            }),
+            Node::Dot(dot) => IROp::Dot(IRDot {
+                dotall: self.flags.dot_all && !dot.not_newline,
+                not_newline: dot.not_newline,
+                span: Default::default(),
+            }),
+            Node::Anchor(anchor) => IROp::Anchor(IRAnchor {
+                at: anchor.at,
+                span: Default::default(),
             }),
-            Node::Dot(_) => IROp::Dot(IRDot {}),
-            Node::Anchor(anchor) => {
-                let at = if anchor.at == "NonWordBoundary" {
-                    "NotWordBoundary".to_string()
-                } else {
-                    anchor.at.clone()
-                };
-                IROp::Anchor(IRAnchor { at })
-            },
             Node::Sequence(seq) => {
                 let parts: Vec<IROp> = seq.parts.iter().map(|p| self.lower(p)).collect();
-                IROp::Seq(IRSeq { parts })
+                IROp::Seq(IRSeq { parts, span: Default::default() })
             }
             Node::Alternation(alt) => {
                 let branches: Vec<IROp> = alt.branches.iter().map(|b| self.lower(b)).collect();
-                IROp::Alt(IRAlt { branches })
+                IROp::Alt(IRAlt { branches, span: Default::default() })
             }
             Node::Quantifier(quant) => {
                 let max = match &quant.max {
@@ -87,11 +316,11 @@ impl Compiler {
                 };
                 
                 let mode = if quant.possessive {
-                    "Possessive".to_string()
+                    QuantMode::Possessive
                 } else if quant.lazy {
-                    "Lazy".to_string()
+                    QuantMode::Lazy
                 } else {
-                    "Greedy".to_string()
+                    QuantMode::Greedy
                 };
 
                 IROp::Quant(IRQuant {
@@ -99,45 +328,107 @@ impl Compiler {
                     min: quant.min,
                     max,
                     mode,
+                    span: Default::default(),
                 })
             }
-            Node::Group(group) => IROp::Group(IRGroup {
-                capturing: group.capturing,
-                name: group.name.clone(),
-                atomic: group.atomic.unwrap_or(false),
+            Node::Group(group) => {
+                let index = if group.capturing {
+                    self.group_count += 1;
+                    self.group_count
+                } else {
+                    0
+                };
+                IROp::Group(IRGroup {
+                    capturing: group.capturing,
+                    name: group.name.clone(),
+                    atomic: group.atomic.unwrap_or(false),
+                    index,
+                    body: Box::new(self.lower(&group.body)),
+                    span: Default::default(),
+                })
+            }
+            Node::BranchReset(reset) => {
+                // Every branch restarts numbering; groups after the reset
+                // continue from the branch with the most captures.
+                let start = self.group_count;
+                let mut end = start;
+                let mut branches = Vec::new();
+                for branch in &reset.branches {
+                    self.group_count = start;
+                    branches.push(self.lower(branch));
+                    end = end.max(self.group_count);
+                }
+                self.group_count = end;
+                IROp::BranchReset(IRBranchReset { branches, span: Default::default() })
+            }
+            Node::BalancingGroup(group) => IROp::BalancingGroup(IRBalancingGroup {
+                push: group.push.clone(),
+                pop: group.pop.clone(),
                 body: Box::new(self.lower(&group.body)),
+                span: Default::default(),
             }),
             Node::Lookahead(look) => IROp::Look(IRLook {
-                dir: "Ahead".to_string(),
+                dir: LookDir::Ahead,
                 neg: false,
                 body: Box::new(self.lower(&look.body)),
+                span: Default::default(),
             }),
             Node::NegativeLookahead(look) => IROp::Look(IRLook {
-                dir: "Ahead".to_string(),
+                dir: LookDir::Ahead,
                 neg: true,
                 body: Box::new(self.lower(&look.body)),
+                span: Default::default(),
             }),
             Node::Lookbehind(look) => IROp::Look(IRLook {
-                dir: "Behind".to_string(),
+                dir: LookDir::Behind,
                 neg: false,
                 body: Box::new(self.lower(&look.body)),
+                span: Default::default(),
             }),
             Node::NegativeLookbehind(look) => IROp::Look(IRLook {
-                dir: "Behind".to_string(),
+                dir: LookDir::Behind,
                 neg: true,
                 body: Box::new(self.lower(&look.body)),
+                span: Default::default(),
             }),
             Node::Backreference(backref) => IROp::Backref(IRBackref {
-                by_index: backref.by_index,
+                by_index: backref.by_index.map(|index| resolve_group_index(index, self.group_count)),
                 by_name: backref.by_name.clone(),
+                span: Default::default(),
             }),
-            Node::CharacterClass(cc) => IROp::CharClass(IRCharClass {
-                negated: cc.negated,
-                items: cc.items.iter().map(|item| self.lower_class_item(item)).collect(),
+            Node::CharacterClass(cc) => IROp::CharClass(self.lower_class(cc)),
+            Node::SetFlags(set) => {
+                self.flags = set.apply(&self.flags);
+                IROp::SetFlags(IRSetFlags {
+                    on: set.on.clone(),
+                    off: set.off.clone(),
+                    span: Default::default(),
+                })
+            }
+            Node::Verb(verb) => IROp::Verb(IRVerb {
+                name: verb.name.clone(),
+                arg: verb.arg.clone(),
+                span: Default::default(),
+            }),
+            // Comments and recovery placeholders carry no pattern; they
+            // lower to an empty literal, which emits as nothing. Comment
+            // text is not kept in the IR.
+            Node::Comment(_) | Node::Error(_) => IROp::Lit(IRLit {
+                value: "".into(),
+                span: Default::default(),
             }),
         }
     }
 
+    /// Lower a character class from AST to IR
+    fn lower_class(&self, cc: &CharacterClass) -> IRCharClass {
+        IRCharClass {
+            negated: cc.negated,
+            items: cc.items.iter().map(|item| self.lower_class_item(item)).collect(),
+            span: Default::default(),
+        }
+    }
+
     /// Lower a class item from AST to IR
     fn lower_class_item(&self, item: &ClassItem) -> IRClassItem {
         match item {
@@ -160,11 +451,139 @@ impl Compiler {
                     property: Some(up.value.clone()),
                 })
             }
+            ClassItem::Intersection(intersection) => IRClassItem::Intersection(IRClassIntersection {
+                sets: intersection.sets.iter().map(|set| self.lower_class(set)).collect(),
+            }),
+        }
+    }
+
+    /// Expand counted repetition if enabled (see
+    /// `with_expand_bounded_repetition`)
+    fn expand_repetition(&mut self, node: IROp) -> IROp {
+        if !self.expand_bounded_repetition {
+            return node;
+        }
+        match node {
+            IROp::Quant(mut quant) => {
+                let child = self.expand_repetition(*quant.child);
+                // An expansion is a sequence; keep it bound as one atom.
+                quant.child = Box::new(match child {
+                    IROp::Seq(_) => IROp::Group(IRGroup {
+                        capturing: false,
+                        name: None,
+                        atomic: false,
+                        index: 0,
+                        body: Box::new(child),
+                        span: Default::default(),
+                    }),
+                    other => other,
+                });
+                self.expand_quant(quant)
+            }
+            IROp::Seq(mut seq) => {
+                seq.parts = seq.parts.into_iter().map(|p| self.expand_repetition(p)).collect();
+                IROp::Seq(seq)
+            }
+            IROp::Alt(mut alt) => {
+                alt.branches = alt.branches.into_iter().map(|b| self.expand_repetition(b)).collect();
+                IROp::Alt(alt)
+            }
+            IROp::BranchReset(mut reset) => {
+                reset.branches = reset.branches.into_iter().map(|b| self.expand_repetition(b)).collect();
+                IROp::BranchReset(reset)
+            }
+            IROp::Group(mut group) => {
+                group.body = Box::new(self.expand_repetition(*group.body));
+                IROp::Group(group)
+            }
+            IROp::BalancingGroup(mut group) => {
+                group.body = Box::new(self.expand_repetition(*group.body));
+                IROp::BalancingGroup(group)
+            }
+            IROp::Look(mut look) => {
+                look.body = Box::new(self.expand_repetition(*look.body));
+                IROp::Look(look)
+            }
+            other => other,
+        }
+    }
+
+    /// Rewrite one quantifier as `min` copies of its child followed by
+    /// optional copies up to `max`, or by a starred copy if unbounded
+    fn expand_quant(&mut self, quant: IRQuant) -> IROp {
+        let min = usize::try_from(quant.min).unwrap_or(0);
+        let max = match quant.max {
+            IRMaxBound::Finite(max) => Some(usize::try_from(max).unwrap_or(0)),
+            IRMaxBound::Infinite(_) => None,
+        };
+        if matches!((min, max), (0 | 1, None) | (0, Some(1))) {
+            return IROp::Quant(quant);
+        }
+        let copies = max.unwrap_or(min + 1);
+        let nodes = copies.saturating_mul(op_count(&quant.child));
+        if nodes > self.max_expanded_nodes {
+            self.expansion_error.get_or_insert(CompileError::RepetitionTooLarge {
+                span: quant.span.0.unwrap_or_default(),
+                nodes,
+                limit: self.max_expanded_nodes,
+            });
+            return IROp::Quant(quant);
+        }
+        if (max != Some(min) || min == 0) && has_captures(&quant.child) {
+            self.warnings.push(STRlingWarning::UnexpandedRepetition {
+                span: quant.span.0.unwrap_or_default(),
+            });
+            return IROp::Quant(quant);
+        }
+
+        // Only the last mandatory copy captures, as the last iteration
+        // would; the count is fixed whenever the child captures.
+        let uncaptured = without_captures(&quant.child);
+        let mut parts: Vec<IROp> = vec![uncaptured.clone(); min.saturating_sub(1)];
+        if min > 0 {
+            parts.push((*quant.child).clone());
+        }
+        let possessive = quant.mode == QuantMode::Possessive;
+        let tail = |min, max| IRQuant {
+            child: Box::new(uncaptured.clone()),
+            min,
+            max,
+            mode: if possessive { QuantMode::Greedy } else { quant.mode },
+            span: Default::default(),
+        };
+        match max {
+            Some(max) => {
+                parts.extend((min..max).map(|_| IROp::Quant(tail(0, IRMaxBound::Finite(1)))));
+            }
+            None => parts.push(IROp::Quant(tail(0, IRMaxBound::Infinite("Inf".to_string())))),
+        }
+
+        let mut expanded = IROp::Seq(IRSeq { parts, span: quant.span });
+        // Possessive copies could still give back to each other; an atomic
+        // group around the whole expansion cannot.
+        if possessive {
+            expanded = IROp::Group(IRGroup {
+                capturing: false,
+                name: None,
+                atomic: true,
+                index: 0,
+                body: Box::new(expanded),
+                span: quant.span,
+            });
+        }
+        expanded
+    }
+
+    /// The IR value for a literal, shared through the interner if enabled
+    fn literal(&mut self, value: &str) -> Arc<str> {
+        match &mut self.interner {
+            Some(interner) => interner.intern(value),
+            None => value.into(),
         }
     }
 
     /// Normalize IR (flatten, coalesce, etc.)
-    fn normalize(&self, node: IROp) -> IROp {
+    fn normalize(&mut self, node: IROp) -> IROp {
         match node {
             IROp::Seq(seq) => {
                 // Flatten nested sequences
@@ -178,41 +597,69 @@ impl Compiler {
                     }
                 }
                 
-                // Coalesce adjacent literals
+                // Coalesce adjacent literals; a merged literal spans the
+                // source of all the literals it replaces
                 let mut coalesced = Vec::new();
                 let mut pending_lit = String::new();
+                let mut pending_span: Option<Span> = None;
                 
                 for part in new_parts {
                     if let IROp::Lit(lit) = &part {
+                        if lit.value.is_empty() {
+                            continue;
+                        }
                         pending_lit.push_str(&lit.value);
+                        pending_span = match (pending_span, lit.span.0) {
+                            (Some((start, _)), Some((_, end))) => Some((start, end)),
+                            (pending, span) => pending.or(span),
+                        };
                     } else {
                         if !pending_lit.is_empty() {
                             coalesced.push(IROp::Lit(IRLit {
-                                value: pending_lit.clone(),
+                                value: self.literal(&pending_lit),
+                                span: SourceSpan(pending_span),
                             }));
                             pending_lit.clear();
                         }
+                        pending_span = None;
                         coalesced.push(part);
                     }
                 }
                 
                 if !pending_lit.is_empty() {
                     coalesced.push(IROp::Lit(IRLit {
-                        value: pending_lit,
+                        value: self.literal(&pending_lit),
+                        span: SourceSpan(pending_span),
                     }));
                 }
                 
                 if coalesced.len() == 1 {
                     coalesced.into_iter().next().unwrap()
                 } else {
-                    IROp::Seq(IRSeq { parts: coalesced })
+                    IROp::Seq(IRSeq { parts: coalesced, span: seq.span })
                 }
             }
             IROp::Alt(mut alt) => {
-                // Normalize branches
-                alt.branches = alt.branches.into_iter().map(|b| self.normalize(b)).collect();
+                // Normalize branches, splicing in the branches of nested
+                // alternations. Capturing and atomic groups stay intact.
+                let mut branches = Vec::new();
+                for branch in alt.branches {
+                    match self.normalize(branch) {
+                        IROp::Alt(inner) => branches.extend(inner.branches),
+                        IROp::Group(group) if !group.capturing && !group.atomic && matches!(*group.body, IROp::Alt(_)) && !group.body.sets_flags() => {
+                            let IROp::Alt(inner) = *group.body else { unreachable!() };
+                            branches.extend(inner.branches);
+                        }
+                        other => branches.push(other),
+                    }
+                }
+                alt.branches = branches;
                 IROp::Alt(alt)
             }
+            IROp::BranchReset(mut reset) => {
+                reset.branches = reset.branches.into_iter().map(|b| self.normalize(b)).collect();
+                IROp::BranchReset(reset)
+            }
             IROp::Quant(mut quant) => {
                 quant.child = Box::new(self.normalize(*quant.child));
                 IROp::Quant(quant)
@@ -222,6 +669,10 @@ impl Compiler {
                 // Normalize atomic from Option<bool> if needed
                 IROp::Group(group)
             }
+            IROp::BalancingGroup(mut group) => {
+                group.body = Box::new(self.normalize(*group.body));
+                IROp::BalancingGroup(group)
+            }
             IROp::Look(mut look) => {
                 look.body = Box::new(self.normalize(*look.body));
                 IROp::Look(look)
@@ -243,22 +694,27 @@ impl Compiler {
                 self.analyze_features(&group.body);
             }
             IROp::Quant(quant) => {
-                if quant.mode == "Possessive" {
+                if quant.mode == QuantMode::Possessive {
                     self.features_used.insert("possessive_quantifier".to_string());
                 }
                 self.analyze_features(&quant.child);
             }
             IROp::Look(look) => {
-                if look.dir == "Behind" {
-                    self.features_used.insert("lookbehind".to_string());
-                } else if look.dir == "Ahead" {
-                    self.features_used.insert("lookahead".to_string());
-                }
+                self.features_used.insert(match look.dir {
+                    LookDir::Ahead => "lookahead".to_string(),
+                    LookDir::Behind => "lookbehind".to_string(),
+                });
                 self.analyze_features(&look.body);
             }
             IROp::Backref(_) => {
                 self.features_used.insert("backreference".to_string());
             }
+            IROp::SetFlags(_) => {
+                self.features_used.insert("inline_flags".to_string());
+            }
+            IROp::Verb(_) => {
+                self.features_used.insert("backtracking_verb".to_string());
+            }
             IROp::CharClass(cc) => {
                 for item in &cc.items {
                     if let IRClassItem::Esc(esc) = item {
@@ -278,6 +734,16 @@ impl Compiler {
                     self.analyze_features(branch);
                 }
             }
+            IROp::BranchReset(reset) => {
+                self.features_used.insert("branch_reset".to_string());
+                for branch in &reset.branches {
+                    self.analyze_features(branch);
+                }
+            }
+            IROp::BalancingGroup(group) => {
+                self.features_used.insert("balancing_group".to_string());
+                self.analyze_features(&group.body);
+            }
             _ => {}
         }
     }
@@ -296,6 +762,130 @@ pub struct CompileResult {
     pub metadata: Metadata,
 }
 
+/// Fail on a group name already used by a group with another number
+fn check_group_names(op: &IROp, seen: &mut HashMap<String, usize>) -> Result<(), CompileError> {
+    match op {
+        IROp::Group(group) => {
+            if let (true, Some(name)) = (group.capturing, &group.name) {
+                if *seen.entry(name.clone()).or_insert(group.index) != group.index {
+                    return Err(CompileError::DuplicateGroupName { name: name.clone() });
+                }
+            }
+            check_group_names(&group.body, seen)
+        }
+        IROp::Seq(seq) => seq.parts.iter().try_for_each(|p| check_group_names(p, seen)),
+        IROp::Alt(alt) => alt.branches.iter().try_for_each(|b| check_group_names(b, seen)),
+        IROp::BranchReset(reset) => reset.branches.iter().try_for_each(|b| check_group_names(b, seen)),
+        IROp::BalancingGroup(group) => check_group_names(&group.body, seen),
+        IROp::Quant(quant) => check_group_names(&quant.child, seen),
+        IROp::Look(look) => check_group_names(&look.body, seen),
+        _ => Ok(()),
+    }
+}
+
+/// The absolute number of group `index` referenced after `opened` capturing
+/// groups have been opened
+///
+/// Every construct that refers to a group by number goes through here. A
+/// negative `index` counts back, so `-1` is the most recently opened group;
+/// one reaching past the first group is returned unchanged for the engine
+/// to reject.
+fn resolve_group_index(index: i32, opened: usize) -> i32 {
+    match i32::try_from(opened).map(|opened| opened + 1 + index) {
+        Ok(absolute) if index < 0 && absolute >= 1 => absolute,
+        _ => index,
+    }
+}
+
+/// Whether `op` contains a capturing group
+fn has_captures(op: &IROp) -> bool {
+    match op {
+        IROp::Group(group) => group.capturing || has_captures(&group.body),
+        IROp::Seq(seq) => seq.parts.iter().any(has_captures),
+        IROp::Alt(alt) => alt.branches.iter().any(has_captures),
+        IROp::BranchReset(reset) => reset.branches.iter().any(has_captures),
+        IROp::BalancingGroup(group) => has_captures(&group.body),
+        IROp::Quant(quant) => has_captures(&quant.child),
+        IROp::Look(look) => has_captures(&look.body),
+        _ => false,
+    }
+}
+
+/// Whether the `i` flag could change what `op` itself matches
+///
+/// Backreferences and Unicode properties count, since case-insensitive
+/// matching applies to them; `\w` and the other shorthands do not, since
+/// they already hold both cases of every letter they hold.
+fn is_case_sensitive(op: &IROp) -> bool {
+    match op {
+        IROp::Lit(lit) => lit.value.chars().any(has_case),
+        IROp::CharClass(cc) => cc.items.iter().any(class_item_has_case),
+        IROp::Backref(_) => true,
+        _ => false,
+    }
+}
+
+fn class_item_has_case(item: &IRClassItem) -> bool {
+    match item {
+        IRClassItem::Char(lit) => lit.ch.chars().any(has_case),
+        IRClassItem::Range(range) => {
+            let (Some(from), Some(to)) = (range.from_ch.chars().next(), range.to_ch.chars().next()) else {
+                return false;
+            };
+            // Outside ASCII, assume a range holds some letter.
+            !to.is_ascii() || [('A', 'Z'), ('a', 'z')].iter().any(|&(lo, hi)| from <= hi && lo <= to)
+        }
+        IRClassItem::Esc(esc) => matches!(esc.escape_type.as_str(), "p" | "P"),
+        IRClassItem::Intersection(intersection) => {
+            intersection.sets.iter().any(|set| set.items.iter().any(class_item_has_case))
+        }
+    }
+}
+
+/// Whether `ch` has a different upper or lower case form
+fn has_case(ch: char) -> bool {
+    ch.is_lowercase() || ch.is_uppercase()
+}
+
+/// Number of IR ops in the tree rooted at `op`
+fn op_count(op: &IROp) -> usize {
+    1 + match op {
+        IROp::Group(group) => op_count(&group.body),
+        IROp::Seq(seq) => seq.parts.iter().map(op_count).sum(),
+        IROp::Alt(alt) => alt.branches.iter().map(op_count).sum(),
+        IROp::BranchReset(reset) => reset.branches.iter().map(op_count).sum(),
+        IROp::BalancingGroup(group) => op_count(&group.body),
+        IROp::Quant(quant) => op_count(&quant.child),
+        IROp::Look(look) => op_count(&look.body),
+        _ => 0,
+    }
+}
+
+/// A copy of `op` with every capturing group made non-capturing
+fn without_captures(op: &IROp) -> IROp {
+    let mut op = op.clone();
+    strip_captures(&mut op);
+    op
+}
+
+fn strip_captures(op: &mut IROp) {
+    match op {
+        IROp::Group(group) => {
+            group.capturing = false;
+            group.name = None;
+            group.index = 0;
+            strip_captures(&mut group.body);
+        }
+        IROp::Seq(seq) => seq.parts.iter_mut().for_each(strip_captures),
+        IROp::Alt(alt) => alt.branches.iter_mut().for_each(strip_captures),
+        IROp::BranchReset(reset) => reset.branches.iter_mut().for_each(strip_captures),
+        IROp::BalancingGroup(group) => strip_captures(&mut group.body),
+        IROp::Quant(quant) => strip_captures(&mut quant.child),
+        IROp::Look(look) => strip_captures(&mut look.body),
+        _ => {}
+    }
+}
+
 /// Metadata about the compiled pattern
 #[derive(Debug, Clone)]
 pub struct Metadata {
@@ -311,10 +901,11 @@ mod tests {
         let mut compiler = Compiler::new();
         let node = Node::Literal(Literal {
             value: "test".to_string(),
+            span: Default::default(),
         });
         let ir = compiler.compile(&node);
         match ir {
-            IROp::Lit(lit) => assert_eq!(lit.value, "test"),
+            IROp::Lit(lit) => assert_eq!(&*lit.value, "test"),
             _ => panic!("Expected IRLit"),
         }
     }
@@ -326,17 +917,230 @@ mod tests {
             parts: vec![
                 Node::Literal(Literal {
                     value: "a".to_string(),
+                    span: Default::default(),
                 }),
                 Node::Literal(Literal {
                     value: "b".to_string(),
+                    span: Default::default(),
                 }),
             ],
+            span: Default::default(),
         });
         let ir = compiler.compile(&node);
         // Should coalesce into a single literal
         match ir {
-            IROp::Lit(lit) => assert_eq!(lit.value, "ab"),
+            IROp::Lit(lit) => assert_eq!(&*lit.value, "ab"),
             _ => panic!("Expected coalesced literal"),
         }
     }
+
+    #[test]
+    fn test_interner_shares_literals() {
+        let ast = crate::core::parser::parse("GET|POST").unwrap().1;
+        let values = |compiler: &mut Compiler| -> Vec<Arc<str>> {
+            let IROp::Alt(alt) = compiler.compile(&ast) else { panic!("Expected IRAlt") };
+            alt.branches
+                .into_iter()
+                .map(|branch| match branch {
+                    IROp::Lit(lit) => lit.value,
+                    _ => panic!("Expected IRLit"),
+                })
+                .collect()
+        };
+
+        let mut interning = Compiler::new().with_interner(true);
+        let (first, second) = (values(&mut interning), values(&mut interning));
+        assert_eq!(first, second);
+        assert!(first.iter().zip(&second).all(|(a, b)| Arc::ptr_eq(a, b)));
+
+        let mut plain = Compiler::new();
+        let (first, second) = (values(&mut plain), values(&mut plain));
+        assert_eq!(first, second);
+        assert!(!Arc::ptr_eq(&first[0], &second[0]));
+    }
+
+    #[test]
+    fn test_spans_carried_into_ir() {
+        let ir = Compiler::new().compile(&crate::core::parser::parse("(abc)d\\.e").unwrap().1);
+        let IROp::Seq(seq) = ir else { panic!("Expected IRSeq") };
+        assert_eq!(seq.parts[0].span(), Some((0, 5)));
+        // Coalesced literals span everything they were merged from.
+        assert_eq!(seq.parts[1].span(), Some((5, 9)));
+    }
+
+    #[test]
+    fn test_capture_group_limit() {
+        let groups = |n: usize| crate::simply::merge((0..n).map(|_| crate::simply::capture(crate::simply::literal("a"))).collect());
+        let mut compiler = Compiler::new().with_max_capture_groups(100);
+        assert_eq!(
+            compiler.try_compile(&groups(101)),
+            Err(CompileError::TooManyCaptureGroups { count: 101, limit: 100 })
+        );
+        assert!(compiler.try_compile(&groups(100)).is_ok());
+        assert!(Compiler::new().try_compile(&groups(101)).is_ok());
+
+        // Branch reset alternatives share their group numbers.
+        let (_, ast) = crate::core::parser::parse("(?|(a)(b)|(c)(d))").unwrap();
+        assert!(Compiler::new().with_max_capture_groups(2).try_compile(&ast).is_ok());
+    }
+
+    #[test]
+    fn test_inline_flags_are_scoped() {
+        let ast = |src: &str| crate::core::parser::parse(src).unwrap().1;
+        let mut compiler = Compiler::new();
+        let ir = compiler.compile(&ast("(?:(?s).).(?s)."));
+        let IROp::Seq(seq) = ir else { panic!("expected sequence") };
+        let dotall: Vec<bool> = seq.parts.iter().filter_map(|part| match part {
+            IROp::Group(group) => match &*group.body {
+                IROp::Seq(body) => match body.parts[1] {
+                    IROp::Dot(ref dot) => Some(dot.dotall),
+                    _ => None,
+                },
+                _ => None,
+            },
+            IROp::Dot(dot) => Some(dot.dotall),
+            _ => None,
+        }).collect();
+        assert_eq!(dotall, vec![true, false, true]);
+        // Nothing leaks into the next compile.
+        assert!(matches!(compiler.compile(&ast(".")), IROp::Dot(IRDot { dotall: false, .. })));
+
+        // A group confining a flag setting is not spliced into its parent.
+        let ir = compiler.compile(&ast("a|(?:(?i)b|c)"));
+        assert_eq!(crate::emitters::pcre2::PCRE2Emitter::new(Flags::default()).emit(&ir), "a|(?:(?i)b|c)");
+    }
+
+    #[test]
+    fn test_resolve_group_index() {
+        assert_eq!(resolve_group_index(2, 5), 2);
+        assert_eq!(resolve_group_index(-1, 5), 5);
+        assert_eq!(resolve_group_index(-5, 5), 1);
+        assert_eq!(resolve_group_index(-6, 5), -6);
+    }
+
+    #[test]
+    fn test_expansion_limit() {
+        let ast = |src: &str| crate::core::parser::parse(src).unwrap().1;
+        let mut compiler = Compiler::new().with_expand_bounded_repetition(true).with_max_expanded_nodes(1000);
+        assert_eq!(
+            compiler.try_compile(&ast("xa{2000}")),
+            Err(CompileError::RepetitionTooLarge { span: (1, 8), nodes: 2000, limit: 1000 })
+        );
+        assert!(matches!(compiler.compile(&ast("xa{2000}")), IROp::Seq(seq) if matches!(seq.parts[1], IROp::Quant(_))));
+        // Nested expansions multiply.
+        assert!(compiler.try_compile(&ast("(?:a{40}){40}")).is_err());
+        assert!(compiler.try_compile(&ast("a{1000}")).is_ok());
+        assert!(Compiler::new().with_max_expanded_nodes(10).try_compile(&ast("a{2000}")).is_ok());
+    }
+
+    fn expanded(src: &str) -> (String, Vec<STRlingWarning>) {
+        let (flags, ast) = crate::core::parser::parse(src).unwrap();
+        let mut compiler = Compiler::new().with_expand_bounded_repetition(true);
+        let ir = compiler.compile(&ast);
+        (crate::emitters::pcre2::PCRE2Emitter::new(flags).emit(&ir), compiler.warnings().to_vec())
+    }
+
+    #[test]
+    fn test_expand_bounded_repetition() {
+        assert_eq!(expanded("a{2,4}").0, "aaa?a?");
+        assert_eq!(expanded("x\\d{3}?").0, "x[\\d][\\d][\\d]");
+        assert_eq!(expanded("(?:ab){1,2}?c{2,}").0, "(?:ab)(?:ab)??ccc*");
+        assert_eq!(expanded("(a|b){2}(c)").0, "(?:a|b)(a|b)(c)");
+        assert_eq!(expanded("a{2,3}+").0, "(?>aaa?)");
+        assert_eq!(expanded("a*b+c?").0, "a*b+c?");
+    }
+
+    #[test]
+    fn test_useless_ignore_case_warns() {
+        let warnings = |src: &str| {
+            let (flags, ast) = crate::core::parser::parse(src).unwrap();
+            let mut compiler = Compiler::new().with_flags(flags);
+            compiler.compile(&ast);
+            compiler.warnings().to_vec()
+        };
+        assert_eq!(warnings("%flags i\n\\d+"), vec![STRlingWarning::UselessIgnoreCase]);
+        assert_eq!(warnings("%flags i\n[0-9_.-]+\\w"), vec![STRlingWarning::UselessIgnoreCase]);
+        for src in ["%flags i\nabc", "%flags i\n\\d[0-z]", "%flags i\n(.)\\g{1}", "%flags i\n\\p{Lu}", "\\d+"] {
+            assert!(warnings(src).is_empty(), "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn test_empty_alternation_branch_warns() {
+        let warnings = |src: &str| {
+            let mut compiler = Compiler::new();
+            compiler.compile(&crate::core::parser::parse(src).unwrap().1);
+            compiler.warnings().to_vec()
+        };
+        assert_eq!(warnings("a||b"), vec![STRlingWarning::EmptyAlternationBranch { branch: 1, span: (2, 2) }]);
+        assert_eq!(warnings("(?:a|)"), vec![STRlingWarning::EmptyAlternationBranch { branch: 1, span: (5, 5) }]);
+        assert!(matches!(warnings("%flags x\na | # none\n")[..], [STRlingWarning::EmptyAlternationBranch { branch: 1, .. }]));
+        assert!(warnings("a|b").is_empty());
+        assert!(warnings("(?:x)?").is_empty());
+    }
+
+    #[test]
+    fn test_quantified_assertion_warns() {
+        let warnings = |src: &str| {
+            let (flags, ast) = crate::core::parser::parse(src).unwrap();
+            let mut compiler = Compiler::new().with_flags(flags);
+            compiler.compile(&ast);
+            compiler.warnings().to_vec()
+        };
+        assert_eq!(warnings("x(?=a)+"), vec![STRlingWarning::QuantifiedAssertion { span: (1, 7) }]);
+        assert_eq!(warnings("^*a"), vec![STRlingWarning::QuantifiedAssertion { span: (0, 2) }]);
+        assert!(warnings("a+").is_empty());
+    }
+
+    #[test]
+    fn test_variable_repetition_of_capture_warns() {
+        let (pattern, warnings) = expanded("x(a){1,3}");
+        assert_eq!(pattern, "x(a){1,3}");
+        assert_eq!(warnings, vec![STRlingWarning::UnexpandedRepetition { span: (1, 9) }]);
+        assert!(expanded("(a){2}").1.is_empty());
+    }
+
+    #[test]
+    fn test_expansion_matches_same_strings() {
+        use crate::emitters::rust_regex::RustRegexEmitter;
+        for src in ["a{2,4}", "(?:ab|c){1,3}d", "[0-9]{2,}x", "(x|yz){3}", "a{0,2}?b"] {
+            let ast = crate::core::parser::parse(src).unwrap().1;
+            let regex = |compiler: Compiler| {
+                let ir = compiler.with_flags(Flags::default()).compile(&ast);
+                let pattern = RustRegexEmitter::new(Flags::default()).emit(&ir).unwrap();
+                regex::Regex::new(&format!("^(?:{})$", pattern)).unwrap()
+            };
+            let (original, rewritten) = (regex(Compiler::new()), regex(Compiler::new().with_expand_bounded_repetition(true)));
+            for subject in ["", "a", "aa", "aaa", "aaaa", "aaaaa", "abd", "cabd", "ababcd", "12x", "1x", "123456x", "xyzx", "xx", "yzyzyz", "b", "ab", "aab", "aaab"] {
+                assert_eq!(original.is_match(subject), rewritten.is_match(subject), "{} on {:?}", src, subject);
+            }
+        }
+    }
+
+    #[test]
+    fn test_duplicate_group_names_rejected() {
+        let try_compile = |src: &str| Compiler::new().try_compile(&crate::core::parser::parse(src).unwrap().1);
+        assert_eq!(
+            try_compile("(?<x>a)(?<x>b)"),
+            Err(CompileError::DuplicateGroupName { name: "x".to_string() })
+        );
+        assert!(try_compile("(?|(?<x>a)|(?<x>b))").is_ok());
+        assert!(try_compile("(?<x>a)(?<y>b)").is_ok());
+    }
+
+    #[test]
+    fn test_nested_alternations_flattened() {
+        let compile = |src: &str| Compiler::new().compile(&crate::core::parser::parse(src).unwrap().1);
+        let emit = |ir: &IROp| crate::emitters::pcre2::PCRE2Emitter::new(Flags::default()).emit(ir);
+
+        let ir = compile("a|(?:b|c)");
+        assert!(matches!(&ir, IROp::Alt(alt) if alt.branches.len() == 3));
+        assert_eq!(emit(&ir), "a|b|c");
+        assert_eq!(emit(&compile("(?:a|(?:b|(?:c|d)))|e")), "a|b|c|d|e");
+
+        // Groups that capture or are atomic keep their alternation.
+        assert_eq!(emit(&compile("a|(b|c)")), "a|(b|c)");
+        assert_eq!(emit(&compile("a|(?>b|c)")), "a|(?>b|c)");
+        assert_eq!(emit(&compile("a|(?:b|c)d")), "a|(?:b|c)d");
+    }
 }
@@ -9,6 +9,96 @@ use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt;
 
+/// A half-open byte range `(start, end)` into the pattern source.
+pub type Span = (usize, usize);
+
+/// The source range a tree node came from, if known.
+///
+/// Nodes built by hand or deserialized have no span. Spans are metadata,
+/// not structure: every `SourceSpan` compares equal and hashes alike, so
+/// trees parsed from different sources still compare by shape.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SourceSpan(pub Option<Span>);
+
+impl PartialEq for SourceSpan {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for SourceSpan {}
+
+impl std::hash::Hash for SourceSpan {
+    fn hash<H: std::hash::Hasher>(&self, _state: &mut H) {}
+}
+
+/// Category of a parse failure.
+///
+/// Tooling can match on the kind to decide how to react (e.g. whether an
+/// error is recoverable by inserting a closing delimiter) instead of
+/// string-matching the human-readable message. Every variant carries the
+/// span of source text the error refers to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ParseErrorKind {
+    /// A group or lookaround opened with `(` was never closed.
+    UnterminatedGroup { span: Span },
+    /// A character class opened with `[` was never closed.
+    UnterminatedClass { span: Span },
+    /// A malformed or impossible quantifier, e.g. `{foo}` or `{5,2}`.
+    InvalidQuantifier { span: Span },
+    /// A quantifier bound that does not fit the `i32` bound fields; the span
+    /// covers the offending digits.
+    QuantifierBoundTooLarge { span: Span },
+    /// A class range whose start comes after its end, e.g. `[z-a]`; the
+    /// span covers the whole range.
+    InvalidRange { span: Span },
+    /// An incomplete or unknown escape sequence.
+    InvalidEscape { span: Span },
+    /// An unknown flag letter.
+    InvalidFlag { span: Span },
+    /// A character the grammar does not allow at this position.
+    UnexpectedChar { found: char, span: Span },
+    /// Any other syntax error.
+    Syntax { span: Span },
+}
+
+impl ParseErrorKind {
+    /// The span of source text the error refers to.
+    pub fn span(&self) -> Span {
+        match self {
+            ParseErrorKind::UnterminatedGroup { span }
+            | ParseErrorKind::UnterminatedClass { span }
+            | ParseErrorKind::InvalidQuantifier { span }
+            | ParseErrorKind::QuantifierBoundTooLarge { span }
+            | ParseErrorKind::InvalidRange { span }
+            | ParseErrorKind::InvalidEscape { span }
+            | ParseErrorKind::InvalidFlag { span }
+            | ParseErrorKind::UnexpectedChar { span, .. }
+            | ParseErrorKind::Syntax { span } => *span,
+        }
+    }
+
+    fn span_mut(&mut self) -> &mut Span {
+        match self {
+            ParseErrorKind::UnterminatedGroup { span }
+            | ParseErrorKind::UnterminatedClass { span }
+            | ParseErrorKind::InvalidQuantifier { span }
+            | ParseErrorKind::QuantifierBoundTooLarge { span }
+            | ParseErrorKind::InvalidRange { span }
+            | ParseErrorKind::InvalidEscape { span }
+            | ParseErrorKind::InvalidFlag { span }
+            | ParseErrorKind::UnexpectedChar { span, .. }
+            | ParseErrorKind::Syntax { span } => span,
+        }
+    }
+}
+
+impl Default for ParseErrorKind {
+    fn default() -> Self {
+        ParseErrorKind::Syntax { span: (0, 0) }
+    }
+}
+
 /// Rich parse error with position tracking and instructional hints.
 ///
 /// This error class transforms parse failures into learning opportunities by
@@ -27,6 +117,9 @@ pub struct STRlingParseError {
     pub text: String,
     /// An instructional hint explaining how to fix the error
     pub hint: Option<String>,
+    /// The category of the error, with the span it covers
+    #[serde(default)]
+    pub kind: ParseErrorKind,
 }
 
 impl STRlingParseError {
@@ -38,15 +131,35 @@ impl STRlingParseError {
     /// * `pos` - The character position (0-indexed) where the error occurred
     /// * `text` - The full input text being parsed (default: "")
     /// * `hint` - An instructional hint explaining how to fix the error (default: None)
+    ///
+    /// The error is categorized as `ParseErrorKind::Syntax`; use `with_kind`
+    /// to assign a more specific category.
     pub fn new(message: String, pos: usize, text: String, hint: Option<String>) -> Self {
         STRlingParseError {
             message,
             pos,
             text,
             hint,
+            kind: ParseErrorKind::Syntax { span: (pos, pos) },
         }
     }
 
+    /// Replace the error's category.
+    pub fn with_kind(mut self, kind: ParseErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Relocate an error found in a slice of `text` that starts at byte
+    /// `offset`, so its position and span refer to `text`.
+    pub(crate) fn within(mut self, text: &str, offset: usize) -> Self {
+        self.pos += offset;
+        let span = self.kind.span_mut();
+        *span = (span.0 + offset, span.1 + offset);
+        self.text = text.to_string();
+        self
+    }
+
     /// Format the error in the visionary state format.
     ///
     /// Returns a formatted error message with context and hints.
@@ -191,3 +304,176 @@ impl fmt::Display for STRlingParseError {
 }
 
 impl Error for STRlingParseError {}
+
+/// Advisory finding produced by the lint passes.
+///
+/// Warnings never block compilation: they flag constructs that are legal but
+/// usually unintended, so tooling can surface them as non-fatal diagnostics.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum STRlingWarning {
+    /// An alternation branch that can only match the empty string, e.g. the
+    /// middle branch of `a||b`. `branch` is the 0-based index of the branch
+    /// and `span` covers it (empty for a branch with no text at all).
+    EmptyAlternationBranch { branch: usize, span: Span },
+    /// An alternation branch identical to an earlier one, e.g. the last
+    /// branch of `a|b|a`. Whatever it could match, the earlier branch
+    /// matches first, so it never contributes. `branch` and `first` are
+    /// 0-based indices of the duplicate and the branch it repeats.
+    DuplicateAlternationBranch { branch: usize, first: usize },
+    /// A quantifier on a zero-width assertion (anchor or lookaround). From
+    /// the parser, which dropped the quantifier, `span` covers the quantifier
+    /// text; from the compiler it covers the whole quantified term.
+    QuantifiedAssertion { span: Span },
+    /// A counted repetition of a capturing group that the compiler could
+    /// not expand into copies without changing what the group captures;
+    /// `span` covers the quantified term.
+    UnexpandedRepetition { span: Span },
+    /// A `$` directly followed by something that must match a character
+    /// other than a newline, as in `foo$bar`. `$` only matches before a
+    /// newline or at the end, with or without multiline mode, so the
+    /// pattern can never match there; `span` covers the `$`.
+    TextAfterEndAnchor { span: Span },
+    /// The `i` flag is set but nothing in the pattern has case, as in
+    /// `%flags i` over `\d+`, so the flag changes nothing and was probably
+    /// meant for another pattern.
+    UselessIgnoreCase,
+}
+
+impl STRlingWarning {
+    /// A concise description of the finding.
+    pub fn message(&self) -> String {
+        match self {
+            STRlingWarning::EmptyAlternationBranch { branch, .. } => {
+                format!("Empty alternation branch (branch {})", branch + 1)
+            }
+            STRlingWarning::DuplicateAlternationBranch { branch, first } => {
+                format!("Alternation branch {} repeats branch {} and is redundant", branch + 1, first + 1)
+            }
+            STRlingWarning::QuantifiedAssertion { .. } => {
+                "Quantifier on a zero-width assertion has no effect".to_string()
+            }
+            STRlingWarning::UnexpandedRepetition { .. } => {
+                "Variable repetition of a capturing group was not expanded".to_string()
+            }
+            STRlingWarning::TextAfterEndAnchor { .. } => {
+                "'$' is followed by text it can never precede".to_string()
+            }
+            STRlingWarning::UselessIgnoreCase => {
+                "The 'i' flag has no effect: the pattern contains no letters".to_string()
+            }
+        }
+    }
+}
+
+impl fmt::Display for STRlingWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "STRling Warning: {}", self.message())
+    }
+}
+
+/// Failure to express an IR construct in a particular target syntax.
+///
+/// Emitters for engines with a restricted feature set (e.g. no backreferences
+/// or lookaround) return this instead of producing a pattern that would fail
+/// to compile, or worse, silently match something different.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EmitError {
+    /// `construct` (e.g. "backreference") has no equivalent in `target`.
+    Unsupported { target: String, construct: String },
+}
+
+impl fmt::Display for EmitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmitError::Unsupported { target, construct } => {
+                write!(f, "STRling Emit Error: {} does not support {}", target, construct)
+            }
+        }
+    }
+}
+
+impl Error for EmitError {}
+
+/// Failure to compile an AST that parsed successfully.
+///
+/// The parser checks what it can see locally; these are the problems only
+/// visible once the whole tree is compiled, including trees built by hand.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompileError {
+    /// Two capturing groups with different numbers share `name`.
+    DuplicateGroupName { name: String },
+    /// Expanding the repetition at `span` would create `nodes` IR nodes,
+    /// more than the compiler's `limit`.
+    RepetitionTooLarge { span: Span, nodes: usize, limit: usize },
+    /// The pattern has `count` capturing groups, more than the compiler's
+    /// `limit`.
+    TooManyCaptureGroups { count: usize, limit: usize },
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::DuplicateGroupName { name } => {
+                write!(f, "STRling Compile Error: Duplicate group name <{}>", name)
+            }
+            CompileError::RepetitionTooLarge { nodes, limit, .. } => write!(
+                f,
+                "STRling Compile Error: Expanding repetition would create {} nodes (limit {})",
+                nodes, limit
+            ),
+            CompileError::TooManyCaptureGroups { count, limit } => write!(
+                f,
+                "STRling Compile Error: {} capturing groups exceed the limit of {}",
+                count, limit
+            ),
+        }
+    }
+}
+
+impl Error for CompileError {}
+
+/// Any failure of the parse, compile and emit pipeline, by stage.
+#[derive(Debug, Clone)]
+pub enum STRlingError {
+    Parse(STRlingParseError),
+    Compile(CompileError),
+    Emit(EmitError),
+}
+
+impl fmt::Display for STRlingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            STRlingError::Parse(err) => err.fmt(f),
+            STRlingError::Compile(err) => err.fmt(f),
+            STRlingError::Emit(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for STRlingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            STRlingError::Parse(err) => Some(err),
+            STRlingError::Compile(err) => Some(err),
+            STRlingError::Emit(err) => Some(err),
+        }
+    }
+}
+
+impl From<STRlingParseError> for STRlingError {
+    fn from(err: STRlingParseError) -> Self {
+        STRlingError::Parse(err)
+    }
+}
+
+impl From<CompileError> for STRlingError {
+    fn from(err: CompileError) -> Self {
+        STRlingError::Compile(err)
+    }
+}
+
+impl From<EmitError> for STRlingError {
+    fn from(err: EmitError) -> Self {
+        STRlingError::Emit(err)
+    }
+}
@@ -39,10 +39,18 @@ pub fn get_hint(error_message: &str, _text: &str, _pos: usize) -> Option<String>
         );
     }
     
-    if error_message.contains("Invalid flag") {
+    if error_message.contains("Invalid flag in flag setting") {
+        return Some(
+            "Inline flag settings such as (?i-s) accept: i (case-insensitive), \
+            m (multiline), s (dotall), x (extended/free-spacing).".to_string()
+        );
+    }
+
+    if error_message.contains("Invalid flag") || error_message.contains("Unknown flag") {
         return Some(
             "Valid flags are: i (case-insensitive), m (multiline), s (dotall), \
-            u (unicode), x (extended/free-spacing).".to_string()
+            u (unicode), x (extended/free-spacing), a (ASCII shorthands), \
+            g (global), y (sticky). Flag letters are lowercase.".to_string()
         );
     }
     
@@ -93,6 +101,11 @@ mod tests {
         let hint = get_hint("Invalid flag 'z'", "test", 0);
         assert!(hint.is_some());
         assert!(hint.unwrap().contains("Valid flags"));
+
+        let hint = get_hint("Unknown flag: q", "/a/q", 3).unwrap();
+        assert!(hint.contains("g (global)") && hint.contains("y (sticky)"));
+        let hint = get_hint("Invalid flag in flag setting: u", "(?u)", 2).unwrap();
+        assert!(hint.contains("(?i-s)") && !hint.contains("u (unicode)"));
     }
 
     #[test]
@@ -14,8 +14,12 @@
 //! sequencing, character classes, quantification, etc.) and can be serialized
 //! to a dictionary representation for further processing or debugging.
 
+use crate::core::errors::{SourceSpan, Span};
+use crate::core::nodes::Flags;
+pub use crate::core::nodes::AnchorKind;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::sync::Arc;
 
 /// Base trait for all IR operations.
 ///
@@ -34,7 +38,7 @@ pub trait IROpTrait {
 ///
 /// This enum encompasses all IR node variants, allowing for type-safe
 /// pattern matching and easy traversal of the IR tree.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(tag = "ir")]
 pub enum IROp {
     Alt(IRAlt),
@@ -45,8 +49,109 @@ pub enum IROp {
     CharClass(IRCharClass),
     Quant(IRQuant),
     Group(IRGroup),
+    BranchReset(IRBranchReset),
+    BalancingGroup(IRBalancingGroup),
     Backref(IRBackref),
     Look(IRLook),
+    SetFlags(IRSetFlags),
+    Verb(IRVerb),
+}
+
+impl IROp {
+    /// The source range this op was compiled from, if known
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            IROp::Alt(n) => n.span.0,
+            IROp::Seq(n) => n.span.0,
+            IROp::Lit(n) => n.span.0,
+            IROp::Dot(n) => n.span.0,
+            IROp::Anchor(n) => n.span.0,
+            IROp::CharClass(n) => n.span.0,
+            IROp::Quant(n) => n.span.0,
+            IROp::Group(n) => n.span.0,
+            IROp::BranchReset(n) => n.span.0,
+            IROp::BalancingGroup(n) => n.span.0,
+            IROp::Backref(n) => n.span.0,
+            IROp::Look(n) => n.span.0,
+            IROp::SetFlags(n) => n.span.0,
+            IROp::Verb(n) => n.span.0,
+        }
+    }
+
+    /// Record the source range this op was compiled from
+    pub fn set_span(&mut self, span: Span) {
+        let slot = match self {
+            IROp::Alt(n) => &mut n.span,
+            IROp::Seq(n) => &mut n.span,
+            IROp::Lit(n) => &mut n.span,
+            IROp::Dot(n) => &mut n.span,
+            IROp::Anchor(n) => &mut n.span,
+            IROp::CharClass(n) => &mut n.span,
+            IROp::Quant(n) => &mut n.span,
+            IROp::Group(n) => &mut n.span,
+            IROp::BranchReset(n) => &mut n.span,
+            IROp::BalancingGroup(n) => &mut n.span,
+            IROp::Backref(n) => &mut n.span,
+            IROp::Look(n) => &mut n.span,
+            IROp::SetFlags(n) => &mut n.span,
+            IROp::Verb(n) => &mut n.span,
+        };
+        *slot = SourceSpan(Some(span));
+    }
+
+    /// Whether this op changes flags for what follows it in its group
+    ///
+    /// A group whose body sets flags confines them, so it cannot be unwrapped
+    /// into its surroundings without changing what they match.
+    pub fn sets_flags(&self) -> bool {
+        match self {
+            IROp::SetFlags(_) => true,
+            IROp::Seq(seq) => seq.parts.iter().any(IROp::sets_flags),
+            IROp::Alt(alt) => alt.branches.iter().any(IROp::sets_flags),
+            _ => false,
+        }
+    }
+
+    /// The ops directly below this one, in pattern order
+    pub fn children(&self) -> &[IROp] {
+        match self {
+            IROp::Alt(alt) => &alt.branches,
+            IROp::Seq(seq) => &seq.parts,
+            IROp::BranchReset(reset) => &reset.branches,
+            IROp::Quant(quant) => std::slice::from_ref(&*quant.child),
+            IROp::Group(group) => std::slice::from_ref(&*group.body),
+            IROp::BalancingGroup(group) => std::slice::from_ref(&*group.body),
+            IROp::Look(look) => std::slice::from_ref(&*look.body),
+            _ => &[],
+        }
+    }
+
+    /// The ops directly below this one, mutably; see `children`
+    pub fn children_mut(&mut self) -> &mut [IROp] {
+        match self {
+            IROp::Alt(alt) => &mut alt.branches,
+            IROp::Seq(seq) => &mut seq.parts,
+            IROp::BranchReset(reset) => &mut reset.branches,
+            IROp::Quant(quant) => std::slice::from_mut(&mut *quant.child),
+            IROp::Group(group) => std::slice::from_mut(&mut *group.body),
+            IROp::BalancingGroup(group) => std::slice::from_mut(&mut *group.body),
+            IROp::Look(look) => std::slice::from_mut(&mut *look.body),
+            _ => &mut [],
+        }
+    }
+
+    /// This op and every op below it, in pre-order
+    ///
+    /// For example, `ir.iter().any(|op| matches!(op, IROp::Backref(_)))`
+    /// tells whether a pattern uses backreferences.
+    pub fn iter(&self) -> impl Iterator<Item = &IROp> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let op = stack.pop()?;
+            stack.extend(op.children().iter().rev());
+            Some(op)
+        })
+    }
 }
 
 impl IROpTrait for IROp {
@@ -60,8 +165,12 @@ impl IROpTrait for IROp {
             IROp::CharClass(n) => n.to_dict(),
             IROp::Quant(n) => n.to_dict(),
             IROp::Group(n) => n.to_dict(),
+            IROp::BranchReset(n) => n.to_dict(),
+            IROp::BalancingGroup(n) => n.to_dict(),
             IROp::Backref(n) => n.to_dict(),
             IROp::Look(n) => n.to_dict(),
+            IROp::SetFlags(n) => n.to_dict(),
+            IROp::Verb(n) => n.to_dict(),
         }
     }
 }
@@ -70,9 +179,11 @@ impl IROpTrait for IROp {
 ///
 /// Matches any one of the provided branches. Equivalent to the | operator
 /// in traditional regex syntax.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IRAlt {
     pub branches: Vec<IROp>,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 impl IROpTrait for IRAlt {
@@ -84,12 +195,59 @@ impl IROpTrait for IRAlt {
     }
 }
 
+/// Represents a branch reset group in the IR.
+///
+/// Matches any one of the branches, like `IRAlt`, but capturing groups in
+/// every branch are numbered from the same starting index.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct IRBranchReset {
+    pub branches: Vec<IROp>,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
+
+impl IROpTrait for IRBranchReset {
+    fn to_dict(&self) -> Value {
+        serde_json::json!({
+            "ir": "BranchReset",
+            "branches": self.branches.iter().map(|b| b.to_dict()).collect::<Vec<_>>()
+        })
+    }
+}
+
+/// Represents a .NET balancing group in the IR.
+///
+/// Matches the body, then pops the most recent capture of `pop`; with a
+/// `push` name, captures the text since that capture under it. Only .NET
+/// can express this.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct IRBalancingGroup {
+    pub push: Option<String>,
+    pub pop: String,
+    pub body: Box<IROp>,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
+
+impl IROpTrait for IRBalancingGroup {
+    fn to_dict(&self) -> Value {
+        serde_json::json!({
+            "ir": "BalancingGroup",
+            "push": self.push,
+            "pop": self.pop,
+            "body": self.body.to_dict()
+        })
+    }
+}
+
 /// Represents a sequence operation in the IR.
 ///
 /// Matches patterns in sequence, one after another.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IRSeq {
     pub parts: Vec<IROp>,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 impl IROpTrait for IRSeq {
@@ -103,10 +261,14 @@ impl IROpTrait for IRSeq {
 
 /// Represents a literal string in the IR.
 ///
-/// Matches the exact string value.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Matches the exact string value. The value is shared rather than owned so
+/// a compiler with an interner can reuse one allocation for repeated
+/// literals (see `Compiler::with_interner`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IRLit {
-    pub value: String,
+    pub value: Arc<str>,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 impl IROpTrait for IRLit {
@@ -121,41 +283,60 @@ impl IROpTrait for IRLit {
 /// Represents the dot (any character) in the IR.
 ///
 /// Matches any single character.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct IRDot;
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct IRDot {
+    /// Whether `.` matches newlines at this position (the effective `s` flag).
+    #[serde(default)]
+    pub dotall: bool,
+    /// Written `\N`: never matches a newline, so `dotall` is always false
+    #[serde(default)]
+    pub not_newline: bool,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
 
 impl IROpTrait for IRDot {
     fn to_dict(&self) -> Value {
-        serde_json::json!({
+        let mut dict = serde_json::json!({
             "ir": "Dot"
-        })
+        });
+        if self.dotall {
+            dict["dotall"] = Value::Bool(true);
+        }
+        if self.not_newline {
+            dict["notNewline"] = Value::Bool(true);
+        }
+        dict
     }
 }
 
 /// Represents an anchor in the IR.
 ///
 /// Matches a specific position in the text (start, end, word boundary, etc.).
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IRAnchor {
-    pub at: String,
+    pub at: AnchorKind,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 impl IROpTrait for IRAnchor {
     fn to_dict(&self) -> Value {
         serde_json::json!({
             "ir": "Anchor",
-            "at": self.at
+            "at": self.at.as_str()
         })
     }
 }
 
 /// Enum representing all possible character class item types in IR.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(tag = "ir")]
 pub enum IRClassItem {
     Range(IRClassRange),
     Char(IRClassLiteral),
     Esc(IRClassEscape),
+    Intersection(IRClassIntersection),
 }
 
 impl IRClassItem {
@@ -164,6 +345,7 @@ impl IRClassItem {
             IRClassItem::Range(r) => r.to_dict(),
             IRClassItem::Char(c) => c.to_dict(),
             IRClassItem::Esc(e) => e.to_dict(),
+            IRClassItem::Intersection(i) => i.to_dict(),
         }
     }
 }
@@ -171,7 +353,7 @@ impl IRClassItem {
 /// Represents a character range in a character class.
 ///
 /// Matches characters within the specified range.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IRClassRange {
     #[serde(rename = "from")]
     pub from_ch: String,
@@ -192,7 +374,7 @@ impl IRClassRange {
 /// Represents a literal character in a character class.
 ///
 /// Matches the exact character.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IRClassLiteral {
     #[serde(rename = "char")]
     pub ch: String,
@@ -210,7 +392,7 @@ impl IRClassLiteral {
 /// Represents a character class escape in IR.
 ///
 /// Matches shorthand character classes like \d, \w, \s, etc.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IRClassEscape {
     #[serde(rename = "type")]
     pub escape_type: String,
@@ -230,13 +412,33 @@ impl IRClassEscape {
     }
 }
 
+/// Represents an intersection of character sets in IR.
+///
+/// Matches characters in every set. Engines without class intersection
+/// need it rewritten; see the PCRE2 emitter.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct IRClassIntersection {
+    pub sets: Vec<IRCharClass>,
+}
+
+impl IRClassIntersection {
+    pub fn to_dict(&self) -> Value {
+        serde_json::json!({
+            "ir": "Intersection",
+            "sets": self.sets.iter().map(|s| s.to_dict()).collect::<Vec<_>>()
+        })
+    }
+}
+
 /// Represents a character class in IR.
 ///
 /// Matches any character from the specified set.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IRCharClass {
     pub negated: bool,
     pub items: Vec<IRClassItem>,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 impl IROpTrait for IRCharClass {
@@ -252,20 +454,50 @@ impl IROpTrait for IRCharClass {
 /// Represents a quantifier in IR.
 ///
 /// Specifies repetition of a pattern.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IRQuant {
     pub child: Box<IROp>,
     pub min: i32,
     /// Maximum repetitions: either a number or "Inf" for unbounded
     pub max: IRMaxBound,
-    /// Quantifier mode: Greedy|Lazy|Possessive
-    pub mode: String,
+    pub mode: QuantMode,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
+
+/// How a quantifier trades repetitions against the rest of the match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub enum QuantMode {
+    /// Match as many repetitions as possible, giving back on backtracking.
+    #[default]
+    Greedy,
+    /// Match as few repetitions as possible, adding more on backtracking.
+    Lazy,
+    /// Match as many repetitions as possible and never give any back.
+    Possessive,
+}
+
+impl QuantMode {
+    /// The mode's name as used in serialized IR, e.g. `"Lazy"`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            QuantMode::Greedy => "Greedy",
+            QuantMode::Lazy => "Lazy",
+            QuantMode::Possessive => "Possessive",
+        }
+    }
+}
+
+impl std::fmt::Display for QuantMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 /// Maximum bound for IR quantifiers.
 ///
 /// Can be either a finite number or infinite.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum IRMaxBound {
     Finite(i32),
@@ -284,7 +516,7 @@ impl IROpTrait for IRQuant {
             "child": self.child.to_dict(),
             "min": self.min,
             "max": max_value,
-            "mode": self.mode
+            "mode": self.mode.as_str()
         })
     }
 }
@@ -292,13 +524,19 @@ impl IROpTrait for IRQuant {
 /// Represents a group in IR.
 ///
 /// A capturing or non-capturing group.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IRGroup {
     pub capturing: bool,
     pub body: Box<IROp>,
     pub name: Option<String>,
     #[serde(default)]
     pub atomic: bool,
+    /// 1-based capture number assigned by the compiler in order of the
+    /// opening parentheses; 0 for non-capturing groups.
+    #[serde(default)]
+    pub index: usize,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 impl IROpTrait for IRGroup {
@@ -312,6 +550,9 @@ impl IROpTrait for IRGroup {
         if let Some(ref name) = self.name {
             obj["name"] = Value::String(name.clone());
         }
+        if self.capturing {
+            obj["index"] = Value::Number(self.index.into());
+        }
         if self.atomic {
             obj["atomic"] = Value::Bool(true);
         }
@@ -323,12 +564,14 @@ impl IROpTrait for IRGroup {
 /// Represents a backreference in IR.
 ///
 /// References a previously captured group.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IRBackref {
     #[serde(rename = "byIndex")]
     pub by_index: Option<i32>,
     #[serde(rename = "byName")]
     pub by_name: Option<String>,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 impl IROpTrait for IRBackref {
@@ -348,23 +591,132 @@ impl IROpTrait for IRBackref {
     }
 }
 
+/// Which side of the current position a lookaround inspects.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LookDir {
+    /// The text after the position.
+    Ahead,
+    /// The text before the position.
+    Behind,
+}
+
+impl LookDir {
+    /// The direction's name as used in serialized IR, e.g. `"Ahead"`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LookDir::Ahead => "Ahead",
+            LookDir::Behind => "Behind",
+        }
+    }
+}
+
+impl std::fmt::Display for LookDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Represents a lookahead/lookbehind assertion in IR.
 ///
 /// Zero-width assertion.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct IRLook {
-    pub dir: String,
+    pub dir: LookDir,
     pub neg: bool,
     pub body: Box<IROp>,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 impl IROpTrait for IRLook {
     fn to_dict(&self) -> Value {
         serde_json::json!({
             "ir": "Look",
-            "dir": self.dir,
+            "dir": self.dir.as_str(),
             "neg": self.neg,
             "body": self.body.to_dict()
         })
     }
 }
+
+/// Represents an inline flag setting in IR, e.g. `(?i)`.
+///
+/// Changes flags for the rest of the enclosing group. The compiler has
+/// already applied the change where it resolves flags itself (the dotall
+/// state of `IRDot`); the op remains so engines apply it everywhere else.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct IRSetFlags {
+    pub on: Flags,
+    pub off: Flags,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
+
+impl IRSetFlags {
+    /// The setting in PCRE2 syntax, e.g. `(?s-i)`
+    pub fn to_inline(&self) -> String {
+        let off = self.off.to_flag_string();
+        if off.is_empty() {
+            format!("(?{})", self.on.to_flag_string())
+        } else {
+            format!("(?{}-{})", self.on.to_flag_string(), off)
+        }
+    }
+
+    /// The setting as emitters write it: like `to_inline`, but without `x`
+    ///
+    /// The parser has already applied extended mode, and emitted literals
+    /// are not escaped for it, so `(?x)a\ b` must not reach the engine.
+    /// Empty if the setting only toggled `x`.
+    pub fn to_emitted(&self) -> String {
+        let set = IRSetFlags {
+            on: Flags { extended: false, ..self.on.clone() },
+            off: Flags { extended: false, ..self.off.clone() },
+            span: self.span,
+        };
+        if set.on.to_flag_string().is_empty() && set.off.to_flag_string().is_empty() {
+            String::new()
+        } else {
+            set.to_inline()
+        }
+    }
+}
+
+impl IROpTrait for IRSetFlags {
+    fn to_dict(&self) -> Value {
+        serde_json::json!({
+            "ir": "SetFlags",
+            "on": self.on.to_flag_string(),
+            "off": self.off.to_flag_string()
+        })
+    }
+}
+
+/// Represents a backtracking control verb in IR, e.g. `(*SKIP:label)`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct IRVerb {
+    pub name: String,
+    pub arg: Option<String>,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
+
+impl IRVerb {
+    /// The verb in PCRE2 syntax, e.g. `(*SKIP:label)`
+    pub fn to_inline(&self) -> String {
+        match &self.arg {
+            Some(arg) => format!("(*{}:{})", self.name, arg),
+            None => format!("(*{})", self.name),
+        }
+    }
+}
+
+impl IROpTrait for IRVerb {
+    fn to_dict(&self) -> Value {
+        serde_json::json!({
+            "ir": "Verb",
+            "name": self.name,
+            "arg": self.arg
+        })
+    }
+}
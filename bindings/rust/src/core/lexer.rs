@@ -0,0 +1,398 @@
+//! STRling Lexer - Source Scanning and Token Stream
+//!
+//! This module holds the cursor the parser reads pattern source with, and
+//! the tokens it can record along the way for consumers such as syntax
+//! highlighters that need to colour every byte of a pattern, including ones
+//! the parser rejects.
+//!
+//! Tokens are recorded by the parser as it consumes each construct, so they
+//! follow its rules exactly: inline flags, `%define` references, the
+//! leading-bracket and brace policies and everything else that decides how
+//! a character is read. Input the parser cannot make sense of is covered by
+//! `Invalid` tokens, so the tokens of any input tile it exactly, in order.
+
+use crate::core::errors::{SourceSpan, Span};
+use crate::core::nodes::{Comment, Node};
+
+/// Lexical category of a token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// A plain character, or an escape standing for one (`\.`, `\n`,
+    /// `\N{U+41}`), or a `\Q...\E` quote in a class.
+    Literal,
+    /// A shorthand, anchor or backreference escape (`\d`, `\b`, `\k<x>`,
+    /// `(?P=x)`), or a backtracking verb (`(*SKIP)`).
+    Escape,
+    /// The `.` wildcard.
+    Dot,
+    /// `^` or `$`.
+    Anchor,
+    /// A `$name` reference to a `%define`, as one token; a `$` that names
+    /// no definition is an `Anchor`.
+    Reference,
+    /// The `|` separator.
+    Alternation,
+    /// `(` together with any `?` prefix, e.g. `(?:`, `(?<=`, `(?<name>`,
+    /// `(?'name'` or the scoped flags of `(?i-s:`.
+    GroupOpen,
+    /// `)`.
+    GroupClose,
+    /// An inline flag setting such as `(?i)` or `(?x-s)`. Like the parser,
+    /// the tokens after `(?x)` or `(?-x)` are read in the new mode up to
+    /// the end of the enclosing group.
+    FlagSet,
+    /// `[` or `[^`.
+    ClassOpen,
+    /// `]` closing a class.
+    ClassClose,
+    /// `-` between two class members.
+    ClassRange,
+    /// `&&` between the operands of a class intersection.
+    ClassIntersection,
+    /// `*`, `+`, `?` or `{m,n}`, including a lazy `?` or possessive `+` suffix.
+    Quantifier,
+    /// Whitespace and `#` comments skipped in free-spacing mode.
+    Trivia,
+    /// Input the parser rejected, such as an unterminated group.
+    Invalid,
+}
+
+/// A token and the byte range of pattern source it covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// Iterator over the tokens of a pattern source, from `Parser::tokens`.
+///
+/// Spans are byte offsets into the pattern text after any directive lines,
+/// the same coordinates used by parse errors.
+#[derive(Debug, Clone)]
+pub struct Lexer {
+    tokens: std::vec::IntoIter<Token>,
+}
+
+impl Lexer {
+    pub(crate) fn new(tokens: Vec<Token>) -> Self {
+        Self { tokens: tokens.into_iter() }
+    }
+}
+
+impl Iterator for Lexer {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        self.tokens.next()
+    }
+}
+
+/// Cursor for tracking position in the input text
+///
+/// `i` is a byte offset into `text` and always sits on a char boundary.
+#[derive(Debug, Clone)]
+pub(crate) struct Cursor {
+    pub(crate) text: String,
+    pub(crate) i: usize,
+    pub(crate) extended_mode: bool,
+    pub(crate) in_class: usize,  // nesting count for char classes
+    /// Tokens consumed so far, when recording for `Parser::tokens`
+    pub(crate) tokens: Option<Vec<Token>>,
+}
+
+#[allow(dead_code)]
+impl Cursor {
+    pub(crate) fn new(text: String, i: usize, extended_mode: bool, in_class: usize) -> Self {
+        Self {
+            text,
+            i,
+            extended_mode,
+            in_class,
+            tokens: None,
+        }
+    }
+
+    pub(crate) fn eof(&self) -> bool {
+        self.i >= self.text.len()
+    }
+
+    pub(crate) fn peek(&self, n: usize) -> String {
+        self.peek_char(n).map(|c| c.to_string()).unwrap_or_default()
+    }
+
+    pub(crate) fn peek_char(&self, n: usize) -> Option<char> {
+        self.text.get(self.i..).and_then(|rest| rest.chars().nth(n))
+    }
+
+    pub(crate) fn take(&mut self) -> Option<char> {
+        let ch = self.peek_char(0)?;
+        self.i += ch.len_utf8();
+        Some(ch)
+    }
+
+    pub(crate) fn match_str(&mut self, s: &str) -> bool {
+        if self.text[self.i..].starts_with(s) {
+            self.i += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Record a token of `kind` from `start` to the cursor, when recording
+    pub(crate) fn token(&mut self, kind: TokenKind, start: usize) {
+        if let Some(tokens) = &mut self.tokens {
+            tokens.push(Token { kind, span: (start, self.i) });
+        }
+    }
+
+    /// Change the kind of the recorded token that starts at `start`
+    pub(crate) fn retag(&mut self, start: usize, kind: TokenKind) {
+        if let Some(token) = self.tokens.iter_mut().flatten().rev().find(|t| t.span.0 == start) {
+            token.kind = kind;
+        }
+    }
+
+    /// Replace the tokens recorded since `start` with one `Invalid` token
+    /// reaching the cursor
+    pub(crate) fn invalidate(&mut self, start: usize) {
+        if let Some(tokens) = &mut self.tokens {
+            tokens.retain(|t| t.span.0 < start);
+            tokens.push(Token { kind: TokenKind::Invalid, span: (start, self.i) });
+        }
+    }
+
+    pub(crate) fn skip_ws_and_comments(&mut self) {
+        self.take_comments();
+    }
+
+    /// Skip free-spacing whitespace and comments, returning the comments
+    ///
+    /// Each `#`-to-end-of-line comment becomes a `Node::Comment` holding its
+    /// trimmed text. Outside extended mode nothing is skipped. Escaped
+    /// spaces and `#` never reach here: the escape parser consumes the
+    /// backslash and its character together as a literal.
+    pub(crate) fn take_comments(&mut self) -> Vec<Node> {
+        let mut comments = Vec::new();
+        if !self.extended_mode || self.in_class > 0 {
+            return comments;
+        }
+        let trivia_start = self.i;
+        // In free-spacing mode, ignore spaces/tabs/newlines and #-to-EOL comments
+        while !self.eof() {
+            if let Some(ch) = self.peek_char(0) {
+                if " \t\r\n".contains(ch) {
+                    self.i += 1;
+                    continue;
+                }
+                if ch == '#' {
+                    let start = self.i;
+                    while !self.eof() && !"\r\n".contains(self.peek_char(0).unwrap_or('\0')) {
+                        self.take();
+                    }
+                    comments.push(Node::Comment(Comment {
+                        text: self.text[start + 1..self.i].trim().to_string(),
+                        span: SourceSpan(Some((start, self.i))),
+                    }));
+                    continue;
+                }
+            }
+            break;
+        }
+        if self.i > trivia_start {
+            self.token(TokenKind::Trivia, trivia_start);
+        }
+        comments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::nodes::Flags;
+    use crate::core::parser::{LeadingBracket, Parser};
+    use TokenKind::*;
+
+    /// The tokens of `src`, which must have no directive lines, as read by
+    /// `parser`
+    fn lex<'a>(parser: &Parser, src: &'a str) -> Vec<(TokenKind, &'a str)> {
+        parser.tokens().map(|t| (t.kind, &src[t.span.0..t.span.1])).collect()
+    }
+
+    fn kinds(src: &str) -> Vec<(TokenKind, &str)> {
+        lex(&Parser::new(src.to_string()), src)
+    }
+
+    fn extended(src: &str) -> Vec<(TokenKind, &str)> {
+        let flags = Flags { extended: true, ..Flags::default() };
+        lex(&Parser::new(src.to_string()).with_default_flags(flags), src)
+    }
+
+    #[test]
+    fn test_lex_quantifiers_and_classes() {
+        assert_eq!(
+            kinds("*a+?[^a-z\\]]{2,3}x{y{,2}"),
+            vec![
+                (Literal, "*"),
+                (Literal, "a"),
+                (Quantifier, "+?"),
+                (ClassOpen, "[^"),
+                (Literal, "a"),
+                (ClassRange, "-"),
+                (Literal, "z"),
+                (Literal, "\\]"),
+                (ClassClose, "]"),
+                (Quantifier, "{2,3}"),
+                (Literal, "x"),
+                (Literal, "{"),
+                (Literal, "y"),
+                (Quantifier, "{,2}"),
+            ]
+        );
+        // A `]` straight after the opener is a member.
+        assert_eq!(
+            kinds("[^]-a]"),
+            vec![(ClassOpen, "[^"), (Literal, "]"), (ClassRange, "-"), (Literal, "a"), (ClassClose, "]")]
+        );
+        // A range cannot end in a shorthand, so its `-` is a member.
+        assert_eq!(
+            kinds("[a-\\d]"),
+            vec![(ClassOpen, "["), (Literal, "a"), (Literal, "-"), (Escape, "\\d"), (ClassClose, "]")]
+        );
+    }
+
+    #[test]
+    fn test_lex_class_intersection() {
+        assert_eq!(
+            kinds("[a-z&&[^b]]+"),
+            vec![
+                (ClassOpen, "["),
+                (Literal, "a"),
+                (ClassRange, "-"),
+                (Literal, "z"),
+                (ClassIntersection, "&&"),
+                (ClassOpen, "[^"),
+                (Literal, "b"),
+                (ClassClose, "]"),
+                (ClassClose, "]"),
+                (Quantifier, "+"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_groups_and_escapes() {
+        assert_eq!(
+            kinds("(?'m'(?<n>\\d))(?P=n)\\k<n>\\k'm'(?<=\\.)(*SKIP:x)\\g{-1}"),
+            vec![
+                (GroupOpen, "(?'m'"),
+                (GroupOpen, "(?<n>"),
+                (Escape, "\\d"),
+                (GroupClose, ")"),
+                (GroupClose, ")"),
+                (Escape, "(?P=n)"),
+                (Escape, "\\k<n>"),
+                (Escape, "\\k'm'"),
+                (GroupOpen, "(?<="),
+                (Literal, "\\."),
+                (GroupClose, ")"),
+                (Escape, "(*SKIP:x)"),
+                (Escape, "\\g{-1}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_extended_mode_trivia() {
+        assert_eq!(
+            extended("a # note\n +"),
+            vec![(Literal, "a"), (Trivia, " # note\n "), (Quantifier, "+")]
+        );
+        let tokens: Vec<TokenKind> = extended("a\\ b\\#c").into_iter().map(|(kind, _)| kind).collect();
+        assert_eq!(tokens, vec![Literal, Literal, Literal, Literal, Literal]);
+    }
+
+    #[test]
+    fn test_lex_flag_settings() {
+        assert_eq!(
+            kinds("(?i)a(?-i:b)"),
+            vec![
+                (FlagSet, "(?i)"),
+                (Literal, "a"),
+                (GroupOpen, "(?-i:"),
+                (Literal, "b"),
+                (GroupClose, ")"),
+            ]
+        );
+        // `(?x)` turns on free-spacing for the rest of its group only.
+        assert_eq!(
+            kinds("(?:(?x) a) b"),
+            vec![
+                (GroupOpen, "(?:"),
+                (FlagSet, "(?x)"),
+                (Trivia, " "),
+                (Literal, "a"),
+                (GroupClose, ")"),
+                (Literal, " "),
+                (Literal, "b"),
+            ]
+        );
+        assert_eq!(
+            extended("a (?-x) b"),
+            vec![(Literal, "a"), (Trivia, " "), (FlagSet, "(?-x)"), (Literal, " "), (Literal, "b")]
+        );
+        assert_eq!(kinds("(?x: a)"), vec![(GroupOpen, "(?x:"), (Trivia, " "), (Literal, "a"), (GroupClose, ")")]);
+    }
+
+    #[test]
+    fn test_lex_definition_references() {
+        let parser = Parser::new("%define d = \\d+\n$d-$x$".to_string());
+        assert_eq!(
+            lex(&parser, "$d-$x$"),
+            vec![(Reference, "$d"), (Literal, "-"), (Anchor, "$"), (Literal, "x"), (Anchor, "$")]
+        );
+    }
+
+    #[test]
+    fn test_lex_leading_bracket() {
+        let close = |src: &'static str| {
+            lex(&Parser::new(src.to_string()).with_leading_bracket(LeadingBracket::Close), src)
+        };
+        assert_eq!(
+            kinds("[]a]"),
+            vec![(ClassOpen, "["), (Literal, "]"), (Literal, "a"), (ClassClose, "]")]
+        );
+        assert_eq!(
+            close("[]a]"),
+            vec![(ClassOpen, "["), (ClassClose, "]"), (Literal, "a"), (Literal, "]")]
+        );
+        assert_eq!(kinds("[]"), vec![(Invalid, "[]")]);
+        assert_eq!(close("[]"), vec![(ClassOpen, "["), (ClassClose, "]")]);
+        assert_eq!(kinds("[^]"), vec![(Invalid, "[^]")]);
+        assert_eq!(close("[^]"), vec![(ClassOpen, "[^"), (ClassClose, "]")]);
+    }
+
+    #[test]
+    fn test_lex_invalid_input() {
+        assert_eq!(kinds("a(b"), vec![(Literal, "a"), (Invalid, "(b")]);
+        assert_eq!(kinds("x)y"), vec![(Literal, "x"), (Invalid, ")"), (Literal, "y")]);
+        assert_eq!(kinds("(?i)*"), vec![(FlagSet, "(?i)"), (Invalid, "*")]);
+        // Recovery resumes outside the failed class.
+        assert_eq!(
+            extended("[z-a] b"),
+            vec![(Invalid, "[z-a"), (Literal, "]"), (Trivia, " "), (Literal, "b")]
+        );
+    }
+
+    #[test]
+    fn test_tokens_tile_the_source() {
+        for src in ["(a[b", "a{", "\\", "é+ü", "(?", "[a-]", "x)|(", "a{5,2}", "[\\Q-]\\E]", "[\\Q", "\\k'x"] {
+            let mut end = 0;
+            for token in Parser::new(src.to_string()).tokens() {
+                assert_eq!(token.span.0, end, "gap before {:?} in {}", token, src);
+                end = token.span.1;
+            }
+            assert_eq!(end, src.len(), "untokenized tail in {}", src);
+        }
+    }
+}
@@ -4,15 +4,21 @@
 //! - AST node definitions (`nodes`)
 //! - IR node definitions (`ir`)
 //! - Error types (`errors`)
+//! - Lexer (`lexer`)
 //! - Parser (`parser`)
 //! - Compiler (`compiler`)
+//! - Optional IR rewrite passes (`optimize`)
 //! - Validator (`validator`)
 //! - Hint Engine (`hint_engine`)
+//! - IR analyses (`analyze`)
 
 pub mod errors;
 pub mod ir;
 pub mod nodes;
+pub mod lexer;
 pub mod parser;
 pub mod compiler;
+pub mod optimize;
 pub mod validator;
 pub mod hint_engine;
+pub mod analyze;
@@ -15,10 +15,12 @@
 //! (alternation, sequencing, character classes, anchors, etc.) and can be
 //! serialized to a dictionary representation for debugging or storage.
 
+use crate::core::errors::{ParseErrorKind, STRlingParseError, SourceSpan, Span};
 use serde::{Deserialize, Serialize};
 #[allow(unused_imports)]
 use serde_json::Value;
 use std::collections::HashMap;
+use std::str::FromStr;
 
 // ---- Flags container ----
 
@@ -26,7 +28,10 @@ use std::collections::HashMap;
 ///
 /// Flags control the behavior of pattern matching (case sensitivity, multiline
 /// mode, etc.). This class encapsulates all standard regex flags.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+///
+/// By default only `unicode` is set: `\d`, `\w` and `\s` are
+/// Unicode-aware unless `ascii` is set.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Flags {
     #[serde(rename = "ignoreCase")]
     pub ignore_case: bool,
@@ -35,9 +40,41 @@ pub struct Flags {
     pub dot_all: bool,
     pub unicode: bool,
     pub extended: bool,
+    /// Restrict `\d`, `\w` and `\s` to ASCII; takes precedence over `unicode`.
+    #[serde(default)]
+    pub ascii: bool,
+    /// JavaScript's `g`: find every match. Matching metadata only; it does
+    /// not change the pattern.
+    #[serde(default)]
+    pub global: bool,
+    /// JavaScript's `y`: match only at the search position. Matching
+    /// metadata only; it does not change the pattern.
+    #[serde(default)]
+    pub sticky: bool,
+}
+
+impl Default for Flags {
+    fn default() -> Self {
+        Flags { unicode: true, ..Flags::none() }
+    }
 }
 
 impl Flags {
+    /// No flag set, not even `unicode`; the starting point for a set of flag
+    /// changes such as the two halves of `(?i-s)`
+    pub fn none() -> Self {
+        Flags {
+            ignore_case: false,
+            multiline: false,
+            dot_all: false,
+            unicode: false,
+            extended: false,
+            ascii: false,
+            global: false,
+            sticky: false,
+        }
+    }
+
     pub fn to_dict(&self) -> HashMap<String, bool> {
         let mut map = HashMap::new();
         map.insert("ignoreCase".to_string(), self.ignore_case);
@@ -45,25 +82,112 @@ impl Flags {
         map.insert("dotAll".to_string(), self.dot_all);
         map.insert("unicode".to_string(), self.unicode);
         map.insert("extended".to_string(), self.extended);
+        map.insert("ascii".to_string(), self.ascii);
+        map.insert("global".to_string(), self.global);
+        map.insert("sticky".to_string(), self.sticky);
         map
     }
 
     pub fn from_letters(letters: &str) -> Self {
         let mut f = Flags::default();
         for ch in letters.replace(",", "").replace(" ", "").chars() {
-            match ch {
-                'i' => f.ignore_case = true,
-                'm' => f.multiline = true,
-                's' => f.dot_all = true,
-                'u' => f.unicode = true,
-                'x' => f.extended = true,
-                _ => {
-                    // Unknown flags are ignored at parser stage; may be warned later
-                }
-            }
+            // Unknown flags are ignored at parser stage; may be warned later
+            f.set_letter(ch);
         }
         f
     }
+
+    /// Turn on the flag spelled by `letter`, returning `false` if no flag
+    /// uses that letter
+    pub(crate) fn set_letter(&mut self, letter: char) -> bool {
+        let flag = match letter {
+            'i' => &mut self.ignore_case,
+            'm' => &mut self.multiline,
+            's' => &mut self.dot_all,
+            'u' => &mut self.unicode,
+            'x' => &mut self.extended,
+            'a' => &mut self.ascii,
+            'g' => &mut self.global,
+            'y' => &mut self.sticky,
+            _ => return false,
+        };
+        *flag = true;
+        true
+    }
+
+    /// Render the active flags as letters, in `%flags` order (`imsuxagy`)
+    pub fn to_flag_string(&self) -> String {
+        self.active().map(FlagKind::letter).collect()
+    }
+
+    /// The flags that are set, in `%flags` order (`imsuxagy`)
+    pub fn active(&self) -> impl Iterator<Item = FlagKind> {
+        [
+            (self.ignore_case, FlagKind::IgnoreCase),
+            (self.multiline, FlagKind::Multiline),
+            (self.dot_all, FlagKind::DotAll),
+            (self.unicode, FlagKind::Unicode),
+            (self.extended, FlagKind::Extended),
+            (self.ascii, FlagKind::Ascii),
+            (self.global, FlagKind::Global),
+            (self.sticky, FlagKind::Sticky),
+        ]
+        .into_iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, kind)| kind)
+    }
+}
+
+/// One of the flags a `Flags` value can carry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FlagKind {
+    IgnoreCase,
+    Multiline,
+    DotAll,
+    Unicode,
+    Extended,
+    Ascii,
+    Global,
+    Sticky,
+}
+
+impl FlagKind {
+    /// The flag's letter in a `%flags` directive, e.g. `'i'`
+    pub fn letter(self) -> char {
+        match self {
+            FlagKind::IgnoreCase => 'i',
+            FlagKind::Multiline => 'm',
+            FlagKind::DotAll => 's',
+            FlagKind::Unicode => 'u',
+            FlagKind::Extended => 'x',
+            FlagKind::Ascii => 'a',
+            FlagKind::Global => 'g',
+            FlagKind::Sticky => 'y',
+        }
+    }
+}
+
+/// Parse a flag string such as `"ims"`, as accepted by the `%flags`
+/// directive; commas and whitespace between letters are ignored.
+impl FromStr for Flags {
+    type Err = STRlingParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = Flags::default();
+        for (pos, ch) in s.char_indices() {
+            if ch == ',' || ch.is_whitespace() || flags.set_letter(ch) {
+                continue;
+            }
+            return Err(STRlingParseError::new(
+                format!("Unknown flag: {}", ch),
+                pos,
+                s.to_string(),
+                None,
+            )
+            .with_kind(ParseErrorKind::InvalidFlag { span: (pos, pos + ch.len_utf8()) }));
+        }
+        Ok(flags)
+    }
 }
 
 // ---- Base node trait ----
@@ -76,7 +200,7 @@ impl Flags {
 ///
 /// This enum encompasses all AST node variants, allowing for type-safe
 /// pattern matching and easy traversal of the AST.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum Node {
     Alternation(Alternation),
@@ -87,57 +211,396 @@ pub enum Node {
     CharacterClass(CharacterClass),
     Quantifier(Quantifier),
     Group(Group),
+    BranchReset(BranchReset),
+    BalancingGroup(BalancingGroup),
     Backreference(Backreference),
     Lookahead(LookaroundBody),
     NegativeLookahead(LookaroundBody),
     Lookbehind(LookaroundBody),
     NegativeLookbehind(LookaroundBody),
+    SetFlags(SetFlags),
+    Verb(Verb),
+    Comment(Comment),
+    Error(ErrorNode),
+}
+
+impl Node {
+    /// The source range this node was parsed from, if known
+    ///
+    /// Set by the parser on every node it builds; `None` for nodes built by
+    /// hand or deserialized.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Node::Alternation(n) => n.span.0,
+            Node::Sequence(n) => n.span.0,
+            Node::Literal(n) => n.span.0,
+            Node::Dot(n) => n.span.0,
+            Node::Anchor(n) => n.span.0,
+            Node::CharacterClass(n) => n.span.0,
+            Node::Quantifier(n) => n.span.0,
+            Node::Group(n) => n.span.0,
+            Node::BranchReset(n) => n.span.0,
+            Node::BalancingGroup(n) => n.span.0,
+            Node::Backreference(n) => n.span.0,
+            Node::Lookahead(n)
+            | Node::NegativeLookahead(n)
+            | Node::Lookbehind(n)
+            | Node::NegativeLookbehind(n) => n.span.0,
+            Node::SetFlags(n) => n.span.0,
+            Node::Verb(n) => n.span.0,
+            Node::Comment(n) => n.span.0,
+            Node::Error(n) => Some(n.span),
+        }
+    }
+
+    /// Record the source range this node was parsed from
+    pub fn set_span(&mut self, span: Span) {
+        let slot = match self {
+            Node::Alternation(n) => &mut n.span,
+            Node::Sequence(n) => &mut n.span,
+            Node::Literal(n) => &mut n.span,
+            Node::Dot(n) => &mut n.span,
+            Node::Anchor(n) => &mut n.span,
+            Node::CharacterClass(n) => &mut n.span,
+            Node::Quantifier(n) => &mut n.span,
+            Node::Group(n) => &mut n.span,
+            Node::BranchReset(n) => &mut n.span,
+            Node::BalancingGroup(n) => &mut n.span,
+            Node::Backreference(n) => &mut n.span,
+            Node::Lookahead(n)
+            | Node::NegativeLookahead(n)
+            | Node::Lookbehind(n)
+            | Node::NegativeLookbehind(n) => &mut n.span,
+            Node::SetFlags(n) => &mut n.span,
+            Node::Verb(n) => &mut n.span,
+            Node::Comment(n) => &mut n.span,
+            Node::Error(n) => {
+                n.span = span;
+                return;
+            }
+        };
+        *slot = SourceSpan(Some(span));
+    }
+
+    /// Whether this node changes flags for what follows it in its group
+    ///
+    /// See `IROp::sets_flags`.
+    pub fn sets_flags(&self) -> bool {
+        match self {
+            Node::SetFlags(_) => true,
+            Node::Sequence(seq) => seq.parts.iter().any(Node::sets_flags),
+            Node::Alternation(alt) => alt.branches.iter().any(Node::sets_flags),
+            _ => false,
+        }
+    }
+
+    /// An alternation of `branches`, for combining parsed fragments
+    ///
+    /// Branches that are alternations are spliced in, and a branch that
+    /// sets flags is grouped so the setting cannot reach later branches. A
+    /// single branch is returned as it is.
+    pub fn alt(branches: Vec<Node>) -> Node {
+        let mut flat = Vec::with_capacity(branches.len());
+        for branch in branches {
+            match branch {
+                Node::Alternation(alt) if !alt.branches.iter().any(Node::sets_flags) => flat.extend(alt.branches),
+                other => flat.push(other),
+            }
+        }
+        if flat.len() == 1 {
+            return flat.pop().unwrap();
+        }
+        let branches = flat.into_iter().map(Node::confined).collect();
+        Node::Alternation(Alternation { branches, span: Default::default() })
+    }
+
+    /// A sequence of `parts`, for combining parsed fragments
+    ///
+    /// Parts that are sequences are spliced in. Alternations, and parts
+    /// that set flags, are grouped so each part still matches what it did
+    /// on its own. A single part is returned as it is.
+    pub fn seq(parts: Vec<Node>) -> Node {
+        let mut flat = Vec::with_capacity(parts.len());
+        for part in parts {
+            match part {
+                Node::Sequence(seq) if !seq.parts.iter().any(Node::sets_flags) => flat.extend(seq.parts),
+                other => flat.push(other),
+            }
+        }
+        if flat.len() == 1 {
+            return flat.pop().unwrap();
+        }
+        let parts = flat
+            .into_iter()
+            .map(|part| match part {
+                Node::Alternation(_) => Node::non_capturing(part),
+                other => other.confined(),
+            })
+            .collect();
+        Node::Sequence(Sequence { parts, span: Default::default() })
+    }
+
+    /// This node followed by `other`; see `Node::seq`
+    pub fn concat(self, other: Node) -> Node {
+        Node::seq(vec![self, other])
+    }
+
+    /// This node or `other`; see `Node::alt`
+    pub fn or(self, other: Node) -> Node {
+        Node::alt(vec![self, other])
+    }
+
+    /// This node, grouped if it sets flags for what follows it
+    fn confined(self) -> Node {
+        if self.sets_flags() {
+            Node::non_capturing(self)
+        } else {
+            self
+        }
+    }
+
+    fn non_capturing(body: Node) -> Node {
+        Node::Group(Group {
+            capturing: false,
+            name: None,
+            atomic: Some(false),
+            body: Box::new(body),
+            span: Default::default(),
+        })
+    }
+
+    /// The nodes directly below this one, in source order
+    pub fn children(&self) -> &[Node] {
+        match self {
+            Node::Alternation(alt) => &alt.branches,
+            Node::Sequence(seq) => &seq.parts,
+            Node::BranchReset(reset) => &reset.branches,
+            Node::Quantifier(quant) => std::slice::from_ref(&*quant.target.child),
+            Node::Group(group) => std::slice::from_ref(&*group.body),
+            Node::BalancingGroup(group) => std::slice::from_ref(&*group.body),
+            Node::Lookahead(look)
+            | Node::NegativeLookahead(look)
+            | Node::Lookbehind(look)
+            | Node::NegativeLookbehind(look) => std::slice::from_ref(&*look.body),
+            _ => &[],
+        }
+    }
+
+    /// This node and every node below it, in pre-order
+    pub fn iter(&self) -> impl Iterator<Item = &Node> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children().iter().rev());
+            Some(node)
+        })
+    }
 }
 
 /// Alternation node (OR operation).
 ///
 /// Represents a choice between multiple branches.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Alternation {
     #[serde(alias = "alternatives")]
     pub branches: Vec<Node>,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 /// Sequence node.
 ///
 /// Represents a sequence of patterns to be matched in order.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Sequence {
     pub parts: Vec<Node>,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 /// Literal string node.
 ///
 /// Represents a literal string to match.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Literal {
     pub value: String,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 /// Dot (any character) node.
 ///
 /// Represents the `.` metacharacter that matches any character.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-pub struct Dot;
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Dot {
+    /// Written `\N`: never matches a newline, whatever the `s` flag
+    #[serde(default)]
+    pub not_newline: bool,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
+
+/// Branch reset group node.
+///
+/// Represents `(?|a|b)`: an alternation whose branches each number their
+/// capturing groups from the same starting index.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BranchReset {
+    pub branches: Vec<Node>,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
+
+/// .NET balancing group node, e.g. `(?<close-open>...)` or `(?<-open>...)`.
+///
+/// Matches `body`, then pops the most recent capture of group `pop`,
+/// failing if there is none. With a `push` name, the text between the
+/// popped capture and this match is captured under that name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BalancingGroup {
+    pub push: Option<String>,
+    pub pop: String,
+    pub body: Box<Node>,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
+
+/// Inline flag setting node, e.g. `(?i)` or `(?s-i)`.
+///
+/// Turns flags on or off from this point to the end of the enclosing
+/// group, later alternatives included. Only `i`, `m`, `s` and `x` can be
+/// set inline; the node itself matches the empty string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SetFlags {
+    pub on: Flags,
+    pub off: Flags,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
+
+impl SetFlags {
+    /// `flags` with this setting applied
+    pub fn apply(&self, flags: &Flags) -> Flags {
+        let mut flags = flags.clone();
+        for (on, off, flag) in [
+            (self.on.ignore_case, self.off.ignore_case, &mut flags.ignore_case),
+            (self.on.multiline, self.off.multiline, &mut flags.multiline),
+            (self.on.dot_all, self.off.dot_all, &mut flags.dot_all),
+            (self.on.extended, self.off.extended, &mut flags.extended),
+        ] {
+            if on {
+                *flag = true;
+            } else if off {
+                *flag = false;
+            }
+        }
+        flags
+    }
+}
+
+/// Backtracking control verb node, e.g. `(*SKIP)` or `(*MARK:name)`.
+///
+/// Only PCRE2 understands these; other emitters reject them.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Verb {
+    /// Verb name without the `(*`, e.g. `"SKIP"`; empty for the `(*:name)`
+    /// shorthand of `MARK`.
+    pub name: String,
+    pub arg: Option<String>,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
+
+/// Comment node.
+///
+/// A `#` comment in extended mode, kept so that tools which print patterns
+/// back out can reproduce it. The compiler drops comments, so they never
+/// reach the IR or any emitted pattern, `with_flags_directive` output
+/// included; reproducing them means working from the AST.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Comment {
+    /// Comment text without the `#` and surrounding whitespace.
+    pub text: String,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
+
+/// Error recovery placeholder.
+///
+/// Stands in for input that failed to parse; only produced by
+/// `Parser::parse_recoverable`, which reports the matching diagnostic.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ErrorNode {
+    /// Byte range of the skipped input within the pattern source.
+    pub span: (usize, usize),
+}
 
 /// Anchor node.
 ///
 /// Represents position anchors in the pattern.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Anchor {
-    /// Anchor type: "Start"|"End"|"WordBoundary"|"NotWordBoundary"|Absolute* variants
-    pub at: String,
+    pub at: AnchorKind,
+    #[serde(skip)]
+    pub span: SourceSpan,
+}
+
+/// The position an anchor asserts, shared by `Anchor` and `IRAnchor`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AnchorKind {
+    /// `^`: start of the subject, or of a line in multiline mode
+    Start,
+    /// `$`: end of the subject, or of a line in multiline mode
+    End,
+    /// `\b`
+    WordBoundary,
+    /// `\B`
+    #[serde(alias = "NonWordBoundary")]
+    NotWordBoundary,
+    /// `\A`: start of the subject
+    AbsoluteStart,
+    /// `\Z`: end of the subject or before a final newline
+    EndBeforeFinalNewline,
+    /// `\z`: end of the subject
+    AbsoluteEnd,
+    /// `\G`: where the previous match ended, or the start position of the
+    /// first search. Only meaningful when matching iteratively, e.g.
+    /// scanning tokens with repeated searches.
+    PreviousMatchEnd,
+    /// GNU `\<`: start of a word
+    WordStart,
+    /// GNU `\>`: end of a word
+    WordEnd,
+}
+
+impl AnchorKind {
+    /// The anchor's name as used in serialized trees, e.g. `"WordBoundary"`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AnchorKind::Start => "Start",
+            AnchorKind::End => "End",
+            AnchorKind::WordBoundary => "WordBoundary",
+            AnchorKind::NotWordBoundary => "NotWordBoundary",
+            AnchorKind::AbsoluteStart => "AbsoluteStart",
+            AnchorKind::EndBeforeFinalNewline => "EndBeforeFinalNewline",
+            AnchorKind::AbsoluteEnd => "AbsoluteEnd",
+            AnchorKind::PreviousMatchEnd => "PreviousMatchEnd",
+            AnchorKind::WordStart => "WordStart",
+            AnchorKind::WordEnd => "WordEnd",
+        }
+    }
+}
+
+impl std::fmt::Display for AnchorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 // --- CharClass ---
 
 /// Enum representing all possible character class item types.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum ClassItem {
     Range(ClassRange),
@@ -147,12 +610,14 @@ pub enum ClassItem {
     Esc(ClassEscape),
     /// Unicode property reference inside a class, e.g. \p{L}
     UnicodeProperty(ClassUnicodeProperty),
+    /// Intersection of sets, e.g. `[\p{L}&&\p{ASCII}]`
+    Intersection(ClassIntersection),
 }
 
 /// Character range in a character class.
 ///
 /// Represents a range like `a-z` or `0-9`.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ClassRange {
     #[serde(rename = "from")]
     pub from_ch: String,
@@ -163,7 +628,7 @@ pub struct ClassRange {
 /// Literal character in a character class.
 ///
 /// Represents a single character literal.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ClassLiteral {
     #[serde(rename = "char", alias = "value")]
     pub ch: String,
@@ -172,7 +637,7 @@ pub struct ClassLiteral {
 /// Character class escape sequence.
 ///
 /// Represents shorthand character classes like `\d`, `\w`, `\s`, etc.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
 pub struct ClassEscape {
     /// Escape type: d, D, w, W, s, S, p, P
     /// Accept both the historical `type` field and the newer `kind` field
@@ -219,19 +684,31 @@ impl<'de> Deserialize<'de> for ClassEscape {
     }
 }
 
+/// Intersection of character sets inside a class.
+///
+/// Written `[A&&B]`, Java and ICU style, and matches the characters in every
+/// operand. An operand is a run of members or, after `&&`, a nested class
+/// such as `[^aeiou]`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ClassIntersection {
+    pub sets: Vec<CharacterClass>,
+}
+
 /// Character class node.
 ///
 /// Represents a character class like `[abc]` or `[^0-9]`.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct CharacterClass {
     pub negated: bool,
     #[serde(alias = "members")]
     pub items: Vec<ClassItem>,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 /// Unicode property entry inside a character class. Matches the JSON shape
 /// used by the test specs (name, value, negated).
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ClassUnicodeProperty {
     pub name: Option<String>,
     pub value: String,
@@ -241,7 +718,7 @@ pub struct ClassUnicodeProperty {
 /// Quantifier node.
 ///
 /// Represents repetition of a pattern with specified min/max bounds.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Quantifier {
     #[serde(flatten)]
     pub target: QuantifierTarget,
@@ -258,6 +735,8 @@ pub struct Quantifier {
     pub lazy: bool,
     #[serde(default)]
     pub possessive: bool,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 fn default_greedy_mode() -> String {
@@ -272,7 +751,7 @@ fn default_greedy_mode() -> String {
 // But wait, `target` in JSON is the node being quantified.
 // I'll use a wrapper or just rename `child` to `target`.
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct QuantifierTarget {
     // Accept both `target` (preferred in the current JSON schema) and
     // the historical `child` key. Some older specs use `child` while
@@ -285,7 +764,7 @@ pub struct QuantifierTarget {
 /// Maximum bound for quantifiers.
 ///
 /// Can be either a finite number or infinite.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum MaxBound {
     Finite(i32),
@@ -298,13 +777,15 @@ pub enum MaxBound {
 /// Group node.
 ///
 /// Represents a capturing or non-capturing group.
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
 pub struct Group {
     pub capturing: bool,
     pub body: Box<Node>,
     pub name: Option<String>,
     /// Extension: atomic group flag
     pub atomic: Option<bool>,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 impl<'de> Deserialize<'de> for Group {
@@ -332,23 +813,29 @@ impl<'de> Deserialize<'de> for Group {
             }
         };
 
-        Ok(Group { capturing: raw.capturing, body, name: raw.name, atomic: raw.atomic })
+        Ok(Group { capturing: raw.capturing, body, name: raw.name, atomic: raw.atomic, span: Default::default() })
     }
 }
 
 /// Backreference node.
 ///
 /// Represents a reference to a previously captured group.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Backreference {
+    /// Group number; negative numbers count back from the reference, as in
+    /// `\g{-1}`, and are resolved by the compiler.
     #[serde(rename = "byIndex", alias = "index")]
     pub by_index: Option<i32>,
     #[serde(rename = "byName", alias = "name")]
     pub by_name: Option<String>,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
 
 /// Lookaround body.
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct LookaroundBody {
     pub body: Box<Node>,
+    #[serde(skip)]
+    pub span: SourceSpan,
 }
@@ -0,0 +1,468 @@
+//! STRling Optimizer - Optional IR Rewrite Passes
+//!
+//! This module contains semantics-preserving IR-to-IR rewrites that callers
+//! may run between compilation and emission. None of them run by default:
+//! the compiler's own normalization stays minimal so that IR mirrors the
+//! source pattern closely.
+
+use crate::core::ir::*;
+use crate::core::nodes::Flags;
+use crate::emitters::pcre2::PCRE2Emitter;
+
+/// Largest Unicode scalar value.
+const MAX_CHAR: u32 = 0x10FFFF;
+/// Surrogate code points, which no `char` (and no class member) can be.
+const SURROGATES: (u32, u32) = (0xD800, 0xDFFF);
+
+/// Replace character classes by their complement where that is shorter
+///
+/// A class such as `[\x00-\t\x0B-\u{10FFFF}]` matches the same characters as
+/// `[^\n]`, and vice versa. Each class made only of literal characters and
+/// ranges is compared against its complement with the negation flipped, and
+/// replaced only when the complement emits strictly shorter PCRE2 syntax
+/// and is made of ASCII characters only. Classes containing escapes such as
+/// `\d` or `\p{L}` are left alone.
+///
+/// Case-insensitive matching does not commute with complementing (under
+/// `i`, `[^a]` also rejects `A`), so nothing is rewritten when `flags` or an
+/// inline flag setting in `op` turns on `ignore_case`.
+pub fn complement_classes(op: IROp, flags: &Flags) -> IROp {
+    let ignore_case = flags.ignore_case
+        || op
+            .iter()
+            .any(|op| matches!(op, IROp::SetFlags(set) if set.on.ignore_case));
+    if ignore_case {
+        return op;
+    }
+    map_classes(op, &shorter_complement)
+}
+
+/// Fold negated classes of a single negated shorthand to the positive form
+///
+/// `[^\D]` matches exactly what `\d` does, so it becomes the plain `\d`
+/// class; likewise for `\W`, `\S` and `\P{..}`.
+pub fn fold_double_negation(op: IROp) -> IROp {
+    map_classes(op, &|cc: IRCharClass| match cc.items.as_slice() {
+        [IRClassItem::Esc(esc)] if cc.negated && ["D", "W", "S", "P"].contains(&esc.escape_type.as_str()) => {
+            IRCharClass {
+                negated: false,
+                items: vec![IRClassItem::Esc(IRClassEscape {
+                    escape_type: esc.escape_type.to_lowercase(),
+                    property: esc.property.clone(),
+                })],
+                span: cc.span,
+            }
+        }
+        _ => cc,
+    })
+}
+
+/// Unwrap non-capturing groups that group nothing
+///
+/// A `(?:...)` is redundant as the whole pattern, as the body of another
+/// group or lookaround, as an alternation branch, and as part of a sequence
+/// or a branch reset branch unless it holds an alternation. Quantified
+/// groups are kept, as are groups that confine an inline flag setting.
+pub fn unwrap_groups(op: IROp) -> IROp {
+    let op = unwrap_children(op);
+    match op {
+        IROp::Group(group) if is_redundant(&group) => *group.body,
+        other => other,
+    }
+}
+
+fn unwrap_children(op: IROp) -> IROp {
+    match op {
+        IROp::Seq(seq) => IROp::Seq(IRSeq {
+            parts: seq
+                .parts
+                .into_iter()
+                .map(|part| match unwrap_children(part) {
+                    IROp::Group(group) if is_redundant(&group) && !matches!(*group.body, IROp::Alt(_)) => *group.body,
+                    other => other,
+                })
+                .collect(),
+            span: seq.span,
+        }),
+        IROp::Alt(alt) => IROp::Alt(IRAlt {
+            branches: alt.branches.into_iter().map(unwrap_groups).collect(),
+            span: alt.span,
+        }),
+        // A branch's alternation must stay grouped: spliced into the branch
+        // reset, its branches would each restart the group numbering.
+        IROp::BranchReset(reset) => IROp::BranchReset(IRBranchReset {
+            branches: reset
+                .branches
+                .into_iter()
+                .map(|branch| match unwrap_children(branch) {
+                    IROp::Group(group) if is_redundant(&group) && !matches!(*group.body, IROp::Alt(_)) => *group.body,
+                    other => other,
+                })
+                .collect(),
+            span: reset.span,
+        }),
+        IROp::Quant(quant) => IROp::Quant(IRQuant {
+            child: Box::new(unwrap_children(*quant.child)),
+            ..quant
+        }),
+        IROp::Group(group) => IROp::Group(IRGroup {
+            body: Box::new(unwrap_groups(*group.body)),
+            ..group
+        }),
+        IROp::BalancingGroup(group) => IROp::BalancingGroup(IRBalancingGroup {
+            body: Box::new(unwrap_groups(*group.body)),
+            ..group
+        }),
+        IROp::Look(look) => IROp::Look(IRLook {
+            body: Box::new(unwrap_groups(*look.body)),
+            ..look
+        }),
+        other => other,
+    }
+}
+
+fn is_redundant(group: &IRGroup) -> bool {
+    !group.capturing && !group.atomic && !group.body.sets_flags()
+}
+
+/// Rewrite `ir` in place into a canonical form, for deterministic output
+///
+/// Patterns that differ only in spelling canonicalize to the same IR, and
+/// so emit byte-identical output:
+/// - class members are sorted (characters and ranges by code point, then
+///   escapes) and duplicates removed
+/// - unbounded quantifiers use the single spelling `Inf`, so `a{1,}` and
+///   `a+` agree
+/// - nested sequences flatten, adjacent literals merge, and single-part
+///   sequences and single-branch alternations unwrap
+///
+/// The result matches exactly what the input did, but its shape (and thus
+/// the emitted text) may change between releases.
+pub fn canonicalize(ir: &mut IROp) {
+    match ir {
+        IROp::Seq(seq) => {
+            let mut parts: Vec<IROp> = Vec::with_capacity(seq.parts.len());
+            for mut part in std::mem::take(&mut seq.parts) {
+                canonicalize(&mut part);
+                let flattened = match part {
+                    IROp::Seq(inner) => inner.parts,
+                    other => vec![other],
+                };
+                for part in flattened {
+                    match (parts.last_mut(), part) {
+                        (_, IROp::Lit(lit)) if lit.value.is_empty() => {}
+                        (Some(IROp::Lit(prev)), IROp::Lit(lit)) => {
+                            prev.value = [&*prev.value, &*lit.value].concat().into()
+                        }
+                        (_, part) => parts.push(part),
+                    }
+                }
+            }
+            match parts.len() {
+                0 => *ir = IROp::Lit(IRLit { value: "".into(), span: seq.span }),
+                1 => *ir = parts.pop().unwrap(),
+                _ => seq.parts = parts,
+            }
+        }
+        IROp::Alt(alt) => {
+            alt.branches.iter_mut().for_each(canonicalize);
+            if alt.branches.len() == 1 {
+                *ir = alt.branches.pop().unwrap();
+            }
+        }
+        IROp::BranchReset(reset) => reset.branches.iter_mut().for_each(canonicalize),
+        IROp::Quant(quant) => {
+            if let IRMaxBound::Infinite(inf) = &mut quant.max {
+                *inf = "Inf".to_string();
+            }
+            canonicalize(&mut quant.child);
+        }
+        IROp::Group(group) => canonicalize(&mut group.body),
+        IROp::BalancingGroup(group) => canonicalize(&mut group.body),
+        IROp::Look(look) => canonicalize(&mut look.body),
+        IROp::CharClass(cc) => {
+            cc.items.sort_by(|a, b| class_item_key(a).cmp(&class_item_key(b)));
+            cc.items.dedup();
+        }
+        _ => {}
+    }
+}
+
+/// Sort key for class members; UTF-8 order of one-character strings is
+/// code point order
+fn class_item_key(item: &IRClassItem) -> (u8, &str, &str) {
+    match item {
+        IRClassItem::Char(lit) => (0, &lit.ch, ""),
+        IRClassItem::Range(range) => (0, &range.from_ch, &range.to_ch),
+        IRClassItem::Esc(esc) => (1, &esc.escape_type, esc.property.as_deref().unwrap_or("")),
+        IRClassItem::Intersection(_) => (2, "", ""),
+    }
+}
+
+/// Rebuild `op` with `f` applied to every character class in it
+fn map_classes(op: IROp, f: &impl Fn(IRCharClass) -> IRCharClass) -> IROp {
+    match op {
+        IROp::CharClass(cc) => IROp::CharClass(f(cc)),
+        IROp::Seq(seq) => IROp::Seq(IRSeq {
+            parts: seq.parts.into_iter().map(|p| map_classes(p, f)).collect(),
+            span: seq.span,
+        }),
+        IROp::Alt(alt) => IROp::Alt(IRAlt {
+            branches: alt.branches.into_iter().map(|b| map_classes(b, f)).collect(),
+            span: alt.span,
+        }),
+        IROp::BranchReset(reset) => IROp::BranchReset(IRBranchReset {
+            branches: reset.branches.into_iter().map(|b| map_classes(b, f)).collect(),
+            span: reset.span,
+        }),
+        IROp::Quant(quant) => IROp::Quant(IRQuant {
+            child: Box::new(map_classes(*quant.child, f)),
+            ..quant
+        }),
+        IROp::Group(group) => IROp::Group(IRGroup {
+            body: Box::new(map_classes(*group.body, f)),
+            ..group
+        }),
+        IROp::BalancingGroup(group) => IROp::BalancingGroup(IRBalancingGroup {
+            body: Box::new(map_classes(*group.body, f)),
+            ..group
+        }),
+        IROp::Look(look) => IROp::Look(IRLook {
+            body: Box::new(map_classes(*look.body, f)),
+            ..look
+        }),
+        other => other,
+    }
+}
+
+fn shorter_complement(cc: IRCharClass) -> IRCharClass {
+    let Some(members) = class_intervals(&cc.items) else {
+        return cc;
+    };
+    let gaps = complement(&members);
+    // Keep classes that would flip to an empty one, which emitters cannot
+    // write as a class, or to one reaching beyond ASCII.
+    match gaps.last() {
+        Some(&(_, hi)) if hi <= 0x7F => {}
+        _ => return cc,
+    }
+    let flipped = IRCharClass {
+        negated: !cc.negated,
+        items: gaps.into_iter().map(interval_item).collect(),
+        span: cc.span,
+    };
+    if emitted_len(&flipped) < emitted_len(&cc) {
+        flipped
+    } else {
+        cc
+    }
+}
+
+fn emitted_len(cc: &IRCharClass) -> usize {
+    PCRE2Emitter::new(Flags::default())
+        .emit(&IROp::CharClass(cc.clone()))
+        .len()
+}
+
+/// The class members as sorted, merged code point intervals, or `None` if
+/// the class contains anything other than literal characters and ranges
+fn class_intervals(items: &[IRClassItem]) -> Option<Vec<(u32, u32)>> {
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => Some(ch as u32),
+            _ => None,
+        }
+    };
+    let mut intervals = items
+        .iter()
+        .map(|item| match item {
+            IRClassItem::Char(lit) => single(&lit.ch).map(|c| (c, c)),
+            IRClassItem::Range(range) => Some((single(&range.from_ch)?, single(&range.to_ch)?)),
+            IRClassItem::Esc(_) | IRClassItem::Intersection(_) => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    intervals.sort_unstable();
+
+    let mut merged: Vec<(u32, u32)> = Vec::new();
+    for (lo, hi) in intervals {
+        match merged.last_mut() {
+            // Adjacent intervals merge, including across the surrogate gap.
+            Some(last) if lo <= last.1 + 1 || (last.1 + 1 == SURROGATES.0 && lo == SURROGATES.1 + 1) => {
+                last.1 = last.1.max(hi);
+            }
+            _ => merged.push((lo, hi)),
+        }
+    }
+    Some(merged)
+}
+
+/// The scalar values not covered by `members` (sorted and merged)
+fn complement(members: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    let mut gaps = Vec::new();
+    let mut next = 0;
+    for &(lo, hi) in members {
+        if lo > next {
+            gaps.push((next, lo - 1));
+        }
+        next = hi + 1;
+    }
+    if next <= MAX_CHAR {
+        gaps.push((next, MAX_CHAR));
+    }
+    // Surrogates are not characters, so gaps must not include them.
+    gaps.into_iter()
+        .flat_map(|(lo, hi)| {
+            if hi < SURROGATES.0 || lo > SURROGATES.1 {
+                vec![(lo, hi)]
+            } else {
+                [(lo, SURROGATES.0 - 1), (SURROGATES.1 + 1, hi)]
+                    .into_iter()
+                    .filter(|(lo, hi)| lo <= hi)
+                    .collect()
+            }
+        })
+        .collect()
+}
+
+fn interval_item((lo, hi): (u32, u32)) -> IRClassItem {
+    let ch = |c: u32| char::from_u32(c).expect("interval excludes surrogates").to_string();
+    if lo == hi {
+        IRClassItem::Char(IRClassLiteral { ch: ch(lo) })
+    } else {
+        IRClassItem::Range(IRClassRange { from_ch: ch(lo), to_ch: ch(hi) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn class(negated: bool, items: Vec<IRClassItem>) -> IROp {
+        IROp::CharClass(IRCharClass { negated, items, span: Default::default() })
+    }
+
+    fn range(from: char, to: char) -> IRClassItem {
+        IRClassItem::Range(IRClassRange { from_ch: from.to_string(), to_ch: to.to_string() })
+    }
+
+    fn ch(c: char) -> IRClassItem {
+        IRClassItem::Char(IRClassLiteral { ch: c.to_string() })
+    }
+
+    #[test]
+    fn test_huge_class_becomes_negated_complement() {
+        let everything_but_newline = class(false, vec![range('\0', '\t'), range('\u{0B}', char::MAX)]);
+        assert_eq!(complement_classes(everything_but_newline, &Flags::default()), class(true, vec![ch('\n')]));
+    }
+
+    #[test]
+    fn test_negated_class_flips_across_surrogate_gap() {
+        // Everything except the complement of [a-z] is [a-z] itself.
+        let not_outside = class(true, vec![range('\0', '`'), range('{', char::MAX)]);
+        assert_eq!(complement_classes(not_outside, &Flags::default()), class(false, vec![range('a', 'z')]));
+    }
+
+    #[test]
+    fn test_class_kept_unless_strictly_shorter() {
+        for op in [
+            class(false, vec![range('a', 'z')]),
+            class(true, vec![ch('\n')]),
+            class(false, vec![IRClassItem::Esc(IRClassEscape { escape_type: "d".to_string(), property: None })]),
+        ] {
+            assert_eq!(complement_classes(op.clone(), &Flags::default()), op);
+        }
+    }
+
+    #[test]
+    fn test_classes_rewritten_inside_nested_ops() {
+        let op = IROp::Quant(IRQuant {
+            child: Box::new(class(false, vec![range('\0', '\t'), range('\u{0B}', char::MAX)])),
+            min: 0,
+            max: IRMaxBound::Infinite("Inf".to_string()),
+            mode: QuantMode::Greedy,
+            span: Default::default(),
+        });
+        let emitted = PCRE2Emitter::new(Flags::default()).emit(&complement_classes(op, &Flags::default()));
+        assert_eq!(emitted, "[^\\n]*");
+    }
+
+    #[test]
+    fn test_complement_kept_under_ignore_case() {
+        // Under `i`, [^a] would also reject `A`, which this class accepts.
+        for src in ["%flags i\n[\\N{U+0}-`b-\\N{U+10FFFF}]", "(?i)[\\N{U+0}-`b-\\N{U+10FFFF}]"] {
+            let (flags, ast) = crate::core::parser::parse(src).unwrap();
+            let ir = crate::core::compiler::Compiler::new().compile(&ast);
+            assert_eq!(complement_classes(ir.clone(), &flags), ir, "{}", src);
+        }
+        let ir = compile("[\\N{U+0}-`b-\\N{U+10FFFF}]");
+        let emitted = PCRE2Emitter::new(Flags::default()).emit(&complement_classes(ir, &Flags::default()));
+        assert_eq!(emitted, "[^a]");
+    }
+
+    #[test]
+    fn test_complement_limited_to_ascii() {
+        // [^\u{100}] is shorter, but reaches beyond ASCII.
+        let op = class(false, vec![range('\0', '\u{FF}'), range('\u{101}', char::MAX)]);
+        assert_eq!(complement_classes(op.clone(), &Flags::default()), op);
+    }
+
+    #[test]
+    fn test_unwrap_redundant_groups() {
+        let unwrapped = |src: &str| PCRE2Emitter::new(Flags::default()).emit(&unwrap_groups(compile(src)));
+        assert_eq!(unwrapped("(?:a)"), "a");
+        assert_eq!(unwrapped("(?:(?:a))"), "a");
+        assert_eq!(unwrapped("x(?:ab)y|(?:c|d)"), "xaby|c|d");
+        assert_eq!(unwrapped("((?:a|b))(?=(?:c))"), "(a|b)(?=c)");
+        assert_eq!(unwrapped("(?:a|b)+"), "(?:a|b)+");
+        assert_eq!(unwrapped("(?:ab)?"), "(?:ab)?");
+        assert_eq!(unwrapped("x(?:a|b)"), "x(?:a|b)");
+        assert_eq!(unwrapped("(?>(?:a))"), "(?>a)");
+        assert_eq!(unwrapped("(?:(?i)a)b"), "(?:(?i)a)b");
+        assert_eq!(unwrapped("(?|(x)|(?:(a)|(b)))\\g{2}"), "(?|(x)|(?:(a)|(b)))\\g{2}");
+        assert_eq!(unwrapped("(?|(?:(x))|(?:y))"), "(?|(x)|y)");
+    }
+
+    fn compile(src: &str) -> IROp {
+        crate::core::compiler::Compiler::new().compile(&crate::core::parser::parse(src).unwrap().1)
+    }
+
+    #[test]
+    fn test_double_negated_shorthands_fold() {
+        assert_eq!(fold_double_negation(compile("[^\\D]")), compile("\\d"));
+        assert_eq!(fold_double_negation(compile("[^\\W]+")), compile("\\w+"));
+        assert_eq!(fold_double_negation(compile("([^\\P{L}])")), compile("([\\p{L}])"));
+    }
+
+    #[test]
+    fn test_other_negated_classes_kept() {
+        for src in ["[^\\d]", "[^\\D\\W]", "[^a]", "\\D"] {
+            assert_eq!(fold_double_negation(compile(src)), compile(src), "{}", src);
+        }
+    }
+
+    fn canonical(src: &str) -> IROp {
+        let mut ir = compile(src);
+        canonicalize(&mut ir);
+        ir
+    }
+
+    #[test]
+    fn test_canonicalize_unifies_spellings() {
+        assert_eq!(canonical("a{1,}"), canonical("a+"));
+        assert_eq!(canonical("a{0,}b{0,1}"), canonical("a*b?"));
+        assert_eq!(canonical("[\\dz0-9a]"), canonical("[0-9a\\dz]"));
+
+        let lit = |s: &str| IROp::Lit(IRLit { value: s.into(), span: Default::default() });
+        let seq = |parts| IROp::Seq(IRSeq { parts, span: Default::default() });
+        let mut nested = seq(vec![seq(vec![lit("a")]), lit(""), lit("b")]);
+        canonicalize(&mut nested);
+        assert_eq!(nested, lit("ab"));
+    }
+
+    #[test]
+    fn test_canonicalize_output() {
+        let emit = |src: &str| PCRE2Emitter::new(Flags::default()).emit(&canonical(src));
+        assert_eq!(emit("[zb-da\\wb-d]{1,}"), "[ab-dz\\w]+");
+    }
+}
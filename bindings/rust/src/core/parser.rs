@@ -14,96 +14,29 @@
 //! to IR and ultimately emitted as target-specific regex patterns. It includes
 //! comprehensive error handling with position tracking for helpful diagnostics.
 
-use crate::core::errors::STRlingParseError;
+use crate::core::errors::{ParseErrorKind, STRlingParseError, STRlingWarning, SourceSpan, Span};
+use crate::core::lexer::{Cursor, Lexer, TokenKind};
 use crate::core::nodes::*;
+use crate::emitters::Anchored;
 use std::collections::{HashMap, HashSet};
 
 /// Alias for backward compatibility
 pub type ParseError = STRlingParseError;
 
-/// Cursor for tracking position in the input text
-#[derive(Debug, Clone)]
-struct Cursor {
-    text: String,
-    i: usize,
-    extended_mode: bool,
-    in_class: usize,  // nesting count for char classes
-}
-
-#[allow(dead_code)]
-impl Cursor {
-    fn new(text: String, i: usize, extended_mode: bool, in_class: usize) -> Self {
-        Self {
-            text,
-            i,
-            extended_mode,
-            in_class,
-        }
-    }
-
-    fn eof(&self) -> bool {
-        self.i >= self.text.len()
-    }
-
-    fn peek(&self, n: usize) -> String {
-        let j = self.i + n;
-        if j >= self.text.len() {
-            String::new()
-        } else {
-            self.text.chars().nth(j).map(|c| c.to_string()).unwrap_or_default()
-        }
-    }
-
-    fn peek_char(&self, n: usize) -> Option<char> {
-        let j = self.i + n;
-        self.text.chars().nth(j)
-    }
-
-    fn take(&mut self) -> Option<char> {
-        if self.eof() {
-            None
-        } else {
-            let ch = self.text.chars().nth(self.i);
-            self.i += 1;
-            ch
-        }
-    }
+/// Shortest run of plain characters (in bytes) that the literal fast path
+/// batches into a single `Literal`; shorter runs parse one node per char.
+const LITERAL_RUN_MIN: usize = 64;
 
-    fn match_str(&mut self, s: &str) -> bool {
-        if self.text[self.i..].starts_with(s) {
-            self.i += s.len();
-            true
-        } else {
-            false
-        }
-    }
+/// Deepest group nesting the parser accepts; deeper patterns are rejected
+/// with an error instead of exhausting the stack in the recursive descent.
+const MAX_NESTING_DEPTH: usize = 100;
 
-    fn skip_ws_and_comments(&mut self) {
-        if !self.extended_mode || self.in_class > 0 {
-            return;
-        }
-        // In free-spacing mode, ignore spaces/tabs/newlines and #-to-EOL comments
-        while !self.eof() {
-            if let Some(ch) = self.peek_char(0) {
-                if " \t\r\n".contains(ch) {
-                    self.i += 1;
-                    continue;
-                }
-                if ch == '#' {
-                    // skip comment to end of line
-                    while !self.eof() && !"\r\n".contains(self.peek_char(0).unwrap_or('\0')) {
-                        self.i += 1;
-                    }
-                    continue;
-                }
-            }
-            break;
-        }
-    }
-}
+/// Backtracking control verbs that `(*NAME)` may spell, besides `MARK`
+const VERBS: [&str; 7] = ["ACCEPT", "FAIL", "F", "COMMIT", "PRUNE", "SKIP", "THEN"];
 
 /// Parser for STRling DSL
 #[allow(dead_code)]
+#[derive(Debug, Clone)]
 pub struct Parser {
     original_text: String,
     flags: Flags,
@@ -112,6 +45,78 @@ pub struct Parser {
     cap_count: usize,
     cap_names: HashSet<String>,
     control_escapes: HashMap<char, char>,
+    recovering: bool,
+    errors: Vec<STRlingParseError>,
+    assertion_quantifiers: AssertionQuantifiers,
+    brace_literals: BraceLiterals,
+    warnings: Vec<STRlingWarning>,
+    depth: usize,
+    /// `%define` bodies by name, parsed on each `$name` reference
+    definitions: HashMap<String, String>,
+    /// Definitions currently being expanded, innermost last
+    expanding: Vec<String>,
+    /// Absolute anchors requested by an `%anchor` directive
+    anchored: Anchored,
+    /// Flags set by a `%flags` directive, if the source has one
+    directive_flags: Option<Flags>,
+    default_flags: Flags,
+    flag_directives: FlagDirectives,
+    leading_bracket: LeadingBracket,
+}
+
+/// How the parser treats a quantifier applied to a zero-width assertion,
+/// such as `^+` or `(?=a)*`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AssertionQuantifiers {
+    /// Keep the quantifier; the compiler records a
+    /// `STRlingWarning::QuantifiedAssertion` for it.
+    #[default]
+    Keep,
+    /// Fail with an `InvalidQuantifier` error.
+    Reject,
+    /// Keep the assertion, drop the quantifier and record a
+    /// `STRlingWarning::QuantifiedAssertion`.
+    Drop,
+}
+
+/// How the parser treats a `{` after an atom that does not begin a valid
+/// quantifier, such as `a{`, `a{1,` or `a{foo}`.
+///
+/// A `{` that cannot follow an atom, as at the start of a pattern, is
+/// always a literal. Well-formed quantifiers with invalid bounds, such as
+/// `a{5,2}`, are errors under every policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BraceLiterals {
+    /// The `{` is a literal, as in PCRE; emitters escape it as `\{`.
+    #[default]
+    Lenient,
+    /// The `{` fails with an `InvalidQuantifier` error.
+    Strict,
+}
+
+/// How a `%flags` directive combines with the parser's default flags (see
+/// `Parser::with_default_flags`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlagDirectives {
+    /// The directive turns flags on in addition to the defaults; it cannot
+    /// turn a default off.
+    #[default]
+    Merge,
+    /// The directive replaces the defaults entirely.
+    Override,
+}
+
+/// How the parser reads a `]` straight after the `[` or `[^` that opens a
+/// character class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LeadingBracket {
+    /// The `]` is a member, as in PCRE: `[]a]` matches `]` or `a`, and `[]`
+    /// is an unterminated class.
+    #[default]
+    Literal,
+    /// The `]` closes the class, as in JavaScript: `[]` is an empty class
+    /// that matches nothing and `[^]` matches any character.
+    Close,
 }
 
 impl Parser {
@@ -125,6 +130,19 @@ impl Parser {
             cap_count: 0,
             cap_names: HashSet::new(),
             control_escapes: HashMap::new(),
+            recovering: false,
+            errors: Vec::new(),
+            assertion_quantifiers: AssertionQuantifiers::Keep,
+            brace_literals: BraceLiterals::Lenient,
+            warnings: Vec::new(),
+            depth: 0,
+            definitions: HashMap::new(),
+            expanding: Vec::new(),
+            anchored: Anchored::None,
+            directive_flags: None,
+            default_flags: Flags::default(),
+            flag_directives: FlagDirectives::Merge,
+            leading_bracket: LeadingBracket::Literal,
         };
         
         // Initialize control escapes
@@ -135,23 +153,132 @@ impl Parser {
         parser.control_escapes.insert('v', '\u{000B}');
         
         // Parse directives
-        let (flags, src) = parser.parse_directives(&text);
-        parser.flags = flags.clone();
-        parser.src = src.clone();
-        parser.cur = Cursor::new(src, 0, flags.extended, 0);
+        let (flags, src, definitions, anchored) = parser.parse_directives(&text);
+        parser.definitions = definitions;
+        parser.anchored = anchored;
+        parser.directive_flags = flags;
+        parser.src = src;
+        parser.resolve_flags();
         
         parser
     }
 
-    fn raise_error(&self, message: String, pos: usize) -> STRlingParseError {
+    /// Create a parser that reads all of `text` as pattern source, with no
+    /// directive lines or comment lines
+    fn verbatim(text: String) -> Self {
+        let mut parser = Parser::new(String::new());
+        parser.original_text = text.clone();
+        parser.src = text;
+        parser.resolve_flags();
+        parser
+    }
+
+    /// Seed the parse with `flags`, for patterns that would otherwise all
+    /// repeat the same `%flags` directive
+    ///
+    /// A `%flags` directive in the source combines with these as
+    /// `with_flag_directives` chooses.
+    pub fn with_default_flags(mut self, flags: Flags) -> Self {
+        self.default_flags = flags;
+        self.resolve_flags();
+        self
+    }
+
+    /// Choose how a `%flags` directive combines with the default flags
+    pub fn with_flag_directives(mut self, policy: FlagDirectives) -> Self {
+        self.flag_directives = policy;
+        self.resolve_flags();
+        self
+    }
+
+    /// Settle the effective flags from the defaults and any directive, and
+    /// restart the cursor in the matching mode
+    fn resolve_flags(&mut self) {
+        let defaults = &self.default_flags;
+        self.flags = match (&self.directive_flags, self.flag_directives) {
+            (None, _) => defaults.clone(),
+            (Some(directive), FlagDirectives::Override) => directive.clone(),
+            (Some(directive), FlagDirectives::Merge) => Flags {
+                ignore_case: defaults.ignore_case || directive.ignore_case,
+                multiline: defaults.multiline || directive.multiline,
+                dot_all: defaults.dot_all || directive.dot_all,
+                unicode: defaults.unicode || directive.unicode,
+                extended: defaults.extended || directive.extended,
+                ascii: defaults.ascii || directive.ascii,
+                global: defaults.global || directive.global,
+                sticky: defaults.sticky || directive.sticky,
+            },
+        };
+        self.cur = Cursor::new(self.src.clone(), 0, self.flags.extended, 0);
+    }
+
+    /// Choose how quantified anchors and lookarounds are handled
+    pub fn with_assertion_quantifiers(mut self, policy: AssertionQuantifiers) -> Self {
+        self.assertion_quantifiers = policy;
+        self
+    }
+
+    /// Choose how a `{` that does not begin a valid quantifier is handled
+    pub fn with_brace_literals(mut self, policy: BraceLiterals) -> Self {
+        self.brace_literals = policy;
+        self
+    }
+
+    /// Choose how a `]` that directly follows `[` or `[^` is read
+    pub fn with_leading_bracket(mut self, policy: LeadingBracket) -> Self {
+        self.leading_bracket = policy;
+        self
+    }
+
+    /// The token stream of the pattern source
+    ///
+    /// The tokens are recorded by a recovering parse of the pattern with
+    /// this parser's flags, definitions and policies, so they split the
+    /// source exactly as parsing does; input that fails to parse becomes
+    /// `TokenKind::Invalid` tokens. Spans share the coordinates of parse
+    /// errors: byte offsets into the pattern text after any directive lines.
+    pub fn tokens(&self) -> Lexer {
+        let mut parser = self.clone();
+        parser.cap_count = 0;
+        parser.cap_names.clear();
+        parser.errors.clear();
+        parser.resolve_flags();
+        parser.cur.tokens = Some(Vec::new());
+        parser.parse_recoverable();
+        Lexer::new(parser.cur.tokens.take().unwrap_or_default())
+    }
+
+    /// Warnings recorded while parsing, e.g. dropped quantifiers
+    pub fn warnings(&self) -> &[STRlingWarning] {
+        &self.warnings
+    }
+
+    fn raise_error(&self, kind: ParseErrorKind, message: String) -> STRlingParseError {
         // TODO: Integrate hint engine
         let hint = None;  // get_hint(message, self.src, pos)
-        STRlingParseError::new(message, pos, self.src.clone(), hint)
+        let pos = kind.span().0;
+        STRlingParseError::new(message, pos, self.src.clone(), hint).with_kind(kind)
     }
 
     /// Parse directives from the input text
-    fn parse_directives(&self, text: &str) -> (Flags, String) {
-        let mut flags = Flags::default();
+    ///
+    /// Besides `%` directive lines, this drops comment and blank lines: a
+    /// line whose first non-whitespace characters are `#` or `//` is a
+    /// comment, and a line of only whitespace is blank. Inside the pattern
+    /// body both are kept when a character class is still open at the end of
+    /// the preceding line, since there `#` and `/` are class members.
+    ///
+    /// `%define name = body` lines name a subpattern that the pattern can
+    /// reference as `$name`; a `%define` without `=` or with a name that is
+    /// not an identifier is ignored like other unknown directives.
+    ///
+    /// `%anchor full`, `%anchor start` and `%anchor end` pin the whole
+    /// pattern to the absolute start and/or end of the subject; any other
+    /// argument is ignored.
+    fn parse_directives(&self, text: &str) -> (Option<Flags>, String, HashMap<String, String>, Anchored) {
+        let mut flags = None;
+        let mut definitions = HashMap::new();
+        let mut anchored = Anchored::None;
         let lines: Vec<&str> = text.lines().collect();
         let mut pattern_lines: Vec<&str> = Vec::new();
         let mut in_pattern = false;
@@ -159,8 +286,9 @@ impl Parser {
         for line in lines {
             let stripped = line.trim();
             
-            // Skip leading blank lines or comments
-            if !in_pattern && (stripped.is_empty() || stripped.starts_with('#')) {
+            // Skip blank lines and comments, unless they continue a class
+            let is_comment = stripped.starts_with('#') || stripped.starts_with("//");
+            if (stripped.is_empty() || is_comment) && !ends_in_open_class(&pattern_lines) {
                 continue;
             }
             
@@ -168,25 +296,8 @@ impl Parser {
             if !in_pattern && stripped.starts_with("%flags") {
                 if let Some(idx) = line.find("%flags") {
                     let after = &line[idx + "%flags".len()..];
-                    
-                    // Extract flags portion
-                    let allowed: HashSet<char> = " ,\t[]imsuxIMSUX".chars().collect();
-                    let mut j = 0;
-                    while j < after.len() && allowed.contains(&after.chars().nth(j).unwrap()) {
-                        j += 1;
-                    }
-                    
-                    let flags_token = &after[..j];
-                    let remainder = &after[j..];
-                    
-                    // Parse flags
-                    let letters: String = flags_token
-                        .chars()
-                        .filter(|c| "imsux".contains(*c) || "IMSUX".contains(*c))
-                        .map(|c| c.to_ascii_lowercase())
-                        .collect();
-                    
-                    flags = Flags::from_letters(&letters);
+                    let (flags_token, remainder) = split_flag_words(after);
+                    flags = Some(Flags::from_letters(flags_token));
                     
                     if !remainder.trim().is_empty() {
                         in_pattern = true;
@@ -196,6 +307,28 @@ impl Parser {
                 continue;
             }
             
+            // Process %define directive
+            if !in_pattern && stripped.starts_with("%define") {
+                if let Some((name, body)) = stripped["%define".len()..].split_once('=') {
+                    let name = name.trim();
+                    if is_identifier(name) {
+                        definitions.insert(name.to_string(), body.trim().to_string());
+                    }
+                }
+                continue;
+            }
+            
+            // Process %anchor directive
+            if !in_pattern && stripped.starts_with("%anchor") {
+                match stripped["%anchor".len()..].trim() {
+                    "full" => anchored = Anchored::Both,
+                    "start" => anchored = Anchored::Start,
+                    "end" => anchored = Anchored::End,
+                    _ => {}
+                }
+                continue;
+            }
+
             // Skip other directives
             if !in_pattern && stripped.starts_with('%') {
                 continue;
@@ -207,86 +340,191 @@ impl Parser {
         }
         
         let pattern = pattern_lines.join("\n");
-        (flags, pattern)
+        (flags, pattern, definitions, anchored)
+    }
+
+    /// Wrap a complete pattern in the anchors an `%anchor` directive asked
+    /// for, grouping a top-level alternation so they bind to every branch
+    fn apply_anchors(&self, node: Node) -> Node {
+        if self.anchored == Anchored::None {
+            return node;
+        }
+        let span = node.span();
+        let mut parts = match node {
+            Node::Sequence(seq) => seq.parts,
+            Node::Alternation(alt) => vec![Node::Group(Group {
+                capturing: false,
+                name: None,
+                atomic: Some(false),
+                body: Box::new(Node::Alternation(alt)),
+                span: SourceSpan(span),
+            })],
+            other => vec![other],
+        };
+        let anchor = |at| Node::Anchor(Anchor { at, span: Default::default() });
+        if self.anchored != Anchored::End {
+            parts.insert(0, anchor(AnchorKind::AbsoluteStart));
+        }
+        if self.anchored != Anchored::Start {
+            parts.push(anchor(AnchorKind::AbsoluteEnd));
+        }
+        Node::Sequence(Sequence { parts, span: SourceSpan(span) })
     }
 
     /// Parse the entire pattern
     /// Returns a tuple of (Flags, Node) where Flags contains parsed directives
     pub fn parse(&mut self) -> Result<(Flags, Node), STRlingParseError> {
+        // An empty pattern is an empty sequence, which matches the empty
+        // string; patterns built by concatenation may have empty parts.
+        if self.cur.eof() {
+            let node = Node::Sequence(Sequence { parts: Vec::new(), span: SourceSpan(Some((0, 0))) });
+            return Ok((self.flags.clone(), self.apply_anchors(node)));
+        }
         let node = self.parse_alt()?;
         self.cur.skip_ws_and_comments();
         
         if !self.cur.eof() {
-            if let Some(ch) = self.cur.peek_char(0) {
-                if ch == ')' {
-                    return Err(STRlingParseError::new(
-                        "Unmatched ')'".to_string(),
-                        self.cur.i,
-                        self.src.clone(),
-                        Some("This ')' character does not have a matching opening '('. Did you mean to escape it with '\\)'?".to_string()),
-                    ));
-                }
-                if ch == '|' {
-                    return Err(self.raise_error(
-                        "Alternation lacks right-hand side".to_string(),
-                        self.cur.i,
-                    ));
-                }
-            }
-            return Err(self.raise_error(
-                "Unexpected trailing input".to_string(),
-                self.cur.i,
-            ));
+            return Err(self.trailing_input_error());
         }
         
-        Ok((self.flags.clone(), node))
+        Ok((self.flags.clone(), self.apply_anchors(node)))
     }
 
-    /// Parse alternation: seq ('|' seq)* | seq
-    fn parse_alt(&mut self) -> Result<Node, STRlingParseError> {
-        self.cur.skip_ws_and_comments();
-        
-        // Check if the pattern starts with a pipe (no left-hand side)
-        if let Some('|') = self.cur.peek_char(0) {
-            return Err(self.raise_error(
-                "Alternation lacks left-hand side".to_string(),
-                self.cur.i,
-            ));
-        }
-        
-        let mut branches = vec![self.parse_seq()?];
-        self.cur.skip_ws_and_comments();
-        
-        while let Some('|') = self.cur.peek_char(0) {
-            let pipe_pos = self.cur.i;
-            self.cur.take();
+    /// Parse the entire pattern, collecting every error instead of stopping
+    ///
+    /// Input that fails to parse is skipped and replaced by a `Node::Error`
+    /// placeholder, so later problems are still diagnosed. Intended for
+    /// editors and other tooling that want all diagnostics at once.
+    ///
+    /// # Returns
+    ///
+    /// The recovered tree, or `None` if nothing but errors could be
+    /// recovered, together with all errors in source order of detection
+    pub fn parse_recoverable(&mut self) -> (Option<(Flags, Node)>, Vec<STRlingParseError>) {
+        self.recovering = true;
+        let mut parts = Vec::new();
+        loop {
+            let start = self.cur.i;
+            match self.parse_alt() {
+                Ok(node) => parts.push(node),
+                Err(err) => parts.push(self.record_error(err, start)),
+            }
             self.cur.skip_ws_and_comments();
-            
-            // Check if the pipe is followed by end-of-input
             if self.cur.eof() {
-                return Err(self.raise_error(
-                    "Alternation lacks right-hand side".to_string(),
-                    pipe_pos,
-                ));
-            }
-            
-            // Check if the pipe is followed by another pipe (empty branch)
-            if let Some('|') = self.cur.peek_char(0) {
-                return Err(self.raise_error(
-                    "Empty alternation branch".to_string(),
-                    pipe_pos,
-                ));
+                break;
             }
-            
-            branches.push(self.parse_seq()?);
-            self.cur.skip_ws_and_comments();
+            // Only a stray `)` or similar can stop `parse_alt` early.
+            let start = self.cur.i;
+            let err = self.trailing_input_error();
+            parts.push(self.record_error(err, start));
         }
-        
+        self.recovering = false;
+
+        let root = if parts.len() == 1 {
+            parts.pop().unwrap()
+        } else {
+            Node::Sequence(Sequence { parts, span: SourceSpan(Some((0, self.cur.i))) })
+        };
+        let errors = std::mem::take(&mut self.errors);
+        match root {
+            Node::Error(_) => (None, errors),
+            root => (Some((self.flags.clone(), self.apply_anchors(root))), errors),
+        }
+    }
+
+    /// Build the error for input left over after a complete expression
+    fn trailing_input_error(&self) -> STRlingParseError {
+        if let Some(')') = self.cur.peek_char(0) {
+            return STRlingParseError::new(
+                "Unmatched ')'".to_string(),
+                self.cur.i,
+                self.src.clone(),
+                Some("This ')' character does not have a matching opening '('. Did you mean to escape it with '\\)'?".to_string()),
+            ).with_kind(ParseErrorKind::UnexpectedChar {
+                found: ')',
+                span: (self.cur.i, self.cur.i + 1),
+            });
+        }
+        let found = self.cur.peek_char(0).unwrap_or_default();
+        self.raise_error(
+            ParseErrorKind::UnexpectedChar {
+                found,
+                span: (self.cur.i, self.cur.i + found.len_utf8()),
+            },
+            "Unexpected trailing input".to_string(),
+        )
+    }
+
+    /// Record `err` during recovery and return a placeholder for the input
+    /// skipped since `start`, always consuming at least one character
+    fn record_error(&mut self, err: STRlingParseError, start: usize) -> Node {
+        self.errors.push(err);
+        if self.cur.i == start {
+            self.cur.take();
+        }
+        // A failed class may not have closed; recovery resumes outside it.
+        self.cur.in_class = 0;
+        self.cur.invalidate(start);
+        Node::Error(ErrorNode {
+            span: (start, self.cur.i),
+        })
+    }
+
+    /// Parse as much of the pattern as forms a complete expression
+    ///
+    /// Unlike `parse`, this stops at the first unmatched `)` (or any other
+    /// input the grammar cannot continue with) instead of reporting it, and
+    /// returns how many bytes of the pattern source were consumed. Errors
+    /// inside the consumed prefix are still reported.
+    ///
+    /// # Returns
+    ///
+    /// A tuple of (Flags, Node, consumed) where `consumed` is a byte offset
+    /// into the pattern source (the text after any directive lines)
+    pub fn parse_partial(&mut self) -> Result<(Flags, Node, usize), STRlingParseError> {
+        let node = self.parse_alt()?;
+        Ok((self.flags.clone(), node, self.cur.i))
+    }
+
+    /// Parse alternation: seq ('|' seq)* | seq
+    ///
+    /// Empty branches (`a||b`, `(|x)`) are legal and parse to an empty
+    /// literal; the compiler records a warning for each.
+    fn parse_alt(&mut self) -> Result<Node, STRlingParseError> {
+        let start = self.cur.i;
+        let branches = self.parse_branches(false)?;
         if branches.len() == 1 {
             Ok(branches.into_iter().next().unwrap())
         } else {
-            Ok(Node::Alternation(Alternation { branches }))
+            Ok(Node::Alternation(Alternation {
+                branches,
+                span: SourceSpan(Some((start, self.cur.i))),
+            }))
+        }
+    }
+
+    /// Parse `|`-separated branches
+    ///
+    /// With `reset_captures`, capture counting restarts at each branch and
+    /// ends at the largest per-branch count, as in a branch reset group.
+    fn parse_branches(&mut self, reset_captures: bool) -> Result<Vec<Node>, STRlingParseError> {
+        let start_count = self.cap_count;
+        let mut end_count = start_count;
+        let mut branches = Vec::new();
+        loop {
+            if reset_captures {
+                self.cap_count = start_count;
+            }
+            branches.push(self.parse_seq()?);
+            end_count = end_count.max(self.cap_count);
+            self.cur.skip_ws_and_comments();
+            if !self.cur.match_str("|") {
+                break;
+            }
+            self.cur.token(TokenKind::Alternation, self.cur.i - 1);
         }
+        self.cap_count = end_count;
+        Ok(branches)
     }
 
     /// Parse sequence: term*
@@ -294,7 +532,7 @@ impl Parser {
         let mut parts = Vec::new();
         
         loop {
-            self.cur.skip_ws_and_comments();
+            parts.extend(self.cur.take_comments());
             
             if self.cur.eof() {
                 break;
@@ -308,11 +546,41 @@ impl Parser {
             }
             
             // Parse one term (atom potentially followed by quantifier)
-            let atom = self.parse_atom()?;
+            let start = self.cur.i;
+            let atom = match self.parse_atom() {
+                Ok(mut atom) => {
+                    atom.set_span((start, self.cur.i));
+                    atom
+                }
+                Err(err) if self.recovering => {
+                    parts.push(self.record_error(err, start));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
             
-            // Check for quantifier after the atom
-            self.cur.skip_ws_and_comments();
-            if let Some(quant) = self.try_parse_quantifier()? {
+            // Check for quantifier after the atom; comments in between
+            // follow the quantified term
+            let comments = self.cur.take_comments();
+            let atom_start = start;
+            let start = self.cur.i;
+            let quant = match self
+                .try_parse_quantifier()
+                .and_then(|quant| self.check_quantifiable(&atom, quant, start))
+            {
+                Ok(quant) => quant,
+                Err(err) if self.recovering => {
+                    parts.push(atom);
+                    parts.extend(comments);
+                    parts.push(self.record_error(err, start));
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if self.cur.i > start {
+                self.cur.token(TokenKind::Quantifier, start);
+            }
+            if let Some(quant) = quant {
                 // Wrap the atom in a quantifier
                 let mode = quant.2;
                 parts.push(Node::Quantifier(Quantifier {
@@ -323,21 +591,26 @@ impl Parser {
                     greedy: mode == "Greedy",
                     lazy: mode == "Lazy",
                     possessive: mode == "Possessive",
+                    span: SourceSpan(Some((atom_start, self.cur.i))),
                 }));
             } else {
                 parts.push(atom);
             }
+            parts.extend(comments);
         }
         
         if parts.is_empty() {
             // Empty sequence - return empty literal
             Ok(Node::Literal(Literal {
                 value: String::new(),
+                span: SourceSpan(Some((self.cur.i, self.cur.i))),
             }))
         } else if parts.len() == 1 {
             Ok(parts.into_iter().next().unwrap())
         } else {
-            Ok(Node::Sequence(Sequence { parts }))
+            let start = parts[0].span().map_or(0, |(start, _)| start);
+            let end = parts[parts.len() - 1].span().map_or(self.cur.i, |(_, end)| end);
+            Ok(Node::Sequence(Sequence { parts, span: SourceSpan(Some((start, end))) }))
         }
     }
 
@@ -365,10 +638,15 @@ impl Parser {
                 (0, MaxBound::Finite(1))
             }
             Some('{') => {
-                // Parse {m,n} or {n}
-                self.cur.take();
-                // TODO: Implement brace quantifier parsing
-                return Ok(None);
+                // Parse {m,n} or {n}; a brace that is not a quantifier is a literal
+                let save = self.cur.i;
+                match self.parse_brace_quant()? {
+                    Some(bounds) => bounds,
+                    None => {
+                        self.cur.i = save;
+                        return Ok(None);
+                    }
+                }
             }
             _ => return Ok(None),
         };
@@ -387,51 +665,303 @@ impl Parser {
         Ok(Some((min, max, mode)))
     }
 
+    /// Apply the assertion-quantifier policy to a parsed quantifier
+    ///
+    /// Anchors, lookarounds and flag settings match no characters, so
+    /// repeating them is meaningless (and engine-dependent); everything else passes through.
+    /// No engine accepts a quantified flag setting or verb, so those are
+    /// only ever dropped or rejected.
+    fn check_quantifiable(
+        &mut self,
+        atom: &Node,
+        quant: Option<(i32, MaxBound, String)>,
+        start: usize,
+    ) -> Result<Option<(i32, MaxBound, String)>, STRlingParseError> {
+        let what = match atom {
+            Node::Anchor(_) => "anchor",
+            Node::Lookahead(_)
+            | Node::NegativeLookahead(_)
+            | Node::Lookbehind(_)
+            | Node::NegativeLookbehind(_) => "lookaround",
+            Node::SetFlags(_) => "flag setting",
+            Node::Verb(_) => "backtracking verb",
+            _ => return Ok(quant),
+        };
+        if quant.is_none() {
+            return Ok(None);
+        }
+        let span = (start, self.cur.i);
+        let assertion = matches!(what, "anchor" | "lookaround");
+        match self.assertion_quantifiers {
+            // The compiler warns about the kept quantifier.
+            AssertionQuantifiers::Keep if assertion => Ok(quant),
+            AssertionQuantifiers::Keep | AssertionQuantifiers::Reject => Err(self.raise_error(
+                ParseErrorKind::InvalidQuantifier { span },
+                format!("Cannot quantify {}", what),
+            )),
+            AssertionQuantifiers::Drop => {
+                self.warnings.push(STRlingWarning::QuantifiedAssertion { span });
+                Ok(None)
+            }
+        }
+    }
+
+    /// Parse a brace quantifier body: {n}, {m,}, {m,n} or {,n}
+    ///
+    /// `{,n}` is shorthand for `{0,n}`. Returns `None` (leaving the caller to
+    /// backtrack) when the brace is not one of these forms and `brace_literals`
+    /// makes it a literal; see `BraceLiterals`.
+    fn parse_brace_quant(&mut self) -> Result<Option<(i32, MaxBound)>, STRlingParseError> {
+        let quant_start = self.cur.i;
+        self.cur.take();  // consume '{'
+
+        let min = match self.read_bound()? {
+            Some(min) => min,
+            None if self.cur.peek_char(0) == Some(',')
+                && self.cur.peek_char(1).is_some_and(|c| c.is_ascii_digit()) => 0,
+            None => {
+                let rest = &self.cur.text[self.cur.i..];
+                let content_end = rest.find(['}', '\r', '\n']).unwrap_or(rest.len());
+                let content = &rest[..content_end];
+                let malformed = rest[content_end..].starts_with('}')
+                    && content.chars().any(|c| !c.is_ascii_digit() && c != ',');
+                return match (self.brace_literals, malformed) {
+                    (BraceLiterals::Lenient, _) => Ok(None),
+                    (BraceLiterals::Strict, false) => Err(self.raise_error(
+                        ParseErrorKind::InvalidQuantifier { span: (quant_start, quant_start + 1) },
+                        "Brace quantifier: '{' does not begin a quantifier".to_string(),
+                    )),
+                    (BraceLiterals::Strict, true) => Err(self.raise_error(
+                        ParseErrorKind::InvalidQuantifier {
+                            span: (quant_start, self.cur.i + content_end + 1),
+                        },
+                        "Brace quantifier: Invalid brace quantifier content".to_string(),
+                    )),
+                };
+            }
+        };
+
+        let max = if self.cur.match_str(",") {
+            match self.read_bound()? {
+                Some(max) => MaxBound::Finite(max),
+                None => MaxBound::Infinite("Inf".to_string()),
+            }
+        } else {
+            MaxBound::Finite(min)
+        };
+
+        if !self.cur.match_str("}") {
+            if self.brace_literals == BraceLiterals::Lenient {
+                return Ok(None);
+            }
+            return Err(self.raise_error(
+                ParseErrorKind::InvalidQuantifier { span: (quant_start, self.cur.i) },
+                "Incomplete quantifier (closing '}')".to_string(),
+            ));
+        }
+
+        if let MaxBound::Finite(max) = max {
+            if min > max {
+                return Err(self.raise_error(
+                    ParseErrorKind::InvalidQuantifier { span: (quant_start, self.cur.i) },
+                    format!("Invalid quantifier range {{{},{}}}", min, max),
+                ));
+            }
+        }
+
+        Ok(Some((min, max)))
+    }
+
+    /// Read an optional decimal quantifier bound
+    fn read_bound(&mut self) -> Result<Option<i32>, STRlingParseError> {
+        let start = self.cur.i;
+        while let Some(ch) = self.cur.peek_char(0) {
+            if !ch.is_ascii_digit() {
+                break;
+            }
+            self.cur.take();
+        }
+        let digits = &self.cur.text[start..self.cur.i];
+        if digits.is_empty() {
+            return Ok(None);
+        }
+        match digits.parse::<i32>() {
+            Ok(n) => Ok(Some(n)),
+            Err(_) => Err(self.raise_error(
+                ParseErrorKind::QuantifierBoundTooLarge { span: (start, self.cur.i) },
+                format!("Quantifier bound {} is too large", digits),
+            )),
+        }
+    }
+
     /// Parse a single atom (character, class, group, etc.)
     fn parse_atom(&mut self) -> Result<Node, STRlingParseError> {
         if self.cur.eof() {
             return Err(self.raise_error(
+                ParseErrorKind::Syntax { span: (self.cur.i, self.cur.i) },
                 "Unexpected end of input".to_string(),
-                self.cur.i,
             ));
         }
         
         let ch = self.cur.peek_char(0).unwrap();
         
+        let start = self.cur.i;
         match ch {
             '.' => {
                 self.cur.take();
-                Ok(Node::Dot(Dot {}))
+                self.cur.token(TokenKind::Dot, start);
+                Ok(Node::Dot(Dot { not_newline: false, span: Default::default() }))
             }
             '^' => {
                 self.cur.take();
+                self.cur.token(TokenKind::Anchor, start);
                 Ok(Node::Anchor(Anchor {
-                    at: "Start".to_string(),
+                    at: AnchorKind::Start,
+                    span: Default::default(),
                 }))
             }
             '$' => {
+                if let Some(name) = self.definition_reference() {
+                    return self.expand_definition(name);
+                }
                 self.cur.take();
+                self.cur.token(TokenKind::Anchor, start);
                 Ok(Node::Anchor(Anchor {
-                    at: "End".to_string(),
+                    at: AnchorKind::End,
+                    span: Default::default(),
                 }))
             }
             '(' => self.parse_group(),
             '[' => self.parse_char_class(),
-            '\\' => self.parse_escape(),
-            _ => self.parse_literal(),
+            '\\' => {
+                let node = self.parse_escape()?;
+                let kind = match node {
+                    Node::Literal(_) => TokenKind::Literal,
+                    _ => TokenKind::Escape,
+                };
+                self.cur.token(kind, start);
+                Ok(node)
+            }
+            _ => match self.parse_literal_run() {
+                Some(node) => Ok(node),
+                None => self.parse_literal(),
+            },
+        }
+    }
+
+    /// The defined name referenced by a `$name` at the cursor, if any
+    ///
+    /// The name is the longest identifier after `$`; when no definition has
+    /// that name, `$` keeps its meaning as the end anchor.
+    fn definition_reference(&self) -> Option<String> {
+        let rest = &self.cur.text[self.cur.i + 1..];
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let name = &rest[..end];
+        (is_identifier(name) && self.definitions.contains_key(name)).then(|| name.to_string())
+    }
+
+    /// Parse the definition referenced by `$name` at the cursor
+    ///
+    /// The body is parsed afresh for every reference, so capturing groups
+    /// inside it are numbered per use. Alternations and sequences come back
+    /// wrapped in a non-capturing group so they bind like a single atom.
+    fn expand_definition(&mut self, name: String) -> Result<Node, STRlingParseError> {
+        let start = self.cur.i;
+        self.cur.i += 1 + name.len();
+        self.cur.token(TokenKind::Reference, start);
+        let span = (start, self.cur.i);
+        if self.expanding.contains(&name) {
+            let chain: Vec<String> = self.expanding.iter().chain([&name]).map(|n| format!("${}", n)).collect();
+            return Err(self.raise_error(
+                ParseErrorKind::Syntax { span },
+                format!("Cyclic definition: {}", chain.join(" -> ")),
+            ));
+        }
+
+        let body = Cursor::new(self.definitions[&name].clone(), 0, self.cur.extended_mode, 0);
+        let outer = std::mem::replace(&mut self.cur, body);
+        let recovering = std::mem::replace(&mut self.recovering, false);
+        self.expanding.push(name.clone());
+        let result = self.parse_alt().and_then(|node| {
+            self.cur.skip_ws_and_comments();
+            if self.cur.eof() {
+                Ok(node)
+            } else {
+                Err(self.trailing_input_error())
+            }
+        });
+        self.expanding.pop();
+        self.recovering = recovering;
+        self.cur = outer;
+
+        // Nested expansions pass errors up unchanged; the outermost
+        // reference reports them at its own position in the pattern.
+        let mut node = match result {
+            Ok(node) => node,
+            Err(err) if !self.expanding.is_empty() => return Err(err),
+            Err(err) => {
+                return Err(self.raise_error(
+                    ParseErrorKind::Syntax { span },
+                    format!("In definition ${}: {}", name, err.message),
+                ))
+            }
+        };
+        // Spans inside the body point into the definition, not the pattern.
+        respan(&mut node, span);
+        Ok(match node {
+            Node::Alternation(_) | Node::Sequence(_) => Node::Group(Group {
+                capturing: false,
+                name: None,
+                atomic: Some(false),
+                body: Box::new(node),
+                span: Default::default(),
+            }),
+            other => other,
+        })
+    }
+
+    /// Fast path: batch a long run of plain characters into one `Literal`
+    ///
+    /// Generated patterns (e.g. allow-lists) are often kilobytes of plain
+    /// text; building one node per character dominates their parse time. The
+    /// compiler coalesces adjacent literals anyway, so emitted output is
+    /// unchanged. Returns `None` for short runs, in free-spacing mode and
+    /// while recording tokens, which are per character.
+    fn parse_literal_run(&mut self) -> Option<Node> {
+        if self.cur.extended_mode || self.cur.tokens.is_some() {
+            return None;
         }
+        let rest = &self.cur.text[self.cur.i..];
+        let mut end = rest
+            .find(|c: char| "\\.^$|()[]{}*+?".contains(c))
+            .unwrap_or(rest.len());
+        // A trailing quantifier binds to the last character only.
+        if rest[end..].starts_with(['*', '+', '?', '{']) {
+            end = rest[..end].char_indices().last().map_or(0, |(i, _)| i);
+        }
+        if end < LITERAL_RUN_MIN {
+            return None;
+        }
+        let value = rest[..end].to_string();
+        self.cur.i += end;
+        Some(Node::Literal(Literal { value, span: Default::default() }))
     }
 
     /// Parse a literal character
     fn parse_literal(&mut self) -> Result<Node, STRlingParseError> {
+        let start = self.cur.i;
         if let Some(ch) = self.cur.take() {
+            self.cur.token(TokenKind::Literal, start);
             Ok(Node::Literal(Literal {
                 value: ch.to_string(),
+                span: Default::default(),
             }))
         } else {
             Err(self.raise_error(
+                ParseErrorKind::Syntax { span: (self.cur.i, self.cur.i) },
                 "Unexpected end of input".to_string(),
-                self.cur.i,
             ))
         }
     }
@@ -443,8 +973,8 @@ impl Parser {
         
         if self.cur.eof() {
             return Err(self.raise_error(
+                ParseErrorKind::InvalidEscape { span: (start_pos, self.cur.i) },
                 "Incomplete escape sequence".to_string(),
-                start_pos,
             ));
         }
         
@@ -453,19 +983,37 @@ impl Parser {
         match ch {
             // Anchors
             'b' => Ok(Node::Anchor(Anchor {
-                at: "WordBoundary".to_string(),
+                at: AnchorKind::WordBoundary,
+                span: Default::default(),
             })),
             'B' => Ok(Node::Anchor(Anchor {
-                at: "NotWordBoundary".to_string(),
+                at: AnchorKind::NotWordBoundary,
+                span: Default::default(),
             })),
             'A' => Ok(Node::Anchor(Anchor {
-                at: "AbsoluteStart".to_string(),
+                at: AnchorKind::AbsoluteStart,
+                span: Default::default(),
             })),
             'Z' => Ok(Node::Anchor(Anchor {
-                at: "EndBeforeFinalNewline".to_string(),
+                at: AnchorKind::EndBeforeFinalNewline,
+                span: Default::default(),
             })),
             'z' => Ok(Node::Anchor(Anchor {
-                at: "AbsoluteEnd".to_string(),
+                at: AnchorKind::AbsoluteEnd,
+                span: Default::default(),
+            })),
+            'G' => Ok(Node::Anchor(Anchor {
+                at: AnchorKind::PreviousMatchEnd,
+                span: Default::default(),
+            })),
+            // GNU word anchors
+            '<' => Ok(Node::Anchor(Anchor {
+                at: AnchorKind::WordStart,
+                span: Default::default(),
+            })),
+            '>' => Ok(Node::Anchor(Anchor {
+                at: AnchorKind::WordEnd,
+                span: Default::default(),
             })),
             
             // Character class escapes
@@ -476,28 +1024,109 @@ impl Parser {
                         escape_type: ch.to_ascii_lowercase().to_string(),
                         property: None,
                     })],
+                    span: Default::default(),
                 }))
             }
             
+            // Named backreference: \k<name>, \k'name' or \k{name}
+            'k' => {
+                let end = if self.cur.match_str("<") {
+                    '>'
+                } else if self.cur.match_str("'") {
+                    '\''
+                } else if self.cur.match_str("{") {
+                    '}'
+                } else {
+                    return Err(self.raise_error(
+                        ParseErrorKind::InvalidEscape { span: (start_pos, self.cur.i) },
+                        "Expected '<', \"'\" or '{' after \\k".to_string(),
+                    ));
+                };
+                let name = self.parse_group_name(&[end])?;
+                if !self.cur.match_str(&end.to_string()) {
+                    return Err(self.raise_error(
+                        ParseErrorKind::InvalidEscape { span: (start_pos, self.cur.i) },
+                        "Unterminated named backref".to_string(),
+                    ));
+                }
+                self.named_backref(name, start_pos)
+            }
+
+            'g' => {
+                if !self.cur.match_str("{") {
+                    return Err(self.raise_error(
+                        ParseErrorKind::InvalidEscape { span: (start_pos, self.cur.i) },
+                        "Expected '{' after \\g".to_string(),
+                    ));
+                }
+                let rest = &self.cur.text[self.cur.i..];
+                let digits = rest.strip_prefix('-').unwrap_or(rest);
+                let len = digits.find(|c: char| !c.is_ascii_digit()).unwrap_or(digits.len());
+                if len == 0 {
+                    let name = self.parse_group_name(&['}'])?;
+                    self.expect_char('}', "Unterminated \\g reference")?;
+                    return self.named_backref(name, start_pos);
+                }
+                let number = &rest[..rest.len() - digits.len() + len];
+                let index = number.parse::<i32>().unwrap_or(i32::MAX);
+                self.cur.i += number.len();
+                self.expect_char('}', "Unterminated \\g reference")?;
+                self.indexed_backref(index, start_pos)
+            }
+
+            // \N{U+XXXX} names a code point; a bare \N is any character
+            // but a newline, even under the `s` flag
+            'N' if self.cur.peek_char(0) == Some('{') => Ok(Node::Literal(Literal {
+                value: self.parse_named_code_point(start_pos)?.to_string(),
+                span: Default::default(),
+            })),
+            'N' => Ok(Node::Dot(Dot { not_newline: true, span: Default::default() })),
+
             // Control escapes
             'n' | 'r' | 't' | 'f' | 'v' => {
                 let value = self.control_escapes.get(&ch).unwrap();
                 Ok(Node::Literal(Literal {
                     value: value.to_string(),
+                    span: Default::default(),
                 }))
             }
             
             // Identity escapes (escape the next character literally)
             _ => Ok(Node::Literal(Literal {
                 value: ch.to_string(),
+                span: Default::default(),
             })),
         }
     }
 
     /// Parse a group: (...)
     fn parse_group(&mut self) -> Result<Node, STRlingParseError> {
-        let _start_pos = self.cur.i;
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(self.raise_error(
+                ParseErrorKind::Syntax { span: (self.cur.i, self.cur.i + 1) },
+                format!("Groups nested more than {} deep", MAX_NESTING_DEPTH),
+            ));
+        }
+        self.depth += 1;
+        // A flag setting changes extended mode for the rest of the enclosing
+        // group; any other group confines its own settings.
+        let extended_mode = self.cur.extended_mode;
+        let result = self.parse_group_inner();
+        if !matches!(result, Ok(Node::SetFlags(_))) {
+            self.cur.extended_mode = extended_mode;
+        }
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_group_inner(&mut self) -> Result<Node, STRlingParseError> {
+        let start = self.cur.i;
         self.cur.take();  // consume '('
+
+        if self.cur.peek_char(0) == Some('*') {
+            // Backtracking control verb: (*SKIP), (*MARK:name)
+            return self.parse_verb();
+        }
         
         // Check for group modifiers
         if let Some('?') = self.cur.peek_char(0) {
@@ -509,28 +1138,33 @@ impl Parser {
                     ':' => {
                         // Non-capturing group: (?:...)
                         self.cur.take();
+                        self.cur.token(TokenKind::GroupOpen, start);
                         let body = self.parse_alt()?;
-                        self.expect_char(')', "Unterminated group")?;
+                        self.close_group("Unterminated group")?;
                         return Ok(Node::Group(Group {
                             capturing: false,
                             name: None,
                             atomic: Some(false),
                             body: Box::new(body),
+                            span: Default::default(),
                         }));
                     }
                     '=' | '!' => {
                         // Lookahead: (?=...) or (?!...)
                         let positive = ch == '=';
                         self.cur.take();
+                        self.cur.token(TokenKind::GroupOpen, start);
                         let body = self.parse_alt()?;
-                        self.expect_char(')', "Unterminated lookahead")?;
+                        self.close_group("Unterminated lookahead")?;
                         if positive {
                             return Ok(Node::Lookahead(LookaroundBody {
                                 body: Box::new(body),
+                                span: Default::default(),
                             }));
                         } else {
                             return Ok(Node::NegativeLookahead(LookaroundBody {
                                 body: Box::new(body),
+                                span: Default::default(),
                             }));
                         }
                     }
@@ -542,50 +1176,86 @@ impl Parser {
                                 // Lookbehind: (?<=...) or (?<!...)
                                 let positive = next_ch == '=';
                                 self.cur.take();
+                                self.cur.token(TokenKind::GroupOpen, start);
                                 let body = self.parse_alt()?;
-                                self.expect_char(')', "Unterminated lookbehind")?;
+                                self.close_group("Unterminated lookbehind")?;
                                 if positive {
                                     return Ok(Node::Lookbehind(LookaroundBody {
                                         body: Box::new(body),
+                                        span: Default::default(),
                                     }));
                                 } else {
                                     return Ok(Node::NegativeLookbehind(LookaroundBody {
                                         body: Box::new(body),
+                                        span: Default::default(),
                                     }));
                                 }
                             } else {
-                                // Named group: (?<name>...)
-                                let name = self.parse_group_name()?;
-                                self.expect_char('>', "Unterminated group name")?;
-                                let body = self.parse_alt()?;
-                                self.expect_char(')', "Unterminated group")?;
-                                self.cap_names.insert(name.clone());
-                                self.cap_count += 1;
-                                return Ok(Node::Group(Group {
-                                    capturing: true,
-                                    name: Some(name),
-                                    atomic: Some(false),
-                                    body: Box::new(body),
-                                }));
+                                // Named group: (?<name>...), or .NET balancing group
+                                return self.parse_named_group_body(start, '>', true);
                             }
                         }
-                    }
+                        return Err(self.raise_error(
+                            ParseErrorKind::UnterminatedGroup { span: (self.cur.i, self.cur.i) },
+                            "Unterminated group name".to_string(),
+                        ));
+                    }
+                    'P' => {
+                        // Python-style named group (?P<name>...) or backreference (?P=name)
+                        let start_pos = self.cur.i - 2;
+                        self.cur.take();
+                        if self.cur.match_str("<") {
+                            return self.parse_named_group_body(start, '>', false);
+                        }
+                        if self.cur.match_str("=") {
+                            let name = self.parse_group_name(&[')'])?;
+                            self.expect_char(')', "Unterminated named backref")?;
+                            self.cur.token(TokenKind::Escape, start);
+                            return self.named_backref(name, start_pos);
+                        }
+                        return Err(self.raise_error(
+                            ParseErrorKind::Syntax { span: (start_pos, self.cur.i) },
+                            "Expected '<' or '=' after (?P".to_string(),
+                        ));
+                    }
+                    '\'' => {
+                        // .NET-style named group: (?'name'...)
+                        self.cur.take();
+                        return self.parse_named_group_body(start, '\'', true);
+                    }
+                    '|' => {
+                        // Branch reset group: (?|...)
+                        self.cur.take();
+                        self.cur.token(TokenKind::GroupOpen, start);
+                        let branches = self.parse_branches(true)?;
+                        self.close_group("Unterminated branch reset group")?;
+                        return Ok(Node::BranchReset(BranchReset { branches, span: Default::default() }));
+                    }
                     '>' => {
                         // Atomic group: (?>...)
                         self.cur.take();
+                        self.cur.token(TokenKind::GroupOpen, start);
                         let body = self.parse_alt()?;
-                        self.expect_char(')', "Unterminated atomic group")?;
+                        self.close_group("Unterminated atomic group")?;
                         return Ok(Node::Group(Group {
                             capturing: false,
                             name: None,
                             atomic: Some(true),
                             body: Box::new(body),
+                            span: Default::default(),
                         }));
                     }
+                    'i' | 'm' | 's' | 'x' | '-' => {
+                        // Inline flag setting: (?i), (?-i), (?s-i)
+                        return self.parse_set_flags();
+                    }
                     _ => {
                         return Err(self.raise_error(
+                            ParseErrorKind::UnexpectedChar {
+                                found: ch,
+                                span: (self.cur.i - 1, self.cur.i + ch.len_utf8()),
+                            },
                             format!("Unknown group modifier: ?{}", ch),
-                            self.cur.i - 1,
                         ));
                     }
                 }
@@ -593,14 +1263,206 @@ impl Parser {
         }
         
         // Regular capturing group
+        self.cur.token(TokenKind::GroupOpen, start);
         self.cap_count += 1;
         let body = self.parse_alt()?;
-        self.expect_char(')', "Unterminated group")?;
+        self.close_group("Unterminated group")?;
         Ok(Node::Group(Group {
             capturing: true,
             name: None,
             atomic: Some(false),
             body: Box::new(body),
+            span: Default::default(),
+        }))
+    }
+
+    /// Parse the remainder of a backtracking control verb once its `(` is
+    /// consumed
+    fn parse_verb(&mut self) -> Result<Node, STRlingParseError> {
+        let start = self.cur.i - 1;
+        self.cur.take();  // consume '*'
+        let rest = &self.cur.text[self.cur.i..];
+        let Some(end) = rest.find(')') else {
+            return Err(self.raise_error(
+                ParseErrorKind::UnterminatedGroup { span: (start, self.cur.text.len()) },
+                "Unterminated verb".to_string(),
+            ));
+        };
+        let (name, arg) = match rest[..end].split_once(':') {
+            Some((name, arg)) => (name.to_string(), Some(arg.to_string())),
+            None => (rest[..end].to_string(), None),
+        };
+        let arg_required = matches!(name.as_str(), "MARK" | "");
+        let known = arg_required || VERBS.contains(&name.as_str());
+        if !known || (arg_required && arg.as_deref().map_or(true, str::is_empty)) {
+            let message = if known {
+                format!("Verb (*{}) requires a name", name)
+            } else {
+                format!("Unknown verb: (*{})", name)
+            };
+            return Err(self.raise_error(
+                ParseErrorKind::Syntax { span: (start, self.cur.i + end + 1) },
+                message,
+            ));
+        }
+        self.cur.i += end + 1;
+        self.cur.token(TokenKind::Escape, start);
+        Ok(Node::Verb(Verb { name, arg, span: Default::default() }))
+    }
+
+    /// Parse the remainder of an inline flag setting once its `(?` is
+    /// consumed
+    ///
+    /// The setting lasts until the end of the enclosing group; turning `x`
+    /// on or off changes how the rest of that group is read. The scoped
+    /// form `(?i-s:...)` parses as a non-capturing group whose body starts
+    /// with the setting, which confines it the same way.
+    fn parse_set_flags(&mut self) -> Result<Node, STRlingParseError> {
+        let start = self.cur.i - 2;
+        let mut on = Flags::none();
+        let mut off = Flags::none();
+        let mut negated = false;
+        loop {
+            let Some(ch) = self.cur.peek_char(0) else {
+                return Err(self.raise_error(
+                    ParseErrorKind::UnterminatedGroup { span: (start, self.cur.i) },
+                    "Unterminated flag setting".to_string(),
+                ));
+            };
+            let flags = if negated { &mut off } else { &mut on };
+            match ch {
+                'i' => flags.ignore_case = true,
+                'm' => flags.multiline = true,
+                's' => flags.dot_all = true,
+                'x' => flags.extended = true,
+                '-' if !negated => negated = true,
+                ')' | ':' => break,
+                _ => {
+                    return Err(self.raise_error(
+                        ParseErrorKind::UnexpectedChar {
+                            found: ch,
+                            span: (self.cur.i, self.cur.i + ch.len_utf8()),
+                        },
+                        format!("Invalid flag in flag setting: {}", ch),
+                    ));
+                }
+            }
+            self.cur.take();
+        }
+        if negated && off == Flags::none() {
+            return Err(self.raise_error(
+                ParseErrorKind::Syntax { span: (start, self.cur.i + 1) },
+                "Expected flags after '-' in flag setting".to_string(),
+            ));
+        }
+        let scoped = self.cur.take() == Some(':');  // consume ')' or ':'
+        if on.extended {
+            self.cur.extended_mode = true;
+        } else if off.extended {
+            self.cur.extended_mode = false;
+        }
+        if !scoped {
+            self.cur.token(TokenKind::FlagSet, start);
+            return Ok(Node::SetFlags(SetFlags { on, off, span: Default::default() }));
+        }
+        self.cur.token(TokenKind::GroupOpen, start);
+        let flags_span = (start, self.cur.i);
+        let body = self.parse_alt()?;
+        let end = self.cur.i;
+        self.close_group("Unterminated group")?;
+        let parts = vec![Node::SetFlags(SetFlags { on, off, span: SourceSpan(Some(flags_span)) }), body];
+        Ok(Node::Group(Group {
+            capturing: false,
+            name: None,
+            atomic: Some(false),
+            body: Box::new(Node::Sequence(Sequence { parts, span: SourceSpan(Some((flags_span.0, end))) })),
+            span: Default::default(),
+        }))
+    }
+
+    /// Parse the remainder of a named group once its opening `(?<`, `(?P<`
+    /// or `(?'` is consumed; `end` closes the name
+    ///
+    /// With `balancing`, the name may also be a .NET balancing group's
+    /// `push-pop` pair, e.g. `close-open` or `-open`. `start` is where the
+    /// group's `(` is.
+    fn parse_named_group_body(&mut self, start: usize, end: char, balancing: bool) -> Result<Node, STRlingParseError> {
+        if balancing && self.cur.match_str("-") {
+            return self.parse_balancing_group_body(start, None, end);
+        }
+        let ends = [end, '-'];
+        let name = self.parse_group_name(if balancing { &ends } else { &ends[..1] })?;
+        if balancing && self.cur.match_str("-") {
+            return self.parse_balancing_group_body(start, Some(name), end);
+        }
+        self.expect_char(end, "Unterminated group name")?;
+        self.cur.token(TokenKind::GroupOpen, start);
+        self.cap_count += 1;
+        let body = self.parse_alt()?;
+        self.close_group("Unterminated group")?;
+        self.cap_names.insert(name.clone());
+        Ok(Node::Group(Group {
+            capturing: true,
+            name: Some(name),
+            atomic: Some(false),
+            body: Box::new(body),
+            span: Default::default(),
+        }))
+    }
+
+    /// Parse the rest of a .NET balancing group once its opening up to the
+    /// `-` before the popped name is consumed
+    fn parse_balancing_group_body(
+        &mut self,
+        start: usize,
+        push: Option<String>,
+        end: char,
+    ) -> Result<Node, STRlingParseError> {
+        let pop = self.parse_group_name(&[end])?;
+        self.expect_char(end, "Unterminated group name")?;
+        self.cur.token(TokenKind::GroupOpen, start);
+        let body = self.parse_alt()?;
+        self.close_group("Unterminated group")?;
+        Ok(Node::BalancingGroup(BalancingGroup {
+            push,
+            pop,
+            body: Box::new(body),
+            span: Default::default(),
+        }))
+    }
+
+    /// Build a named backreference, rejecting names no earlier group defined
+    fn named_backref(&self, name: String, start_pos: usize) -> Result<Node, STRlingParseError> {
+        if !self.cap_names.contains(&name) {
+            return Err(self.raise_error(
+                ParseErrorKind::Syntax { span: (start_pos, self.cur.i) },
+                format!("Backreference to undefined group <{}>", name),
+            ));
+        }
+        Ok(Node::Backreference(Backreference {
+            by_index: None,
+            by_name: Some(name),
+            span: Default::default(),
+        }))
+    }
+
+    /// Build a numbered backreference from `\g{n}`, rejecting numbers no
+    /// earlier group has
+    ///
+    /// A negative `index` counts back from the reference (`\g{-1}` is the
+    /// most recently opened group) and is left for the compiler to resolve.
+    fn indexed_backref(&self, index: i32, start_pos: usize) -> Result<Node, STRlingParseError> {
+        let opened = i32::try_from(self.cap_count).unwrap_or(i32::MAX);
+        if index == 0 || index > opened || index < -opened {
+            return Err(self.raise_error(
+                ParseErrorKind::Syntax { span: (start_pos, self.cur.i) },
+                format!("Backreference to undefined group \\g{{{}}}", index),
+            ));
+        }
+        Ok(Node::Backreference(Backreference {
+            by_index: Some(index),
+            by_name: None,
+            span: Default::default(),
         }))
     }
 
@@ -617,49 +1479,279 @@ impl Parser {
         } else {
             false
         };
+        self.cur.token(TokenKind::ClassOpen, start_pos);
         
         let mut items = Vec::new();
+        // Where the last item began, for range error spans
+        let mut item_start = self.cur.i;
+        // A `]` right after the opener is read as `leading_bracket` says
+        if self.cur.peek_char(0) == Some(']') {
+            self.cur.take();
+            match self.leading_bracket {
+                LeadingBracket::Literal => {
+                    self.cur.token(TokenKind::Literal, item_start);
+                    items.push(ClassItem::Char(ClassLiteral { ch: "]".to_string() }));
+                }
+                LeadingBracket::Close => {
+                    self.cur.token(TokenKind::ClassClose, item_start);
+                    self.cur.in_class -= 1;
+                    return Ok(Node::CharacterClass(CharacterClass { negated, items, span: Default::default() }));
+                }
+            }
+        }
+        // Operands of a `&&` intersection finished so far, and whether the
+        // current one is already among them (a nested class is)
+        let mut operands: Vec<CharacterClass> = Vec::new();
+        let mut operand_done = false;
         
         // Parse class items
         loop {
             if self.cur.eof() {
                 return Err(self.raise_error(
+                    ParseErrorKind::UnterminatedClass { span: (start_pos, self.cur.i) },
                     "Unterminated character class".to_string(),
-                    start_pos,
                 ));
             }
             
             if let Some(']') = self.cur.peek_char(0) {
                 self.cur.take();
+                self.cur.token(TokenKind::ClassClose, self.cur.i - 1);
                 break;
             }
+
+            // `\Q...\E` quotes its contents as plain members, so `-` and `]`
+            // in it neither form ranges nor close the class
+            let quote_start = self.cur.i;
+            if self.cur.match_str("\\Q") {
+                while !self.cur.eof() && !self.cur.match_str("\\E") {
+                    let ch = self.cur.take().expect("checked for end of input");
+                    items.push(ClassItem::Char(ClassLiteral { ch: ch.to_string() }));
+                }
+                self.cur.token(TokenKind::Literal, quote_start);
+                item_start = self.cur.i;
+                continue;
+            }
+
+            if self.cur.match_str("&&") {
+                self.cur.token(TokenKind::ClassIntersection, self.cur.i - 2);
+                if !operand_done {
+                    operands.push(self.intersection_operand(std::mem::take(&mut items))?);
+                }
+                operand_done = false;
+                if self.cur.peek_char(0) == Some('[') {
+                    operands.push(self.parse_nested_class()?);
+                    operand_done = true;
+                }
+                item_start = self.cur.i;
+                continue;
+            }
+            if operand_done {
+                return Err(self.raise_error(
+                    ParseErrorKind::Syntax { span: (self.cur.i, self.cur.i) },
+                    "Expected '&&' or ']' after nested class".to_string(),
+                ));
+            }
             
-            // Parse one class item
-            // TODO: Implement full class item parsing (ranges, escapes, etc.)
-            let ch = self.cur.take().unwrap();
-            items.push(ClassItem::Char(ClassLiteral {
-                ch: ch.to_string(),
-            }));
+            // '-' forms a range only after a literal and before anything but ']'
+            let range_start = matches!(items.last(), Some(ClassItem::Char(_)))
+                && self.cur.peek_char(0) == Some('-')
+                && self.cur.peek_char(1).is_some_and(|c| c != ']');
+            if !range_start {
+                item_start = self.cur.i;
+                items.push(self.parse_class_item()?);
+                continue;
+            }
+            let dash = self.cur.i;
+            self.cur.take();  // consume '-'
+            self.cur.token(TokenKind::ClassRange, dash);
+            match self.parse_class_item()? {
+                ClassItem::Char(end) => {
+                    let Some(ClassItem::Char(start)) = items.pop() else {
+                        unreachable!("range start checked above");
+                    };
+                    if start.ch.chars().next() > end.ch.chars().next() {
+                        return Err(self.raise_error(
+                            ParseErrorKind::InvalidRange { span: (item_start, self.cur.i) },
+                            format!("Invalid character range [{}-{}]", start.ch, end.ch),
+                        ));
+                    }
+                    items.push(ClassItem::Range(ClassRange {
+                        from_ch: start.ch,
+                        to_ch: end.ch,
+                    }));
+                }
+                // A range cannot end in a shorthand such as `\d`; keep the
+                // '-' as a literal instead.
+                end => {
+                    self.cur.retag(dash, TokenKind::Literal);
+                    items.push(ClassItem::Char(ClassLiteral { ch: "-".to_string() }));
+                    items.push(end);
+                }
+            }
         }
         
         self.cur.in_class -= 1;
+
+        if !operands.is_empty() {
+            if !operand_done {
+                operands.push(self.intersection_operand(items)?);
+            }
+            items = vec![ClassItem::Intersection(ClassIntersection { sets: operands })];
+        }
         
         if items.is_empty() {
             return Err(self.raise_error(
+                ParseErrorKind::Syntax { span: (start_pos, self.cur.i) },
                 "Empty character class".to_string(),
-                start_pos,
             ));
         }
         
-        Ok(Node::CharacterClass(CharacterClass { negated, items }))
+        Ok(Node::CharacterClass(CharacterClass { negated, items, span: Default::default() }))
     }
 
-    /// Parse a group name for named groups
-    fn parse_group_name(&mut self) -> Result<String, STRlingParseError> {
+    /// The members of one side of a `&&` as a class of their own
+    fn intersection_operand(&self, items: Vec<ClassItem>) -> Result<CharacterClass, STRlingParseError> {
+        if items.is_empty() {
+            return Err(self.raise_error(
+                ParseErrorKind::Syntax { span: (self.cur.i, self.cur.i) },
+                "Empty operand in class intersection".to_string(),
+            ));
+        }
+        Ok(CharacterClass { negated: false, items, span: Default::default() })
+    }
+
+    /// Parse a class nested in an intersection, e.g. `[^aeiou]` in
+    /// `[a-z&&[^aeiou]]`
+    fn parse_nested_class(&mut self) -> Result<CharacterClass, STRlingParseError> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(self.raise_error(
+                ParseErrorKind::Syntax { span: (self.cur.i, self.cur.i + 1) },
+                format!("Classes nested more than {} deep", MAX_NESTING_DEPTH),
+            ));
+        }
+        self.depth += 1;
+        let result = self.parse_char_class();
+        self.depth -= 1;
+        match result? {
+            Node::CharacterClass(cc) => Ok(cc),
+            _ => unreachable!("parse_char_class returns a class"),
+        }
+    }
+
+    /// Parse a single class member: a literal character or an escape
+    fn parse_class_item(&mut self) -> Result<ClassItem, STRlingParseError> {
+        let start = self.cur.i;
+        let item = if let Some('\\') = self.cur.peek_char(0) {
+            self.parse_class_escape()?
+        } else {
+            let ch = self.cur.take().expect("caller checked for end of input");
+            ClassItem::Char(ClassLiteral {
+                ch: ch.to_string(),
+            })
+        };
+        let kind = match item {
+            ClassItem::Char(_) => TokenKind::Literal,
+            _ => TokenKind::Escape,
+        };
+        self.cur.token(kind, start);
+        Ok(item)
+    }
+
+    /// Parse an escape inside a character class
+    ///
+    /// Class escapes differ from top-level ones: there are no anchors, so
+    /// `\b` means backspace (U+0008) rather than a word boundary.
+    fn parse_class_escape(&mut self) -> Result<ClassItem, STRlingParseError> {
+        let start_pos = self.cur.i;
+        self.cur.take();  // consume '\'
+
+        let Some(ch) = self.cur.take() else {
+            return Err(self.raise_error(
+                ParseErrorKind::InvalidEscape { span: (start_pos, self.cur.i) },
+                "Incomplete escape sequence".to_string(),
+            ));
+        };
+
+        let literal = |ch: char| ClassItem::Char(ClassLiteral { ch: ch.to_string() });
+        match ch {
+            'd' | 'D' | 'w' | 'W' | 's' | 'S' => Ok(ClassItem::Esc(ClassEscape {
+                escape_type: ch.to_string(),
+                property: None,
+            })),
+            'p' | 'P' => {
+                if !self.cur.match_str("{") {
+                    return Err(self.raise_error(
+                        ParseErrorKind::InvalidEscape { span: (start_pos, self.cur.i) },
+                        "Expected { after \\p/\\P".to_string(),
+                    ));
+                }
+                let close = self.cur.text[self.cur.i..].find('}');
+                let Some(len) = close else {
+                    return Err(self.raise_error(
+                        ParseErrorKind::InvalidEscape { span: (start_pos, self.cur.text.len()) },
+                        "Unterminated \\p{...}".to_string(),
+                    ));
+                };
+                let property = self.cur.text[self.cur.i..self.cur.i + len].to_string();
+                self.cur.i += len + 1;
+                Ok(ClassItem::Esc(ClassEscape {
+                    escape_type: ch.to_string(),
+                    property: Some(property),
+                }))
+            }
+            'b' => Ok(literal('\u{0008}')),
+            'N' if self.cur.peek_char(0) == Some('{') => {
+                Ok(literal(self.parse_named_code_point(start_pos)?))
+            }
+            'N' => Err(self.raise_error(
+                ParseErrorKind::InvalidEscape { span: (start_pos, self.cur.i) },
+                "\\N is not allowed in a character class".to_string(),
+            )),
+            'n' | 'r' | 't' | 'f' | 'v' => Ok(literal(*self.control_escapes.get(&ch).unwrap())),
+            // Identity escapes such as `\]`, `\\` and `\-`
+            _ => Ok(literal(ch)),
+        }
+    }
+
+    /// Parse the `{U+XXXX}` of a `\N{U+XXXX}` escape into its code point
+    ///
+    /// Character names such as `\N{LATIN SMALL LETTER A}` are rejected:
+    /// there is no name table to look them up in.
+    fn parse_named_code_point(&mut self, start_pos: usize) -> Result<char, STRlingParseError> {
+        self.cur.take();  // consume '{'
+        let rest = &self.cur.text[self.cur.i..];
+        let Some(len) = rest.find('}') else {
+            return Err(self.raise_error(
+                ParseErrorKind::InvalidEscape { span: (start_pos, self.cur.text.len()) },
+                "Unterminated \\N{...}".to_string(),
+            ));
+        };
+        let name = rest[..len].to_string();
+        self.cur.i += len + 1;
+        let Some(hex) = name.strip_prefix("U+") else {
+            return Err(self.raise_error(
+                ParseErrorKind::InvalidEscape { span: (start_pos, self.cur.i) },
+                format!("Unsupported character name \\N{{{}}}, use \\N{{U+XXXX}}", name),
+            ));
+        };
+        let code_point = Some(hex)
+            .filter(|hex| !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()))
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32);
+        code_point.ok_or_else(|| {
+            self.raise_error(
+                ParseErrorKind::InvalidEscape { span: (start_pos, self.cur.i) },
+                format!("Invalid code point \\N{{{}}}", name),
+            )
+        })
+    }
+
+    /// Parse a group name, stopping before any of `ends`
+    fn parse_group_name(&mut self, ends: &[char]) -> Result<String, STRlingParseError> {
         let mut name = String::new();
         
         while let Some(ch) = self.cur.peek_char(0) {
-            if ch == '>' {
+            if ends.contains(&ch) {
                 break;
             }
             if ch.is_alphanumeric() || ch == '_' {
@@ -667,40 +1759,89 @@ impl Parser {
                 self.cur.take();
             } else {
                 return Err(self.raise_error(
+                    ParseErrorKind::UnexpectedChar {
+                        found: ch,
+                        span: (self.cur.i, self.cur.i + ch.len_utf8()),
+                    },
                     format!("Invalid character in group name: {}", ch),
-                    self.cur.i,
                 ));
             }
         }
         
         if name.is_empty() {
             return Err(self.raise_error(
+                ParseErrorKind::Syntax { span: (self.cur.i, self.cur.i) },
                 "Empty group name".to_string(),
-                self.cur.i,
             ));
         }
         
         Ok(name)
     }
 
-    /// Expect a specific character at the current position
+    /// Expect a group-closing character at the current position
     fn expect_char(&mut self, expected: char, error_msg: &str) -> Result<(), STRlingParseError> {
-        if let Some(ch) = self.cur.take() {
-            if ch == expected {
-                Ok(())
-            } else {
-                Err(self.raise_error(
-                    error_msg.to_string(),
-                    self.cur.i - 1,
-                ))
-            }
-        } else {
-            Err(self.raise_error(
+        let pos = self.cur.i;
+        match self.cur.take() {
+            Some(ch) if ch == expected => Ok(()),
+            _ => Err(self.raise_error(
+                ParseErrorKind::UnterminatedGroup { span: (pos, pos) },
                 error_msg.to_string(),
-                self.cur.i,
-            ))
+            )),
         }
     }
+
+    /// Expect the `)` that closes a group
+    fn close_group(&mut self, error_msg: &str) -> Result<(), STRlingParseError> {
+        self.expect_char(')', error_msg)?;
+        self.cur.token(TokenKind::GroupClose, self.cur.i - 1);
+        Ok(())
+    }
+}
+
+/// Give `node` and everything below it the span `span`
+fn respan(node: &mut Node, span: Span) {
+    node.set_span(span);
+    match node {
+        Node::Alternation(Alternation { branches: nodes, .. })
+        | Node::Sequence(Sequence { parts: nodes, .. })
+        | Node::BranchReset(BranchReset { branches: nodes, .. }) => {
+            nodes.iter_mut().for_each(|n| respan(n, span));
+        }
+        Node::Quantifier(quant) => respan(&mut quant.target.child, span),
+        Node::Group(group) => respan(&mut group.body, span),
+        Node::BalancingGroup(group) => respan(&mut group.body, span),
+        Node::Lookahead(look)
+        | Node::NegativeLookahead(look)
+        | Node::Lookbehind(look)
+        | Node::NegativeLookbehind(look) => respan(&mut look.body, span),
+        _ => {}
+    }
+}
+
+/// Whether `name` can name a `%define`: letters, digits and `_`, not
+/// starting with a digit
+fn is_identifier(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Whether `lines`, joined as pattern source, end inside a character class
+fn ends_in_open_class(lines: &[&str]) -> bool {
+    let mut in_class = false;
+    for line in lines {
+        let mut chars = line.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' => {
+                    chars.next();
+                }
+                '[' => in_class = true,
+                ']' => in_class = false,
+                _ => {}
+            }
+        }
+    }
+    in_class
 }
 
 /// Parse a STRling pattern into an AST
@@ -721,6 +1862,121 @@ pub fn parse(text: &str) -> Result<(Flags, Node), STRlingParseError> {
     parser.parse()
 }
 
+/// Parse a pattern in the `/pattern/flags` form used by JavaScript and sed
+///
+/// The letters after the closing slash are JavaScript's `g`, `i`, `m`, `s`,
+/// `u` and `y`, and set the flags of the same letter in `%flags`. The text
+/// between the slashes is all pattern: `%flags` and `#` lines there are
+/// not directives or comments.
+/// Outside a character class a `/` in the pattern must be escaped as `\/`.
+/// Error positions refer to `text`, delimiters included.
+///
+/// # Errors
+///
+/// Returns STRlingParseError if the delimiters or flags are malformed, or
+/// if the pattern between them is invalid
+pub fn parse_delimited(text: &str) -> Result<(Flags, Node), STRlingParseError> {
+    let error = |message: &str, kind: ParseErrorKind| {
+        STRlingParseError::new(message.to_string(), kind.span().0, text.to_string(), None).with_kind(kind)
+    };
+    let Some(body) = text.strip_prefix('/') else {
+        return Err(error("Expected '/' to open a delimited pattern", ParseErrorKind::Syntax { span: (0, 0) }));
+    };
+
+    let (mut escaped, mut in_class) = (false, false);
+    let end = body.char_indices().find_map(|(i, ch)| {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '/' if !in_class => return Some(i),
+            _ => {}
+        }
+        None
+    });
+    let Some(end) = end else {
+        return Err(error(
+            "Unterminated delimited pattern",
+            ParseErrorKind::Syntax { span: (0, text.len()) },
+        ));
+    };
+
+    let mut flags = Flags::default();
+    for (i, ch) in body[end + 1..].char_indices() {
+        if !matches!(ch, 'g' | 'i' | 'm' | 's' | 'u' | 'y') || !flags.set_letter(ch) {
+            let pos = end + 2 + i;
+            return Err(error(
+                &format!("Unknown flag: {}", ch),
+                ParseErrorKind::InvalidFlag { span: (pos, pos + ch.len_utf8()) },
+            ));
+        }
+    }
+    Parser::verbatim(body[..end].to_string())
+        .with_default_flags(flags)
+        .parse()
+        .map_err(|e| e.within(text, 1))
+}
+
+/// Split the text after `%flags` into its flag words and the pattern text
+/// after them
+///
+/// Flag words are runs of flag letters separated by spaces, tabs or commas,
+/// optionally bracketed as in `%flags [i,m]`. The first other word starts
+/// the pattern text, so `%flags i abc` sets only `i`.
+fn split_flag_words(after: &str) -> (&str, &str) {
+    let is_separator = |ch: char| matches!(ch, ' ' | '\t' | ',');
+    let mut i = 0;
+    while i < after.len() {
+        let rest = &after[i..];
+        let len = rest.find(is_separator).unwrap_or(rest.len());
+        if len == 0 {
+            i += 1;
+            continue;
+        }
+        let letters = rest[..len].trim_start_matches('[').trim_end_matches(']');
+        if !letters.chars().all(|ch| Flags::none().set_letter(ch)) {
+            return (&after[..i], &after[i..]);
+        }
+        i += len;
+    }
+    (after, "")
+}
+
+/// Escape `literal` so that it parses as a pattern matching exactly itself
+///
+/// Backslashes every character with a meaning of its own: the regex
+/// metacharacters, plus whitespace and `#`, which free-spacing mode skips.
+/// Control characters become `\n`-style escapes so the result is a single
+/// line, and a leading `%` or `/` is escaped so it cannot read as a directive
+/// or comment line.
+pub fn escape_pattern(literal: &str) -> String {
+    let mut out = String::with_capacity(literal.len());
+    for (i, ch) in literal.char_indices() {
+        match ch {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{000C}' => out.push_str("\\f"),
+            '\u{000B}' => out.push_str("\\v"),
+            '.' | '^' | '$' | '*' | '+' | '?' | '(' | ')' | '[' | ']' | '{' | '}' | '|' | '\\' | '#' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            '%' | '/' if i == 0 => {
+                out.push('\\');
+                out.push(ch);
+            }
+            _ if ch.is_whitespace() => {
+                out.push('\\');
+                out.push(ch);
+            }
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -745,6 +2001,626 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_parse_ascii_flag() {
+        let (flags, _) = parse("%flags a\n\\w").unwrap();
+        assert!(flags.ascii);
+        // Unicode mode stays on by default; ASCII mode takes precedence.
+        assert!(flags.unicode);
+    }
+
+    #[test]
+    fn test_comment_and_blank_lines_ignored() {
+        let plain = parse("%flags i\na\nb").unwrap();
+        assert_eq!(parse("# leading comment\n%flags i\n\na\n").unwrap().0, plain.0);
+        assert_eq!(parse("// about this pattern\nab").unwrap(), parse("ab").unwrap());
+        assert_eq!(parse("%flags i\na\n  # the b part\n\n// end\nb").unwrap(), plain);
+        // An escaped `#` starts a pattern line, not a comment.
+        let (_, ast) = parse("\\#x").unwrap();
+        assert!(matches!(ast, Node::Sequence(ref seq) if seq.parts[0] == Node::Literal(Literal { value: "#".to_string(), span: Default::default() })));
+    }
+
+    #[test]
+    fn test_comment_lines_inside_open_class_are_kept() {
+        let (_, ast) = parse("[a\n# b]").unwrap();
+        let Node::CharacterClass(cc) = ast else {
+            panic!("Expected CharacterClass");
+        };
+        assert_eq!(cc.items.len(), 5);
+    }
+
+    #[test]
+    fn test_define_references_expand() {
+        let compile = |src: &str| crate::core::compiler::Compiler::new().compile(&parse(src).unwrap().1);
+        assert_eq!(compile("%define g = \\d{3}\n$g-$g"), compile("\\d{3}-\\d{3}"));
+        assert_eq!(compile("%define ab = a|b\n%define x=$ab+\n$x$"), compile("(?:a|b)+$"));
+        assert_eq!(compile("%define digits = \\d\n$dig$"), compile("$dig$"));
+    }
+
+    #[test]
+    fn test_define_errors() {
+        let err = parse("%define a = x$b\n%define b = $a\n($a)").unwrap_err();
+        assert_eq!(err.message, "In definition $a: Cyclic definition: $a -> $b -> $a");
+        assert_eq!(err.kind, ParseErrorKind::Syntax { span: (1, 3) });
+
+        let err = parse("%define g = (x\n$g").unwrap_err();
+        assert_eq!(err.message, "In definition $g: Unterminated group");
+    }
+
+    #[test]
+    fn test_parse_python_named_group_and_backref() {
+        let (_, ast) = parse("(?P<x>\\w+)(?P=x)").unwrap();
+        match ast {
+            Node::Sequence(seq) => {
+                assert!(matches!(&seq.parts[0], Node::Group(g) if g.name.as_deref() == Some("x")));
+                assert_eq!(
+                    seq.parts[1],
+                    Node::Backreference(Backreference {
+                        by_index: None,
+                        by_name: Some("x".to_string()),
+                        span: Default::default(),
+                    })
+                );
+            }
+            _ => panic!("Expected Sequence node"),
+        }
+        assert_eq!(parse("(?<x>a)\\k<x>").unwrap().1, parse("(?P<x>a)(?P=x)").unwrap().1);
+    }
+
+    #[test]
+    fn test_named_group_spellings() {
+        for src in ["(?<name>a)", "(?P<name>a)", "(?'name'a)"] {
+            assert_eq!(
+                parse(src).unwrap().1,
+                Node::Group(Group {
+                    capturing: true,
+                    name: Some("name".to_string()),
+                    atomic: Some(false),
+                    body: Box::new(Node::Literal(Literal { value: "a".to_string(), span: Default::default() })),
+                    span: Default::default(),
+                }),
+                "{}",
+                src
+            );
+        }
+        assert_eq!(parse("(?'name>a)").unwrap_err().message, "Invalid character in group name: >");
+        assert_eq!(parse("(?'name'a)\\k'name").unwrap_err().message, "Unterminated named backref");
+        assert_eq!(parse("(?'name").unwrap_err().message, "Unterminated group name");
+    }
+
+    #[test]
+    fn test_balancing_groups() {
+        let body = Box::new(Node::Literal(Literal { value: ")".to_string(), span: Default::default() }));
+        for src in ["(?<close-open>\\))", "(?'close-open'\\))"] {
+            assert_eq!(
+                parse(src).unwrap().1,
+                Node::BalancingGroup(BalancingGroup {
+                    push: Some("close".to_string()),
+                    pop: "open".to_string(),
+                    body: body.clone(),
+                    span: Default::default(),
+                }),
+                "{}",
+                src
+            );
+        }
+        assert!(matches!(parse("(?<-open>\\))").unwrap().1, Node::BalancingGroup(g) if g.push.is_none() && g.pop == "open"));
+        assert_eq!(parse("(?P<close-open>a)").unwrap_err().message, "Invalid character in group name: -");
+        assert_eq!(parse("(?<close->a)").unwrap_err().message, "Empty group name");
+    }
+
+    #[test]
+    fn test_quoted_named_backref() {
+        let (_, ast) = parse("(?'tag'\\w+)\\k'tag'").unwrap();
+        assert_eq!(ast, parse("(?<tag>\\w+)\\k<tag>").unwrap().1);
+        let Node::Sequence(seq) = ast else { panic!("Expected Sequence node") };
+        assert!(matches!(&seq.parts[0], Node::Group(g) if g.name.as_deref() == Some("tag")));
+        assert!(matches!(&seq.parts[1], Node::Backreference(b) if b.by_name.as_deref() == Some("tag")));
+    }
+
+    #[test]
+    fn test_g_backrefs() {
+        let backref = |src: &str| match parse(src).unwrap().1 {
+            Node::Sequence(seq) => seq.parts.last().cloned().unwrap(),
+            other => panic!("Expected Sequence node, got {:?}", other),
+        };
+        assert!(matches!(backref("(a)(b)\\g{-1}"), Node::Backreference(b) if b.by_index == Some(-1)));
+        assert!(matches!(backref("(a)(b)\\g{1}"), Node::Backreference(b) if b.by_index == Some(1)));
+        assert!(matches!(backref("(?<x>a)\\g{x}"), Node::Backreference(b) if b.by_name.as_deref() == Some("x")));
+
+        for (src, message) in [
+            ("(a)\\g{0}", "Backreference to undefined group \\g{0}"),
+            ("(a)\\g{2}", "Backreference to undefined group \\g{2}"),
+            ("(a)\\g{-2}", "Backreference to undefined group \\g{-2}"),
+            ("(a)\\g{y}", "Backreference to undefined group <y>"),
+            ("(a)\\g1", "Expected '{' after \\g"),
+            ("(a)\\g{1", "Unterminated \\g reference"),
+        ] {
+            assert_eq!(parse(src).unwrap_err().message, message, "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn test_named_backref_spellings() {
+        let expected = parse("(?<tag>a)\\k<tag>").unwrap().1;
+        for src in ["(?<tag>a)\\k'tag'", "(?<tag>a)\\k{tag}", "(?<tag>a)(?P=tag)", "(?<tag>a)\\g{tag}"] {
+            let (flags, ast) = parse(src).unwrap();
+            assert_eq!(ast, expected, "{:?}", src);
+            let ir = crate::core::compiler::Compiler::new().compile(&ast);
+            assert_eq!(crate::emitters::pcre2::PCRE2Emitter::new(flags).emit(&ir), "(?<tag>a)\\k<tag>");
+        }
+        assert_eq!(parse("(?<tag>a)\\k{tag").unwrap_err().message, "Unterminated named backref");
+        assert_eq!(parse("(?<tag>a)\\kx").unwrap_err().message, "Expected '<', \"'\" or '{' after \\k");
+    }
+
+    #[test]
+    fn test_n_escapes() {
+        let emit = |src: &str| {
+            let (flags, ast) = parse(src).unwrap();
+            let ir = crate::core::compiler::Compiler::new().with_flags(flags.clone()).compile(&ast);
+            crate::emitters::pcre2::PCRE2Emitter::new(flags).emit(&ir)
+        };
+        assert!(matches!(parse("\\N").unwrap().1, Node::Dot(Dot { not_newline: true, .. })));
+        assert_eq!(emit("%flags s\n\\N."), "\\N.");
+        assert_eq!(emit("a\\N{U+0042}[\\N{U+63}]"), "aB[c]");
+        assert_eq!(emit("\\N{U+E9}"), "é");
+
+        for (src, message) in [
+            ("\\N{U+}", "Invalid code point \\N{U+}"),
+            ("\\N{U+D800}", "Invalid code point \\N{U+D800}"),
+            ("\\N{U++41}", "Invalid code point \\N{U++41}"),
+            ("\\N{U+41", "Unterminated \\N{...}"),
+            ("\\N{LATIN SMALL LETTER A}", "Unsupported character name \\N{LATIN SMALL LETTER A}, use \\N{U+XXXX}"),
+            ("[\\N]", "\\N is not allowed in a character class"),
+        ] {
+            assert_eq!(parse(src).unwrap_err().message, message, "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn test_leading_bracket() {
+        let class = |negated, members: &[&str]| Node::CharacterClass(CharacterClass {
+            negated,
+            items: members.iter().map(|ch| ClassItem::Char(ClassLiteral { ch: ch.to_string() })).collect(),
+            span: Default::default(),
+        });
+        assert_eq!(parse("[]a]").unwrap().1, class(false, &["]", "a"]));
+        assert_eq!(parse("[^]a]").unwrap().1, class(true, &["]", "a"]));
+        assert_eq!(parse("[]-a]").unwrap().1, parse("[\\]-a]").unwrap().1);
+        for src in ["[]", "[^]"] {
+            assert_eq!(parse(src).unwrap_err().message, "Unterminated character class", "{:?}", src);
+        }
+
+        let close = |src: &str| {
+            let mut parser = Parser::new(src.to_string()).with_leading_bracket(LeadingBracket::Close);
+            parser.parse().unwrap().1
+        };
+        let literal = |value: &str| Node::Literal(Literal { value: value.to_string(), span: Default::default() });
+        assert_eq!(close("[]"), class(false, &[]));
+        assert_eq!(close("[^]"), class(true, &[]));
+        assert_eq!(close("[]a]"), Node::Sequence(Sequence {
+            parts: vec![class(false, &[]), literal("a"), literal("]")],
+            span: Default::default(),
+        }));
+    }
+
+    #[test]
+    fn test_global_and_sticky_flags() {
+        let (flags, ast) = parse("%flags g,y\nab").unwrap();
+        assert!(flags.global && flags.sticky);
+        assert_eq!(flags.to_flag_string(), "ugy");
+        assert_eq!("gy".parse::<Flags>().unwrap(), flags);
+
+        let ir = crate::core::compiler::Compiler::new().with_flags(flags.clone()).compile(&ast);
+        let emitter = crate::emitters::pcre2::PCRE2Emitter::new(flags);
+        assert_eq!(emitter.emit(&ir), "ab");
+        assert_eq!(emitter.external_flags(), Flags::default());
+    }
+
+    #[test]
+    fn test_parse_delimited() {
+        let (flags, ast) = parse_delimited("/abc/i").unwrap();
+        assert!(flags.ignore_case);
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        assert!(matches!(ir, crate::core::ir::IROp::Lit(lit) if &*lit.value == "abc"));
+
+        let (flags, ast) = parse_delimited("/\\d+/gi").unwrap();
+        assert_eq!(flags, Flags { ignore_case: true, global: true, ..Flags::default() });
+        assert_eq!(ast, parse("\\d+").unwrap().1);
+        assert!(parse_delimited("/a/y").unwrap().0.sticky);
+        assert_eq!(parse_delimited("/a\\/b[/]/").unwrap().1, parse("a\\/b[/]").unwrap().1);
+        for (src, literal) in [("/%flags x/", "%flags x"), ("/#x/", "#x")] {
+            let ir = crate::core::compiler::Compiler::new().compile(&parse_delimited(src).unwrap().1);
+            assert!(matches!(ir, crate::core::ir::IROp::Lit(lit) if &*lit.value == literal), "{:?}", src);
+        }
+
+        for (src, message, span) in [
+            ("abc/i", "Expected '/' to open a delimited pattern", (0, 0)),
+            ("/abc", "Unterminated delimited pattern", (0, 4)),
+            ("/abc\\/", "Unterminated delimited pattern", (0, 6)),
+            ("/abc/iq", "Unknown flag: q", (6, 7)),
+            ("/a/b/", "Unknown flag: b", (3, 4)),
+            ("/a/I", "Unknown flag: I", (3, 4)),
+            ("/a/x", "Unknown flag: x", (3, 4)),
+            ("/a(b/", "Unterminated group", (4, 4)),
+        ] {
+            let err = parse_delimited(src).unwrap_err();
+            assert_eq!((err.message.as_str(), err.kind.span()), (message, span), "{:?}", src);
+            assert_eq!(err.text, src);
+        }
+    }
+
+    #[test]
+    fn test_empty_pattern() {
+        let emit = |src: &str| {
+            let (flags, ast) = parse(src).unwrap();
+            let ir = crate::core::compiler::Compiler::new().compile(&ast);
+            crate::emitters::pcre2::PCRE2Emitter::new(flags).emit(&ir)
+        };
+        let (flags, ast) = parse("").unwrap();
+        assert_eq!(flags, Flags::default());
+        assert!(matches!(&ast, Node::Sequence(seq) if seq.parts.is_empty()));
+        assert_eq!(emit(""), "");
+        assert_eq!(emit("%flags i\n"), "");
+        assert_eq!(emit("%anchor full\n"), "\\A\\z");
+    }
+
+    #[test]
+    fn test_class_intersection() {
+        let class = |src: &str| match parse(src).unwrap().1 {
+            Node::CharacterClass(cc) => cc,
+            other => panic!("Expected CharacterClass node, got {:?}", other),
+        };
+        let cc = class("[\\p{L}&&\\p{ASCII}]");
+        let [ClassItem::Intersection(intersection)] = cc.items.as_slice() else { panic!("expected intersection") };
+        assert_eq!(intersection.sets.len(), 2);
+        assert!(intersection.sets.iter().all(|set| set.items.len() == 1 && !set.negated));
+
+        let cc = class("[a-z&&[^aeiou]&&b-y]");
+        let [ClassItem::Intersection(intersection)] = cc.items.as_slice() else { panic!("expected intersection") };
+        assert_eq!(intersection.sets.len(), 3);
+        assert!(intersection.sets[1].negated && intersection.sets[1].items.len() == 5);
+
+        for (src, message) in [
+            ("[&&a]", "Empty operand in class intersection"),
+            ("[a&&]", "Empty operand in class intersection"),
+            ("[a&&[b]c]", "Expected '&&' or ']' after nested class"),
+            ("[a&&[b]", "Unterminated character class"),
+        ] {
+            assert_eq!(parse(src).unwrap_err().message, message, "{:?}", src);
+        }
+    }
+
+    #[test]
+    fn test_flags_directive_stops_at_pattern_text() {
+        let (flags, ast) = parse("%flags i abc").unwrap();
+        assert_eq!(flags, Flags { ignore_case: true, ..Flags::default() });
+        assert_eq!(ast, parse("abc").unwrap().1);
+
+        let (flags, ast) = parse("%flags [i,m] [ab]").unwrap();
+        assert_eq!(flags, Flags { ignore_case: true, multiline: true, ..Flags::default() });
+        assert_eq!(ast, parse("[ab]").unwrap().1);
+
+        // Flag letters are lowercase only.
+        let (flags, ast) = parse("%flags I\na").unwrap();
+        assert_eq!(flags, Flags::default());
+        assert_eq!(ast, parse("I\na").unwrap().1);
+    }
+
+    #[test]
+    fn test_default_flags() {
+        let ignore_case = Flags { ignore_case: true, ..Flags::default() };
+        let flags = |src: &str, policy| {
+            let parser = Parser::new(src.to_string()).with_default_flags(ignore_case.clone());
+            parser.with_flag_directives(policy).parse().unwrap().0
+        };
+        assert_eq!(flags("abc", FlagDirectives::Merge), ignore_case);
+        assert_eq!(flags("abc", FlagDirectives::Override), ignore_case);
+        assert_eq!(flags("%flags m\nabc", FlagDirectives::Merge), Flags { multiline: true, ..ignore_case.clone() });
+        assert_eq!(flags("%flags m\nabc", FlagDirectives::Override), Flags { multiline: true, ..Flags::default() });
+
+        // A default `x` flag changes how the pattern is read.
+        let extended = Flags { extended: true, ..Flags::default() };
+        let (_, ast) = Parser::new("a b".to_string()).with_default_flags(extended).parse().unwrap();
+        assert_eq!(ast, parse("ab").unwrap().1);
+    }
+
+    #[test]
+    fn test_undefined_named_backref() {
+        assert!(parse("(?P=x)").is_err());
+        assert!(parse("\\k<x>(?<x>a)").is_err());
+    }
+
+    #[test]
+    fn test_parse_recoverable_collects_all_errors() {
+        let (result, errors) = Parser::new("(a[b".to_string()).parse_recoverable();
+        assert!(result.is_none());
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0].kind, ParseErrorKind::UnterminatedClass { .. }));
+        assert!(matches!(errors[1].kind, ParseErrorKind::UnterminatedGroup { .. }));
+    }
+
+    #[test]
+    fn test_parse_recoverable_keeps_valid_parts() {
+        let (result, errors) = Parser::new("a)b".to_string()).parse_recoverable();
+        assert_eq!(errors.len(), 1);
+        let (_, node) = result.unwrap();
+        match node {
+            Node::Sequence(seq) => {
+                assert_eq!(seq.parts.len(), 3);
+                assert_eq!(seq.parts[1], Node::Error(ErrorNode { span: (1, 2) }));
+            }
+            _ => panic!("Expected Sequence node"),
+        }
+
+        let (result, errors) = Parser::new("ab".to_string()).parse_recoverable();
+        assert!(errors.is_empty());
+        assert!(result.is_some());
+    }
+
+    #[test]
+    fn test_parse_brace_quantifier_bounds() {
+        for (src, min, max) in [
+            ("a{3}", 3, MaxBound::Finite(3)),
+            ("a{2,5}", 2, MaxBound::Finite(5)),
+            ("a{2,}", 2, MaxBound::Infinite("Inf".to_string())),
+        ] {
+            match parse(src).unwrap().1 {
+                Node::Quantifier(q) => {
+                    assert_eq!(q.min, min);
+                    assert_eq!(q.max, max);
+                }
+                _ => panic!("Expected Quantifier node for {}", src),
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_brace_without_quantifier_is_literal() {
+        assert_eq!(
+            parse("a{").unwrap().1,
+            Node::Sequence(Sequence {
+                parts: vec![
+                    Node::Literal(Literal { value: "a".to_string(), span: Default::default() }),
+                    Node::Literal(Literal { value: "{".to_string(), span: Default::default() }),
+                ],
+                span: Default::default(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_brace_literal_policies() {
+        let parse_with = |src: &str, policy| Parser::new(src.to_string()).with_brace_literals(policy).parse();
+        let literal = |src: &str| {
+            Node::Sequence(Sequence {
+                parts: src.chars().map(|c| Node::Literal(Literal { value: c.to_string(), span: Default::default() })).collect(),
+                span: Default::default(),
+            })
+        };
+
+        let (flags, ast) = parse_with("a{", BraceLiterals::Lenient).unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        let pattern = crate::emitters::pcre2::PCRE2Emitter::new(flags).emit(&ir);
+        assert!(regex::Regex::new(&pattern).unwrap().is_match("xa{y"));
+        for src in ["a{1,", "a{foo}", "a{,}"] {
+            assert_eq!(parse_with(src, BraceLiterals::Lenient).unwrap().1, literal(src), "{}", src);
+        }
+        assert!(parse_with("a{5,2}", BraceLiterals::Lenient).is_err());
+        assert!(parse_with("a{2,3}", BraceLiterals::Lenient).is_ok());
+
+        for src in ["a{", "a{,}", "a{1,", "a{foo}"] {
+            let err = parse_with(src, BraceLiterals::Strict).unwrap_err();
+            assert!(matches!(err.kind, ParseErrorKind::InvalidQuantifier { span: (1, _) }), "{}: {:?}", src, err.kind);
+        }
+        assert!(parse_with("a{2}", BraceLiterals::Strict).is_ok());
+    }
+
+    #[test]
+    fn test_bare_braces_are_literals() {
+        let matches = |src: &str, text: &str| {
+            let (flags, ast) = parse(src).unwrap();
+            let ir = crate::core::compiler::Compiler::new().compile(&ast);
+            let pattern = crate::emitters::pcre2::PCRE2Emitter::new(flags).emit(&ir);
+            regex::Regex::new(&format!("^(?:{})$", pattern)).unwrap().is_match(text)
+        };
+
+        assert!(matches("a{", "a{"));
+        assert!(matches("a{x}", "a{x}"));
+        assert!(matches("a{1,", "a{1,"));
+        assert!(matches("{3,}", "{3,}"));
+        assert!(matches("a{2}", "aa"));
+        assert!(!matches("a{2}", "a{2}"));
+    }
+
+    #[test]
+    fn test_invalid_brace_quantifiers() {
+        for src in ["a{5,2}", "a{1", "a{foo}"] {
+            let err = Parser::new(src.to_string()).with_brace_literals(BraceLiterals::Strict).parse().unwrap_err();
+            assert!(
+                matches!(err.kind, ParseErrorKind::InvalidQuantifier { .. }),
+                "{} gave {:?}",
+                src,
+                err.kind
+            );
+        }
+    }
+
+    #[test]
+    fn test_quantifier_bound_too_large() {
+        let err = parse("a{99999999999999999999}").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::QuantifierBoundTooLarge { span: (2, 22) });
+        let err = parse("a{1,2147483648}").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::QuantifierBoundTooLarge { span: (4, 14) });
+        assert!(parse("a{2147483647}").is_ok());
+
+        let err = parse("a{5,2}").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidQuantifier { span: (1, 6) });
+        assert_eq!(err.message, "Invalid quantifier range {5,2}");
+    }
+
+    #[test]
+    fn test_parse_gnu_word_anchors() {
+        assert_eq!(
+            parse("\\<a\\>").unwrap().1,
+            Node::Sequence(Sequence {
+                parts: vec![
+                    Node::Anchor(Anchor { at: AnchorKind::WordStart, span: Default::default() }),
+                    Node::Literal(Literal { value: "a".to_string(), span: Default::default() }),
+                    Node::Anchor(Anchor { at: AnchorKind::WordEnd, span: Default::default() }),
+                ],
+                span: Default::default(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_quantified_assertion_kept() {
+        assert!(matches!(parse("^+").unwrap().1, Node::Quantifier(_)));
+        assert!(matches!(parse("(?=a)*").unwrap().1, Node::Quantifier(_)));
+        assert!(matches!(parse("(?:a)+").unwrap().1, Node::Quantifier(_)));
+    }
+
+    #[test]
+    fn test_quantified_assertion_rejected() {
+        let reject = |src: &str| {
+            Parser::new(src.to_string())
+                .with_assertion_quantifiers(AssertionQuantifiers::Reject)
+                .parse()
+        };
+        let err = reject("^+").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::InvalidQuantifier { span: (1, 2) });
+        assert_eq!(err.message, "Cannot quantify anchor");
+        assert!(reject("(?=a)*").is_err());
+        assert!(matches!(reject("(?:a)+").unwrap().1, Node::Quantifier(_)));
+    }
+
+    #[test]
+    fn test_quantified_assertion_dropped() {
+        let mut parser = Parser::new("^+a".to_string())
+            .with_assertion_quantifiers(AssertionQuantifiers::Drop);
+        let (_, ast) = parser.parse().unwrap();
+        assert_eq!(
+            ast,
+            Node::Sequence(Sequence {
+                parts: vec![
+                    Node::Anchor(Anchor { at: AnchorKind::Start, span: Default::default() }),
+                    Node::Literal(Literal { value: "a".to_string(), span: Default::default() }),
+                ],
+                span: Default::default(),
+            })
+        );
+        assert_eq!(parser.warnings(), &[STRlingWarning::QuantifiedAssertion { span: (1, 2) }]);
+    }
+
+    #[test]
+    fn test_literal_fast_path() {
+        let run = "x".repeat(LITERAL_RUN_MIN);
+        assert_eq!(parse(&run).unwrap().1, Node::Literal(Literal { value: run.clone(), span: Default::default() }));
+
+        // The quantified final character stays separate.
+        let (_, ast) = parse(&format!("{}y+", run)).unwrap();
+        match ast {
+            Node::Sequence(seq) => {
+                assert_eq!(seq.parts[0], Node::Literal(Literal { value: run.clone(), span: Default::default() }));
+                assert!(matches!(&seq.parts[1], Node::Quantifier(q)
+                    if *q.target.child == Node::Literal(Literal { value: "y".to_string(), span: Default::default() })));
+            }
+            _ => panic!("Expected Sequence node"),
+        }
+
+        // Short runs keep one node per character.
+        match parse("abc").unwrap().1 {
+            Node::Sequence(seq) => assert_eq!(seq.parts.len(), 3),
+            _ => panic!("Expected Sequence node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_previous_match_end_anchor() {
+        assert_eq!(
+            parse("\\Ga").unwrap().1,
+            Node::Sequence(Sequence {
+                parts: vec![
+                    Node::Anchor(Anchor { at: AnchorKind::PreviousMatchEnd, span: Default::default() }),
+                    Node::Literal(Literal { value: "a".to_string(), span: Default::default() }),
+                ],
+                span: Default::default(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_tokens() {
+        use crate::core::lexer::{Token, TokenKind};
+
+        let parser = Parser::new("%flags i\n(a|b)".to_string());
+        let tokens: Vec<Token> = parser.tokens().collect();
+        let expected = [
+            (TokenKind::GroupOpen, (0, 1)),
+            (TokenKind::Literal, (1, 2)),
+            (TokenKind::Alternation, (2, 3)),
+            (TokenKind::Literal, (3, 4)),
+            (TokenKind::GroupClose, (4, 5)),
+        ];
+        assert_eq!(
+            tokens,
+            expected.map(|(kind, span)| Token { kind, span }).to_vec()
+        );
+    }
+
+    #[test]
+    fn test_parse_class_escapes() {
+        let (_, ast) = parse("[\\b\\d\\]\\n]\\bword").unwrap();
+        let Node::Sequence(seq) = ast else {
+            panic!("Expected Sequence node");
+        };
+        assert_eq!(
+            seq.parts[0],
+            Node::CharacterClass(CharacterClass {
+                negated: false,
+                items: vec![
+                    ClassItem::Char(ClassLiteral { ch: "\u{0008}".to_string() }),
+                    ClassItem::Esc(ClassEscape { escape_type: "d".to_string(), property: None }),
+                    ClassItem::Char(ClassLiteral { ch: "]".to_string() }),
+                    ClassItem::Char(ClassLiteral { ch: "\n".to_string() }),
+                ],
+                span: Default::default(),
+            })
+        );
+        assert_eq!(seq.parts[1], Node::Anchor(Anchor { at: AnchorKind::WordBoundary, span: Default::default() }));
+    }
+
+    #[test]
+    fn test_parse_class_ranges() {
+        let range = |from: &str, to: &str| ClassItem::Range(ClassRange {
+            from_ch: from.to_string(),
+            to_ch: to.to_string(),
+        });
+        let ch = |c: &str| ClassItem::Char(ClassLiteral { ch: c.to_string() });
+        let items = |src: &str| match parse(src).unwrap().1 {
+            Node::CharacterClass(cc) => cc.items,
+            other => panic!("Expected CharacterClass, got {:?}", other),
+        };
+
+        assert_eq!(items("[a-z0-9_]"), vec![range("a", "z"), range("0", "9"), ch("_")]);
+        assert_eq!(items("[😀-😇]"), vec![range("😀", "😇")]);
+        assert_eq!(items("[\\t-\\-]"), vec![range("\t", "-")]);
+        assert_eq!(items("[-a-]"), vec![ch("-"), ch("a"), ch("-")]);
+        assert_eq!(
+            items("[a-\\d]"),
+            vec![ch("a"), ch("-"), ClassItem::Esc(ClassEscape { escape_type: "d".to_string(), property: None })]
+        );
+    }
+
+    #[test]
+    fn test_inverted_class_range() {
+        let err = parse("x[az-a]").unwrap_err();
+        assert_eq!(err.message, "Invalid character range [z-a]");
+        assert_eq!(err.kind, ParseErrorKind::InvalidRange { span: (3, 6) });
+        assert!(parse("[😇-😀]").is_err());
+    }
+
     #[test]
     fn test_parse_dot() {
         let result = parse(".");
@@ -800,19 +2676,285 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_partial_stops_at_unmatched_paren() {
+        let mut parser = Parser::new("abc)".to_string());
+        let (_, node, consumed) = parser.parse_partial().unwrap();
+        assert_eq!(consumed, 3);
+        match node {
+            Node::Sequence(seq) => assert_eq!(seq.parts.len(), 3),
+            _ => panic!("Expected Seq node"),
+        }
+    }
+
+    #[test]
+    fn test_parse_partial_counts_bytes() {
+        let mut parser = Parser::new("é)".to_string());
+        let (_, _, consumed) = parser.parse_partial().unwrap();
+        assert_eq!(consumed, "é".len());
+    }
+
     #[test]
     fn test_unmatched_paren_error() {
         let result = parse("test)");
         assert!(result.is_err());
         let err = result.unwrap_err();
         assert!(err.message.contains("Unmatched"));
+        assert_eq!(err.kind, ParseErrorKind::UnexpectedChar { found: ')', span: (4, 5) });
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let err = parse("(abc").unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::UnterminatedGroup { .. }));
+        assert_eq!(err.to_string(), err.to_formatted_string());
+
+        let err = parse("[abc").unwrap_err();
+        assert_eq!(err.kind, ParseErrorKind::UnterminatedClass { span: (0, 4) });
+
+        let err = parse("abc\\").unwrap_err();
+        assert!(matches!(err.kind, ParseErrorKind::InvalidEscape { .. }));
+    }
+
+    #[test]
+    fn test_malformed_input_errors_without_panicking() {
+        let err = parse("(?<").unwrap_err();
+        assert_eq!(err.message, "Unterminated group name");
+        assert!(matches!(err.kind, ParseErrorKind::UnterminatedGroup { .. }));
+
+        for src in ["\\", "(?P<", "(?<a", "[\\p{", "\\k<", "(?"] {
+            assert!(parse(src).is_err(), "{}", src);
+        }
+        assert_eq!(parse("{,}").unwrap().1, Node::Sequence(Sequence {
+            parts: "{,}".chars().map(|c| Node::Literal(Literal { value: c.to_string(), span: Default::default() })).collect(),
+            span: Default::default(),
+        }));
+    }
+
+    #[test]
+    fn test_deep_nesting_rejected() {
+        let depth = MAX_NESTING_DEPTH;
+        assert!(parse(&("(".repeat(depth) + &")".repeat(depth))).is_ok());
+
+        let src = "(?:".repeat(depth + 1) + &")".repeat(depth + 1);
+        let err = parse(&src).unwrap_err();
+        assert_eq!(err.message, "Groups nested more than 100 deep");
+        assert_eq!(err.pos, 3 * depth);
+
+        let src = "(".repeat(1000);
+        let (_, errors) = Parser::new(src).parse_recoverable();
+        assert!(!errors.is_empty());
     }
 
     #[test]
     fn test_empty_alternation() {
         let result = parse("a||b");
-        assert!(result.is_err());
-        let err = result.unwrap_err();
-        assert!(err.message.contains("Empty alternation"));
+        assert!(result.is_ok());
+        let (_, node) = result.unwrap();
+        match node {
+            Node::Alternation(alt) => {
+                assert_eq!(alt.branches.len(), 3);
+                assert_eq!(alt.branches[1], Node::Literal(Literal { value: String::new(), span: Default::default() }));
+            }
+            _ => panic!("Expected Alt node"),
+        }
+    }
+
+    #[test]
+    fn test_spans_cover_source() {
+        let (_, node) = parse("(abc)+x").unwrap();
+        assert_eq!(node.span(), Some((0, 7)));
+        let Node::Sequence(seq) = node else { panic!("Expected Sequence") };
+        assert_eq!(seq.parts[0].span(), Some((0, 6)));
+        let Node::Quantifier(quant) = &seq.parts[0] else { panic!("Expected Quantifier") };
+        assert_eq!(quant.target.child.span(), Some((0, 5)));
+        assert_eq!(seq.parts[1].span(), Some((6, 7)));
+
+        let (_, node) = parse("%define d = [0-9]\n$d|b").unwrap();
+        let Node::Alternation(alt) = node else { panic!("Expected Alternation") };
+        assert_eq!(alt.branches[0].span(), Some((0, 2)));
+    }
+
+    #[test]
+    fn test_extended_mode_comments_kept() {
+        let (_, node) = parse("%flags x\nfoo # the foo").unwrap();
+        let Node::Sequence(seq) = node else { panic!("Expected Sequence") };
+        assert_eq!(seq.parts.len(), 4);
+        assert_eq!(seq.parts[3], Node::Comment(Comment { text: "the foo".to_string(), span: Default::default() }));
+        assert_eq!(seq.parts[3].span(), Some((4, 13)));
+
+        // A comment between an atom and its quantifier follows the quantifier.
+        let (_, node) = parse("%flags x\na # many\n+").unwrap();
+        let Node::Sequence(seq) = node else { panic!("Expected Sequence") };
+        assert!(matches!(&seq.parts[..], [Node::Quantifier(_), Node::Comment(c)] if c.text == "many"));
+    }
+
+    #[test]
+    fn test_anchor_directive() {
+        let full_match = |src: &str, text: &str| {
+            let (flags, ast) = parse(src).unwrap();
+            let ir = crate::core::compiler::Compiler::new().compile(&ast);
+            let pattern = crate::emitters::pcre2::PCRE2Emitter::new(flags).emit(&ir);
+            regex::Regex::new(&pattern).unwrap().is_match(text)
+        };
+
+        for (text, expected) in [("a", true), ("b", true), ("ab", false), ("xa", false), ("b\n", false)] {
+            assert_eq!(full_match("%anchor full\na|b", text), expected, "{:?}", text);
+        }
+        assert!(full_match("%anchor start\nab", "abc"));
+        assert!(!full_match("%anchor start\nab", "cab"));
+        assert!(full_match("%anchor end\nab", "cab"));
+        assert!(!full_match("%anchor end\nab", "abc"));
+        assert!(full_match("%anchor sideways\nab", "cabc"));
+
+        let (_, ast) = parse("%anchor full\na|b").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        assert_eq!(crate::emitters::pcre2::PCRE2Emitter::new(Flags::default()).emit(&ir), "\\A(?:a|b)\\z");
+    }
+
+    #[test]
+    fn test_inline_flag_settings() {
+        let (_, ast) = parse("a(?s-i)b").unwrap();
+        let Node::Sequence(seq) = ast else { panic!("expected sequence") };
+        let Node::SetFlags(set) = &seq.parts[1] else { panic!("expected flag setting") };
+        assert!(set.on.dot_all && !set.on.ignore_case);
+        assert!(set.off.ignore_case && !set.off.dot_all);
+
+        let matches = |src: &str, text: &str| {
+            let (flags, ast) = parse(src).unwrap();
+            let ir = crate::core::compiler::Compiler::new().compile(&ast);
+            let pattern = crate::emitters::pcre2::PCRE2Emitter::new(flags).emit(&ir);
+            regex::Regex::new(&format!("^(?:{})$", pattern)).unwrap().is_match(text)
+        };
+        assert!(matches("a(?i)b", "aB"));
+        assert!(!matches("a(?i)b", "AB"));
+        assert!(matches("(?i)a(?-i)b", "Ab"));
+        assert!(!matches("(?i)a(?-i)b", "AB"));
+        // The setting ends with its group.
+        assert!(matches("(?:a(?i)b)c", "aBc"));
+        assert!(!matches("(?:a(?i)b)c", "aBC"));
+
+        // (?x) changes how the rest of the group is read.
+        assert!(matches("(?:(?x) a b )c d", "abc d"));
+
+        for bad in ["(?i", "(?-)", "(?i-m-s)", "(?iq)"] {
+            assert!(parse(bad).is_err(), "{:?}", bad);
+        }
+        assert!(parse("(?i)*").is_err());
+    }
+
+    #[test]
+    fn test_scoped_flag_groups() {
+        let (_, ast) = parse("(?i-s:a.)").unwrap();
+        let Node::Group(group) = ast else { panic!("expected group") };
+        assert!(!group.capturing);
+        let Node::Sequence(seq) = *group.body else { panic!("expected sequence") };
+        assert!(matches!(&seq.parts[0], Node::SetFlags(set) if set.on.ignore_case && set.off.dot_all));
+        assert_eq!(seq.parts[0].span(), Some((0, 6)));
+
+        let matches = |src: &str, text: &str| {
+            let (flags, ast) = parse(src).unwrap();
+            let ir = crate::core::compiler::Compiler::new().compile(&ast);
+            let pattern = crate::emitters::pcre2::PCRE2Emitter::new(flags).emit(&ir);
+            regex::Regex::new(&format!("^(?:{})$", pattern)).unwrap().is_match(text)
+        };
+        assert!(matches("(?i:ab)c", "ABc"));
+        assert!(!matches("(?i:ab)c", "ABC"));
+        assert!(matches("(?i:a|b)c", "Bc"));
+        assert!(matches("(?x: a b )c d", "abc d"));
+        assert!(matches("(?i:)a", "a"));
+
+        for bad in ["(?i:a", "(?-:a)", "(?:i:a)x)"] {
+            assert!(parse(bad).is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_backtracking_verbs() {
+        let (_, ast) = parse("a(*ACCEPT)b").unwrap();
+        let Node::Sequence(seq) = ast else { panic!("expected sequence") };
+        assert!(matches!(&seq.parts[1], Node::Verb(v) if v.name == "ACCEPT" && v.arg.is_none()));
+
+        let (_, ast) = parse("(*SKIP:label)").unwrap();
+        assert!(matches!(&ast, Node::Verb(v) if v.name == "SKIP" && v.arg.as_deref() == Some("label")));
+        assert!(matches!(parse("(*:m)").unwrap().1, Node::Verb(v) if v.name.is_empty()));
+
+        for (bad, message) in [
+            ("(*SKIP", "Unterminated verb"),
+            ("(*BOGUS)", "Unknown verb: (*BOGUS)"),
+            ("(*MARK)", "Verb (*MARK) requires a name"),
+            ("(*:)", "Verb (*) requires a name"),
+            ("(*COMMIT)+", "Cannot quantify backtracking verb"),
+        ] {
+            assert_eq!(parse(bad).unwrap_err().message, message, "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_escape_pattern_round_trips() {
+        assert_eq!(escape_pattern("a.b*c"), "a\\.b\\*c");
+        let re = |src: &str| {
+            let (flags, ast) = parse(src).unwrap();
+            let ir = crate::core::compiler::Compiler::new().compile(&ast);
+            let pattern = crate::emitters::pcre2::PCRE2Emitter::new(flags).emit(&ir);
+            regex::Regex::new(&format!("^(?:{})$", pattern)).unwrap()
+        };
+        let literal = re(&escape_pattern("a.b*c"));
+        assert!(literal.is_match("a.b*c"));
+        assert!(!literal.is_match("axbbbc"));
+
+        for s in [
+            "(a|b)+[^c]{2,}?$\\d",
+            "%flags x",
+            "// not a comment",
+            "# nor this\n\n x  y\t",
+            "\r\n\u{000B}\u{000C}\u{3000}",
+            "héllo wörld ✓ 😀",
+            "{,3}\\k<x>(?#)",
+        ] {
+            assert!(re(&escape_pattern(s)).is_match(s), "{:?}", s);
+            assert!(re(&format!("%flags x\n{}", escape_pattern(s))).is_match(s), "{:?} in extended mode", s);
+        }
+    }
+
+    #[test]
+    fn test_extended_mode_respects_escapes() {
+        let full_match = |src: &str, text: &str| {
+            let (flags, ast) = parse(src).unwrap();
+            let ir = crate::core::compiler::Compiler::new().compile(&ast);
+            let pattern = crate::emitters::pcre2::PCRE2Emitter::new(flags).emit(&ir);
+            regex::Regex::new(&format!("^(?:{})$", pattern)).unwrap().is_match(text)
+        };
+
+        assert!(full_match("%flags x\na\\ b", "a b"));
+        assert!(full_match("%flags x\na\\#b", "a#b"));
+        assert!(full_match("%flags x\na\\\\#b", "a\\"));
+        assert!(full_match("%flags x\na b", "ab"));
+        assert!(full_match("%flags x\na#b", "a"));
+        assert!(full_match("%flags x\na\\ +", "a   "));
+    }
+
+    #[test]
+    fn test_quoted_class_members() {
+        let members = |src: &str| match parse(src).unwrap().1 {
+            Node::CharacterClass(cc) => cc.items,
+            other => panic!("Expected CharacterClass, got {:?}", other),
+        };
+        let chars = |s: &str| -> Vec<ClassItem> {
+            s.chars().map(|c| ClassItem::Char(ClassLiteral { ch: c.to_string() })).collect()
+        };
+        assert_eq!(members("[\\Q-]\\E]"), chars("-]"));
+        assert_eq!(members("[\\Q.^$\\E]"), chars(".^$"));
+        assert_eq!(members("[x\\Q\\d\\E]"), chars("x\\d"));
+        assert!(parse("[\\Q]").is_err());
+    }
+
+    #[test]
+    fn test_brace_quantifier_without_min() {
+        let (_, node) = parse("a{,3}").unwrap();
+        let Node::Quantifier(quant) = node else { panic!("Expected Quantifier") };
+        assert_eq!((quant.min, quant.max), (0, MaxBound::Finite(3)));
+        assert_eq!(quant.span.0, Some((0, 5)));
+        assert!(matches!(parse("a{,3}?").unwrap().1, Node::Quantifier(q) if q.lazy));
     }
 }
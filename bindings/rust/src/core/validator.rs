@@ -3,7 +3,8 @@
 //! This module provides validation for STRling patterns against the
 //! JSON schema and semantic rules.
 
-use crate::core::nodes::Node;
+use crate::core::errors::STRlingWarning;
+use crate::core::nodes::{AnchorKind, Node};
 #[allow(unused_imports)]
 use serde_json::Value;
 
@@ -21,6 +22,105 @@ pub fn validate(_node: &Node) -> Result<(), ValidationError> {
     Ok(())
 }
 
+/// Lint a parsed AST for constructs that are legal but usually bugs
+///
+/// Empty alternation branches are not reported here; the compiler records
+/// those as warnings.
+///
+/// # Arguments
+///
+/// * `node` - The AST node to lint
+///
+/// # Returns
+///
+/// All warning-level findings, in pre-order
+pub fn lint(node: &Node) -> Vec<STRlingWarning> {
+    let mut warnings = Vec::new();
+    lint_node(node, &mut warnings);
+    warnings
+}
+
+fn lint_node(node: &Node, warnings: &mut Vec<STRlingWarning>) {
+    match node {
+        Node::Alternation(alt) => {
+            for (i, branch) in alt.branches.iter().enumerate() {
+                if let Some(first) = duplicated_branch(&alt.branches[..i], branch) {
+                    warnings.push(STRlingWarning::DuplicateAlternationBranch { branch: i, first });
+                }
+                lint_node(branch, warnings);
+            }
+        }
+        Node::Sequence(seq) => {
+            let parts: Vec<&Node> = seq.parts.iter().filter(|p| !matches!(p, Node::Comment(_))).collect();
+            for (i, part) in parts.iter().enumerate() {
+                if let (Node::Anchor(anchor), Some(next)) = (part, parts.get(i + 1)) {
+                    if anchor.at == AnchorKind::End && starts_with_non_newline(next) {
+                        warnings.push(STRlingWarning::TextAfterEndAnchor {
+                            span: anchor.span.0.unwrap_or_default(),
+                        });
+                    }
+                }
+                lint_node(part, warnings);
+            }
+        }
+        Node::Quantifier(quant) => lint_node(&quant.target.child, warnings),
+        Node::Group(group) => lint_node(&group.body, warnings),
+        Node::BalancingGroup(group) => lint_node(&group.body, warnings),
+        Node::BranchReset(reset) => {
+            for branch in &reset.branches {
+                lint_node(branch, warnings);
+            }
+        }
+        Node::Lookahead(look)
+        | Node::NegativeLookahead(look)
+        | Node::Lookbehind(look)
+        | Node::NegativeLookbehind(look) => lint_node(&look.body, warnings),
+        _ => {}
+    }
+}
+
+/// Whether every match of `node` starts with a character that is certainly
+/// not a newline
+///
+/// Only literals are inspected; classes and `.` depend on flags and
+/// members, so they count as possibly matching a newline.
+fn starts_with_non_newline(node: &Node) -> bool {
+    match node {
+        Node::Literal(lit) => lit.value.chars().next().is_some_and(|c| c != '\n' && c != '\r'),
+        Node::Sequence(seq) => seq
+            .parts
+            .iter()
+            .find(|p| !matches!(p, Node::Comment(_)))
+            .is_some_and(starts_with_non_newline),
+        Node::Alternation(alt) => alt.branches.iter().all(starts_with_non_newline),
+        Node::Quantifier(quant) => quant.min > 0 && starts_with_non_newline(&quant.target.child),
+        Node::Group(group) => starts_with_non_newline(&group.body),
+        _ => false,
+    }
+}
+
+/// The index of the first of `earlier` branches identical to `branch`
+///
+/// Spans take no part in node equality, so this compares structure only.
+/// A flag setting in an earlier branch reaches the ones after it, so no
+/// branch is compared past one. Empty branches are left to the compiler's
+/// `EmptyAlternationBranch` warning.
+fn duplicated_branch(earlier: &[Node], branch: &Node) -> Option<usize> {
+    if is_empty_branch(branch) || earlier.iter().any(Node::sets_flags) {
+        return None;
+    }
+    earlier.iter().position(|other| other == branch)
+}
+
+fn is_empty_branch(node: &Node) -> bool {
+    match node {
+        Node::Literal(lit) => lit.value.is_empty(),
+        Node::Sequence(seq) => seq.parts.iter().all(|part| matches!(part, Node::Comment(_))),
+        Node::Comment(_) => true,
+        _ => false,
+    }
+}
+
 /// Validation error type
 #[derive(Debug, Clone)]
 pub struct ValidationError {
@@ -44,7 +144,45 @@ mod tests {
     fn test_validate_literal() {
         let node = Node::Literal(Literal {
             value: "test".to_string(),
+            span: Default::default(),
         });
         assert!(validate(&node).is_ok());
     }
+
+    #[test]
+    fn test_lint_duplicate_alternation_branch() {
+        let (_, node) = crate::core::parser::parse("(?:a|b|a)").unwrap();
+        let warnings = lint(&node);
+        assert_eq!(warnings, vec![STRlingWarning::DuplicateAlternationBranch { branch: 2, first: 0 }]);
+        assert_eq!(warnings[0].message(), "Alternation branch 3 repeats branch 1 and is redundant");
+
+        // Repeated empty branches are no duplicates, a prefix is no
+        // duplicate, and `(?i)` changes what the later `x` matches.
+        for src in ["a||b|", "abc|ab", "(?i)x|y|x"] {
+            let (_, node) = crate::core::parser::parse(src).unwrap();
+            assert!(lint(&node).iter().all(|w| !matches!(w, STRlingWarning::DuplicateAlternationBranch { .. })), "{}", src);
+        }
+    }
+
+    #[test]
+    fn test_lint_clean_alternation() {
+        let (_, node) = crate::core::parser::parse("a|b").unwrap();
+        assert!(lint(&node).is_empty());
+    }
+
+    #[test]
+    fn test_lint_ignores_optional_group() {
+        let (_, node) = crate::core::parser::parse("(?:x)?").unwrap();
+        assert!(lint(&node).is_empty());
+    }
+
+    #[test]
+    fn test_lint_text_after_end_anchor() {
+        let lint_src = |src: &str| lint(&crate::core::parser::parse(src).unwrap().1);
+        assert_eq!(lint_src("foo$bar"), vec![STRlingWarning::TextAfterEndAnchor { span: (3, 4) }]);
+        assert_eq!(lint_src("x|a$(?:b|c)+"), vec![STRlingWarning::TextAfterEndAnchor { span: (3, 4) }]);
+        for src in ["foo$", "foo$\\n", "a$\\d", "a$(?:b)?", "a$b*", "(?:a$)b"] {
+            assert!(lint_src(src).is_empty(), "{}", src);
+        }
+    }
 }
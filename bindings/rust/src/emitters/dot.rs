@@ -0,0 +1,129 @@
+//! DOT Emitter - Render IR trees as Graphviz graphs
+//!
+//! This module renders an IR tree as a Graphviz `digraph` for debugging and
+//! documentation, e.g. `dot -Tsvg`. Unlike the regex emitters it produces a
+//! picture of the IR rather than a pattern: one graph node per IR op, labeled
+//! with the op kind and its key attributes, and one edge per child.
+
+use crate::core::ir::*;
+use crate::core::nodes::Flags;
+use crate::emitters::pcre2::PCRE2Emitter;
+use std::fmt::Write;
+
+/// Render `ir` as Graphviz `digraph` text
+///
+/// Nodes are named `n0`, `n1`, ... in pre-order, so the root is always `n0`.
+pub fn to_dot(ir: &IROp) -> String {
+    let mut out = String::from("digraph IR {\n    node [shape=box, fontname=monospace];\n");
+    let mut next_id = 0;
+    write_op(ir, &mut next_id, &mut out);
+    out.push_str("}\n");
+    out
+}
+
+/// Declare `op` and its subtree, returning the id of `op`'s node
+fn write_op(op: &IROp, next_id: &mut usize, out: &mut String) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    writeln!(out, "    n{} [label=\"{}\"];", id, escape(&label(op))).unwrap();
+    for child in children(op) {
+        let child_id = write_op(child, next_id, out);
+        writeln!(out, "    n{} -> n{};", id, child_id).unwrap();
+    }
+    id
+}
+
+fn label(op: &IROp) -> String {
+    match op {
+        IROp::Alt(_) => "Alt".to_string(),
+        IROp::Seq(_) => "Seq".to_string(),
+        IROp::Lit(lit) => format!("Lit {:?}", lit.value),
+        IROp::Dot(dot) if dot.dotall => "Dot (dotall)".to_string(),
+        IROp::Dot(dot) if dot.not_newline => "Dot (\\N)".to_string(),
+        IROp::Dot(_) => "Dot".to_string(),
+        IROp::Anchor(anchor) => format!("Anchor {}", anchor.at),
+        IROp::CharClass(cc) => {
+            let pattern = PCRE2Emitter::new(Flags::default()).emit(&IROp::CharClass(cc.clone()));
+            format!("CharClass {}", pattern)
+        }
+        IROp::Quant(quant) => {
+            let max = match &quant.max {
+                IRMaxBound::Finite(n) => n.to_string(),
+                IRMaxBound::Infinite(_) => "Inf".to_string(),
+            };
+            format!("Quant {{{},{}}} {}", quant.min, max, quant.mode)
+        }
+        IROp::Group(group) => {
+            let kind = if group.atomic {
+                "atomic".to_string()
+            } else if !group.capturing {
+                "non-capturing".to_string()
+            } else {
+                match &group.name {
+                    Some(name) => format!("#{} <{}>", group.index, name),
+                    None => format!("#{}", group.index),
+                }
+            };
+            format!("Group {}", kind)
+        }
+        IROp::BranchReset(_) => "BranchReset".to_string(),
+        IROp::BalancingGroup(group) => {
+            format!("BalancingGroup <{}-{}>", group.push.as_deref().unwrap_or(""), group.pop)
+        }
+        IROp::Backref(backref) => match (&backref.by_name, backref.by_index) {
+            (Some(name), _) => format!("Backref <{}>", name),
+            (None, Some(index)) => format!("Backref #{}", index),
+            (None, None) => "Backref".to_string(),
+        },
+        IROp::Look(look) => format!("Look {}{}", if look.neg { "!" } else { "" }, look.dir),
+        IROp::SetFlags(set) => format!("SetFlags {}", set.to_inline()),
+        IROp::Verb(verb) => format!("Verb {}", verb.to_inline()),
+    }
+}
+
+fn children(op: &IROp) -> Vec<&IROp> {
+    match op {
+        IROp::Alt(alt) => alt.branches.iter().collect(),
+        IROp::BranchReset(reset) => reset.branches.iter().collect(),
+        IROp::Seq(seq) => seq.parts.iter().collect(),
+        IROp::Quant(quant) => vec![&quant.child],
+        IROp::Group(group) => vec![&group.body],
+        IROp::BalancingGroup(group) => vec![&group.body],
+        IROp::Look(look) => vec![&look.body],
+        _ => Vec::new(),
+    }
+}
+
+/// Escape a label for a double-quoted DOT string
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::compiler::Compiler;
+    use crate::core::parser::parse;
+
+    fn dot(src: &str) -> String {
+        to_dot(&Compiler::new().compile(&parse(src).unwrap().1))
+    }
+
+    #[test]
+    fn test_one_declaration_per_op() {
+        // Seq -> [Lit "a", Quant -> Group -> Alt -> [Lit "b", CharClass]]
+        let out = dot("a(?<x>b|[0-9])+");
+        assert!(out.starts_with("digraph IR {\n"));
+        assert_eq!(out.matches("[label=").count(), 7);
+        assert_eq!(out.matches(" -> ").count(), 6);
+        assert!(out.contains("n2 [label=\"Quant {1,Inf} Greedy\"];"));
+        assert!(out.contains("n3 [label=\"Group #1 <x>\"];"));
+        assert!(out.contains("[label=\"CharClass [0-9]\"]"));
+    }
+
+    #[test]
+    fn test_labels_are_escaped() {
+        let out = dot("\"\\.");
+        assert!(out.contains("n0 [label=\"Lit \\\"\\\\\\\".\\\"\"];"), "{}", out);
+    }
+}
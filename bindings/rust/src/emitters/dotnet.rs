@@ -0,0 +1,170 @@
+//! .NET Emitter - Generate patterns for `System.Text.RegularExpressions`
+//!
+//! .NET syntax is close enough to PCRE2 that this emitter reuses
+//! `PCRE2Emitter`, switching it to the .NET spelling where the two differ:
+//! Unicode mode is the engine's own, `\N` becomes `[^\n]`, possessive
+//! quantifiers become atomic groups and numbered backreferences are written
+//! `\k<n>`. Balancing groups such as `(?<close-open>...)` are written as
+//! they were parsed. Branch reset groups and backtracking verbs have no
+//! .NET equivalent and are reported as an `EmitError`.
+//!
+//! .NET numbers unnamed groups first and named groups after them, so
+//! numbered backreferences are renumbered to match and `emit_with_map`
+//! reports the numbers .NET gives each group.
+
+use crate::core::analyze::CaptureMap;
+use crate::core::errors::EmitError;
+use crate::core::ir::IROp;
+use crate::core::nodes::Flags;
+use crate::emitters::pcre2::PCRE2Emitter;
+use crate::emitters::{EmitResult, GroupMapping};
+
+const TARGET: &str = ".NET";
+
+/// Emitter that generates .NET regular expressions
+#[derive(Debug, Clone)]
+pub struct DotNetEmitter {
+    inner: PCRE2Emitter,
+}
+
+impl DotNetEmitter {
+    /// Create a new .NET emitter with the given flags
+    pub fn new(flags: Flags) -> Self {
+        Self { inner: PCRE2Emitter::new(flags).with_dotnet_syntax() }
+    }
+
+    /// The flags this emitter was constructed with
+    pub fn flags(&self) -> &Flags {
+        self.inner.flags()
+    }
+
+    /// The subset of `flags()` the caller must apply as `RegexOptions`:
+    /// `IgnoreCase`, `Multiline` and `Singleline` for the `s` flag
+    pub fn external_flags(&self) -> Flags {
+        self.inner.external_flags()
+    }
+
+    /// Emit a .NET pattern from IR
+    ///
+    /// # Errors
+    ///
+    /// Returns `EmitError::Unsupported` for branch reset groups and
+    /// backtracking verbs.
+    pub fn emit(&self, ir: &IROp) -> Result<String, EmitError> {
+        Ok(self.emit_with_map(ir)?.pattern)
+    }
+
+    /// Emit a pattern like `emit`, with the numbers .NET gives its capture
+    /// groups
+    ///
+    /// # Errors
+    ///
+    /// As for `emit`.
+    pub fn emit_with_map(&self, ir: &IROp) -> Result<EmitResult, EmitError> {
+        for op in ir.iter() {
+            let construct = match op {
+                IROp::BranchReset(_) => "branch reset groups",
+                IROp::Verb(_) => "backtracking verbs",
+                _ => continue,
+            };
+            return Err(EmitError::Unsupported {
+                target: TARGET.to_string(),
+                construct: construct.to_string(),
+            });
+        }
+        let group_map = group_map(&CaptureMap::from_ir(ir));
+        let mut renumbered = ir.clone();
+        renumber_backrefs(&mut renumbered, &group_map);
+        Ok(EmitResult { pattern: self.inner.emit(&renumbered), group_map })
+    }
+}
+
+/// The .NET number of each group: unnamed groups in order, then named ones
+fn group_map(captures: &CaptureMap) -> Vec<GroupMapping> {
+    let unnamed = captures.iter().filter(|(_, name)| name.is_none()).count();
+    let (mut next_unnamed, mut next_named) = (0, unnamed);
+    captures
+        .iter()
+        .map(|(index, name)| {
+            let counter = if name.is_some() { &mut next_named } else { &mut next_unnamed };
+            *counter += 1;
+            GroupMapping { index, name: name.map(str::to_string), emitted_index: *counter }
+        })
+        .collect()
+}
+
+/// Point numbered backreferences in `op` at the .NET number of their group,
+/// or at its name if it has one
+fn renumber_backrefs(op: &mut IROp, group_map: &[GroupMapping]) {
+    match op {
+        IROp::Backref(backref) => {
+            let target = backref
+                .by_index
+                .and_then(|index| usize::try_from(index).ok())
+                .and_then(|index| group_map.iter().find(|mapping| mapping.index == index));
+            if let Some(mapping) = target {
+                match &mapping.name {
+                    Some(name) => {
+                        backref.by_name = Some(name.clone());
+                        backref.by_index = None;
+                    }
+                    None => backref.by_index = i32::try_from(mapping.emitted_index).ok(),
+                }
+            }
+        }
+        IROp::Seq(seq) => seq.parts.iter_mut().for_each(|p| renumber_backrefs(p, group_map)),
+        IROp::Alt(alt) => alt.branches.iter_mut().for_each(|b| renumber_backrefs(b, group_map)),
+        IROp::Quant(quant) => renumber_backrefs(&mut quant.child, group_map),
+        IROp::Group(group) => renumber_backrefs(&mut group.body, group_map),
+        IROp::BalancingGroup(group) => renumber_backrefs(&mut group.body, group_map),
+        IROp::Look(look) => renumber_backrefs(&mut look.body, group_map),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::compiler::Compiler;
+    use crate::core::parser::parse;
+
+    fn emit(src: &str) -> Result<String, EmitError> {
+        let (flags, ast) = parse(src).unwrap();
+        let ir = Compiler::new().with_flags(flags.clone()).compile(&ast);
+        DotNetEmitter::new(flags).emit(&ir)
+    }
+
+    #[test]
+    fn test_emit_balancing_groups_verbatim() {
+        let src = "^(?:[^()]|(?<open>\\()|(?<close-open>\\))|(?<-open>x))*$";
+        assert_eq!(emit(src).unwrap(), src);
+    }
+
+    #[test]
+    fn test_dotnet_spellings() {
+        assert_eq!(emit("%flags u\n\\N+").unwrap(), "[^\\n]+");
+        assert_eq!(emit("a*+b").unwrap(), "(?>a*)b");
+    }
+
+    #[test]
+    fn test_named_groups_numbered_last() {
+        let (flags, ast) = parse("(?<a>x)(y)\\g{2}\\g{1}").unwrap();
+        let ir = Compiler::new().compile(&ast);
+        let result = DotNetEmitter::new(flags).emit_with_map(&ir).unwrap();
+        assert_eq!(result.pattern, "(?<a>x)(y)\\k<1>\\k<a>");
+        assert_eq!(result.source_group(1).map(|g| g.index), Some(2));
+        assert_eq!(result.source_group(2).map(|g| g.name.as_deref()), Some(Some("a")));
+    }
+
+    #[test]
+    fn test_unsupported_constructs_name_target() {
+        for (src, construct) in [("(?|(a)|(b))", "branch reset groups"), ("a(*SKIP)b", "backtracking verbs")] {
+            assert_eq!(
+                emit(src),
+                Err(EmitError::Unsupported { target: TARGET.to_string(), construct: construct.to_string() }),
+                "{:?}",
+                src
+            );
+        }
+    }
+}
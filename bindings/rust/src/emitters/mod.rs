@@ -2,4 +2,204 @@
 //!
 //! This module contains emitters for various regex engines and formats.
 
+pub mod dot;
+pub mod dotnet;
 pub mod pcre2;
+pub mod php;
+pub mod regex_automata;
+pub mod rust_regex;
+
+use crate::core::analyze::CaptureMap;
+use crate::core::errors::EmitError;
+use crate::core::ir::{IRClassItem, IRClassLiteral, IRClassRange, IROp};
+use crate::core::nodes::Flags;
+
+/// A regex dialect STRling can emit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Flavor {
+    /// PCRE2, via `PCRE2Emitter`.
+    #[default]
+    Pcre2,
+    /// The Rust `regex` crate, via `RustRegexEmitter`.
+    RustRegex,
+    /// `regex-automata` DFAs, via `RegexAutomataEmitter`.
+    RegexAutomata,
+    /// .NET `System.Text.RegularExpressions`, via `DotNetEmitter`.
+    DotNet,
+}
+
+impl Flavor {
+    /// Emit `ir` with this flavor's emitter and its default options
+    ///
+    /// # Errors
+    ///
+    /// Returns `EmitError::Unsupported` if the flavor cannot express `ir`.
+    pub fn emit(self, flags: Flags, ir: &IROp) -> Result<String, EmitError> {
+        match self {
+            Flavor::Pcre2 => pcre2::PCRE2Emitter::new(flags).try_emit(ir),
+            Flavor::RustRegex => rust_regex::RustRegexEmitter::new(flags).emit(ir),
+            Flavor::RegexAutomata => regex_automata::RegexAutomataEmitter::new(flags).emit(ir),
+            Flavor::DotNet => dotnet::DotNetEmitter::new(flags).emit(ir),
+        }
+    }
+
+    /// Emit `ir` like `emit`, along with where each capturing group ended
+    /// up in the emitted pattern
+    ///
+    /// # Errors
+    ///
+    /// Returns `EmitError::Unsupported` if the flavor cannot express `ir`.
+    pub fn emit_with_map(self, flags: Flags, ir: &IROp) -> Result<EmitResult, EmitError> {
+        match self {
+            Flavor::Pcre2 => {
+                let pattern = pcre2::PCRE2Emitter::new(flags).try_emit(ir)?;
+                Ok(EmitResult::numbered_as_compiled(pattern, ir))
+            }
+            Flavor::RustRegex => rust_regex::RustRegexEmitter::new(flags).emit_with_map(ir),
+            Flavor::RegexAutomata => regex_automata::RegexAutomataEmitter::new(flags).emit_with_map(ir),
+            Flavor::DotNet => dotnet::DotNetEmitter::new(flags).emit_with_map(ir),
+        }
+    }
+}
+
+/// Where one capturing group of the compiled pattern is in an emitted one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupMapping {
+    /// The group number the compiler assigned (`IRGroup::index`)
+    pub index: usize,
+    /// The group's name, if it has one
+    pub name: Option<String>,
+    /// The number the target engine gives the group
+    pub emitted_index: usize,
+}
+
+/// An emitted pattern with the numbering of its capturing groups, for
+/// translating the target engine's capture indices back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmitResult {
+    /// The pattern, as `emit` returns it
+    pub pattern: String,
+    /// One entry per group number of the compiled pattern that the emitted
+    /// one still captures, in order
+    pub group_map: Vec<GroupMapping>,
+}
+
+impl EmitResult {
+    /// A result for a target that numbers groups as the compiler does
+    pub(crate) fn numbered_as_compiled(pattern: String, ir: &IROp) -> Self {
+        let group_map = CaptureMap::from_ir(ir)
+            .iter()
+            .map(|(index, name)| GroupMapping {
+                index,
+                name: name.map(str::to_string),
+                emitted_index: index,
+            })
+            .collect();
+        EmitResult { pattern, group_map }
+    }
+
+    /// The group a capture index reported by the target engine refers to
+    pub fn source_group(&self, emitted_index: usize) -> Option<&GroupMapping> {
+        self.group_map.iter().find(|mapping| mapping.emitted_index == emitted_index)
+    }
+}
+
+/// Which ends of the subject an emitted pattern is pinned to.
+///
+/// Anchors use `\A` and `\z`, which match only at the absolute start and end
+/// of the subject regardless of the multiline flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Anchored {
+    /// Emit the pattern as-is.
+    #[default]
+    None,
+    /// Prefix the pattern with `\A`.
+    Start,
+    /// Suffix the pattern with `\z`.
+    End,
+    /// Wrap the pattern in `\A...\z` for a full-string match.
+    Both,
+}
+
+/// Case-folded counterparts of class items not already in the class
+///
+/// ASCII letters (single or within ranges) always fold. Other single
+/// characters fold only when `unicode` is set and their opposite case is a
+/// single character; non-ASCII range members and escapes are left alone.
+pub(crate) fn case_folded_items(items: &[IRClassItem], unicode: bool) -> Vec<IRClassItem> {
+    let mut extra = Vec::new();
+    let mut push = |item: IRClassItem| {
+        if !items.contains(&item) && !extra.contains(&item) {
+            extra.push(item);
+        }
+    };
+    for item in items {
+        match item {
+            IRClassItem::Char(lit) => {
+                let mut chars = lit.ch.chars();
+                if let (Some(ch), None) = (chars.next(), chars.next()) {
+                    if let Some(folded) = fold_char(ch, unicode) {
+                        push(class_span(folded, folded));
+                    }
+                }
+            }
+            IRClassItem::Range(range) => {
+                let (Some(from), Some(to)) = (range.from_ch.chars().next(), range.to_ch.chars().next()) else {
+                    continue;
+                };
+                for (lo, hi) in [('a', 'z'), ('A', 'Z')] {
+                    let (start, end) = (from.max(lo), to.min(hi));
+                    if start <= end {
+                        let swap = |c: char| (c as u8 ^ 0x20) as char;
+                        push(class_span(swap(start), swap(end)));
+                    }
+                }
+            }
+            IRClassItem::Esc(_) | IRClassItem::Intersection(_) => {}
+        }
+    }
+    extra
+}
+
+/// The opposite-case form of `ch`, if it has exactly one
+fn fold_char(ch: char, unicode: bool) -> Option<char> {
+    if ch.is_ascii_alphabetic() {
+        return Some((ch as u8 ^ 0x20) as char);
+    }
+    if !unicode || ch.is_ascii() {
+        return None;
+    }
+    let folded: Vec<char> = if ch.is_lowercase() {
+        ch.to_uppercase().collect()
+    } else {
+        ch.to_lowercase().collect()
+    };
+    match folded[..] {
+        [other] if other != ch => Some(other),
+        _ => None,
+    }
+}
+
+fn class_span(from: char, to: char) -> IRClassItem {
+    if from == to {
+        IRClassItem::Char(IRClassLiteral { ch: from.to_string() })
+    } else {
+        IRClassItem::Range(IRClassRange {
+            from_ch: from.to_string(),
+            to_ch: to.to_string(),
+        })
+    }
+}
+
+/// ASCII-only members for the positive shorthands `\d`, `\w` and `\s`.
+///
+/// Negated shorthands have no in-class expansion; emitters leave them
+/// unchanged unless they stand alone in a class.
+pub(crate) fn ascii_shorthand(escape_type: &str) -> Option<&'static str> {
+    match escape_type {
+        "d" => Some("0-9"),
+        "w" => Some("A-Za-z0-9_"),
+        "s" => Some("\\t\\n\\x0B\\f\\r "),
+        _ => None,
+    }
+}
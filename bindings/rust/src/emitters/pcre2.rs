@@ -3,18 +3,200 @@
 //! This module implements code generation for the PCRE2 regex engine.
 //! It transforms the intermediate representation (IR) into PCRE2 syntax.
 
+use crate::core::analyze::CaptureMap;
+use crate::core::errors::EmitError;
 use crate::core::ir::*;
 use crate::core::nodes::Flags;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt;
+use crate::emitters::{ascii_shorthand, case_folded_items, Anchored, EmitResult, GroupMapping};
 
 /// PCRE2 emitter that generates PCRE2-compatible regex patterns from IR
+#[derive(Debug, Clone)]
 pub struct PCRE2Emitter {
     flags: Flags,
+    anchored: Anchored,
+    fold_case_in_classes: bool,
+    escape_policy: EscapePolicy,
+    flags_directive: bool,
+    /// Escape whitespace and `#` in literals, as `emit_extended` output needs
+    escape_trivia: bool,
+    expand_dotall: bool,
+    strip_names: bool,
+    noncapturing_when_unreferenced: bool,
+    unicode_verbs: bool,
+    /// Write the .NET spelling of constructs the two syntaxes share, for
+    /// `DotNetEmitter`
+    dotnet: bool,
+}
+
+/// Extra escaping applied to literal characters, for embedding the pattern
+/// in other syntaxes. The default escapes only what PCRE2 itself requires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EscapePolicy {
+    /// Write `/` as `\/`, as JavaScript `/.../` literals require.
+    pub escape_slash: bool,
+    /// Write non-ASCII characters as `\x{..}` code point escapes, keeping
+    /// the pattern ASCII-only.
+    pub hex_non_ascii: bool,
+}
+
+/// Where a member sits in a character class, which decides what in it
+/// needs escaping
+#[derive(Debug, Clone, Copy)]
+struct ClassPosition {
+    /// First thing after `[`, where `^` would negate the class.
+    leading: bool,
+    /// First or last member, where `-` cannot form a range.
+    edge: bool,
 }
 
 impl PCRE2Emitter {
     /// Create a new PCRE2 emitter with the given flags
     pub fn new(flags: Flags) -> Self {
-        Self { flags }
+        Self {
+            flags,
+            anchored: Anchored::None,
+            fold_case_in_classes: false,
+            escape_policy: EscapePolicy::default(),
+            flags_directive: false,
+            escape_trivia: false,
+            expand_dotall: false,
+            strip_names: false,
+            noncapturing_when_unreferenced: false,
+            unicode_verbs: false,
+            dotnet: false,
+        }
+    }
+
+    /// Switch to .NET syntax where it differs from PCRE2: Unicode mode is
+    /// the engine's own, `\N` as `[^\n]`, numbered backreferences as `\k<n>` and
+    /// possessive quantifiers as atomic groups
+    pub(crate) fn with_dotnet_syntax(mut self) -> Self {
+        self.dotnet = true;
+        self
+    }
+
+    /// Under `ignore_case`, spell out the other case inside character
+    /// classes (`[a-c]` becomes `[a-cA-C]`) for engines that cannot apply
+    /// the flag to classes themselves
+    pub fn with_fold_case_in_classes(mut self, enabled: bool) -> Self {
+        self.fold_case_in_classes = enabled;
+        self
+    }
+
+    /// Write a dot that matches newlines as `[\s\S]`, for engines with
+    /// neither a dotall option nor inline `(?s:...)` groups
+    ///
+    /// The `s` flag is then carried by the pattern itself rather than
+    /// `external_flags`, and a dot that excludes newlines is a plain `.`.
+    pub fn with_expand_dotall(mut self, enabled: bool) -> Self {
+        self.expand_dotall = enabled;
+        self
+    }
+
+    /// Write named groups as plain capturing groups, `(?<x>a)` as `(a)`,
+    /// for targets that have no use for the names
+    ///
+    /// Named backreferences are written by number instead.
+    pub fn with_strip_names(mut self, enabled: bool) -> Self {
+        self.strip_names = enabled;
+        self
+    }
+
+    /// Write capturing groups no backreference refers to as non-capturing
+    /// ones, for callers that only need to know whether and where the
+    /// pattern matches
+    ///
+    /// The groups left capturing are renumbered from 1, and backreferences
+    /// with them; `emit_with_map` reports the new numbers.
+    pub fn with_noncapturing_when_unreferenced(mut self, enabled: bool) -> Self {
+        self.noncapturing_when_unreferenced = enabled;
+        self
+    }
+
+    /// Choose how literal characters are escaped beyond what PCRE2 requires
+    pub fn with_escape_policy(mut self, policy: EscapePolicy) -> Self {
+        self.escape_policy = policy;
+        self
+    }
+
+    /// Write every non-ASCII literal and class member as a `\x{..}` escape,
+    /// for systems that mishandle multibyte pattern text
+    ///
+    /// Shorthand for an `EscapePolicy` with `hex_non_ascii` set. Group
+    /// names and verb arguments are written as they are.
+    pub fn with_ascii_safe(mut self, enabled: bool) -> Self {
+        self.escape_policy.hex_non_ascii = enabled;
+        self
+    }
+
+    /// Begin the output with a `%flags` directive carrying every set flag
+    /// but the default `u`, so the result re-parses as STRling source with
+    /// the same `Flags`
+    ///
+    /// The directive replaces any `(*UTF)(*UCP)` prefix, and under extended
+    /// mode literal spaces and `#` are escaped so re-parsing keeps them.
+    /// Comments in the source are not reproduced, since the compiler drops
+    /// them.
+    pub fn with_flags_directive(mut self, enabled: bool) -> Self {
+        self.flags_directive = enabled;
+        self
+    }
+
+    /// Begin the output with `(*UTF)(*UCP)` under Unicode mode, for callers
+    /// that cannot pass `PCRE2_UTF | PCRE2_UCP` as compile options
+    ///
+    /// Unicode mode then no longer appears in `external_flags`.
+    pub fn with_unicode_verbs(mut self, enabled: bool) -> Self {
+        self.unicode_verbs = enabled;
+        self
+    }
+
+    /// Pin the emitted pattern to the start and/or end of the subject
+    pub fn with_anchors(mut self, anchored: Anchored) -> Self {
+        self.anchored = anchored;
+        self
+    }
+
+    /// The flags this emitter was constructed with
+    pub fn flags(&self) -> &Flags {
+        &self.flags
+    }
+
+    /// The subset of `flags()` already reflected in the emitted pattern
+    ///
+    /// Extended mode is resolved by the parser and ASCII mode by expanding
+    /// shorthands, so neither needs to reach the engine. Unicode mode is
+    /// emitted as a leading `(*UTF)(*UCP)` under `with_unicode_verbs`, and
+    /// dotall mode is resolved here under `with_expand_dotall`.
+    pub fn inline_flags(&self) -> Flags {
+        Flags {
+            dot_all: self.flags.dot_all && self.expand_dotall,
+            extended: self.flags.extended,
+            ascii: self.flags.ascii,
+            unicode: self.writes_unicode_verbs(),
+            ..Flags::none()
+        }
+    }
+
+    /// The subset of `flags()` the caller must apply as PCRE2 compile
+    /// options; `unicode` stands for `PCRE2_UTF | PCRE2_UCP`, unless ASCII
+    /// mode overrides it
+    pub fn external_flags(&self) -> Flags {
+        Flags {
+            ignore_case: self.flags.ignore_case,
+            multiline: self.flags.multiline,
+            dot_all: self.flags.dot_all && !self.expand_dotall,
+            unicode: self.flags.unicode && !self.flags.ascii && !self.unicode_verbs && !self.dotnet,
+            ..Flags::none()
+        }
+    }
+
+    /// Whether the output starts with `(*UTF)(*UCP)`
+    fn writes_unicode_verbs(&self) -> bool {
+        self.unicode_verbs && self.flags.unicode && !self.flags.ascii && !self.dotnet && !self.flags_directive
     }
 
     /// Emit PCRE2 pattern from IR
@@ -27,189 +209,610 @@ impl PCRE2Emitter {
     ///
     /// A string containing the PCRE2 pattern
     pub fn emit(&self, ir: &IROp) -> String {
-        self.emit_node(ir)
+        let mut out = String::new();
+        self.emit_to(ir, &mut out)
+            .expect("writing to a String cannot fail");
+        out
+    }
+
+    /// Emit a PCRE2 pattern like `emit`, rejecting constructs PCRE2 lacks
+    ///
+    /// # Errors
+    ///
+    /// Returns `EmitError::Unsupported` for .NET balancing groups, which
+    /// `emit` writes through unchanged for PCRE2 to reject when compiling
+    /// the pattern.
+    pub fn try_emit(&self, ir: &IROp) -> Result<String, EmitError> {
+        if ir.iter().any(|op| matches!(op, IROp::BalancingGroup(_))) {
+            return Err(EmitError::Unsupported {
+                target: "PCRE2".to_string(),
+                construct: "balancing groups".to_string(),
+            });
+        }
+        Ok(self.emit(ir))
+    }
+
+    /// Emit a PCRE2 pattern like `emit`, with its capture group numbering
+    ///
+    /// PCRE2 numbers groups, branch resets included, exactly as the
+    /// compiler does, so the map is the identity unless
+    /// `with_noncapturing_when_unreferenced` dropped some groups.
+    pub fn emit_with_map(&self, ir: &IROp) -> EmitResult {
+        let pattern = self.emit(ir);
+        if !self.noncapturing_when_unreferenced {
+            return EmitResult::numbered_as_compiled(pattern, ir);
+        }
+        let captures = CaptureMap::from_ir(ir);
+        let group_map = self
+            .kept_groups(ir, &captures)
+            .into_iter()
+            .enumerate()
+            .map(|(i, index)| GroupMapping {
+                index,
+                name: captures.name(index).map(str::to_string),
+                emitted_index: i + 1,
+            })
+            .collect();
+        EmitResult { pattern, group_map }
+    }
+
+    /// `ir` rewritten for `with_strip_names` and
+    /// `with_noncapturing_when_unreferenced`
+    fn apply_capture_options<'a>(&self, ir: &'a IROp) -> Cow<'a, IROp> {
+        if !self.strip_names && !self.noncapturing_when_unreferenced {
+            return Cow::Borrowed(ir);
+        }
+        let captures = CaptureMap::from_ir(ir);
+        let kept = self.kept_groups(ir, &captures);
+        let mut ir = ir.clone();
+        rewrite_captures(&mut ir, &captures, &kept, self.strip_names);
+        Cow::Owned(ir)
+    }
+
+    /// The numbers of the groups the emitted pattern still captures
+    fn kept_groups(&self, ir: &IROp, captures: &CaptureMap) -> Vec<usize> {
+        let referenced: HashSet<usize> = ir
+            .iter()
+            .filter_map(|op| match op {
+                IROp::Backref(backref) => backref_target(backref, captures),
+                _ => None,
+            })
+            .collect();
+        (1..=captures.len())
+            .filter(|index| !self.noncapturing_when_unreferenced || referenced.contains(index))
+            .collect()
+    }
+
+    /// Emit PCRE2 pattern from IR directly into `out`
+    ///
+    /// Produces exactly what `emit` returns, without building intermediate
+    /// strings, so large patterns can be streamed into a file or appended to
+    /// a reused buffer. `out` may be a `&mut dyn fmt::Write`.
+    ///
+    /// # Errors
+    ///
+    /// Propagates any error reported by `out`.
+    pub fn emit_to(&self, ir: &IROp, out: &mut (impl fmt::Write + ?Sized)) -> fmt::Result {
+        let ir = &*self.apply_capture_options(ir);
+        // Start-of-pattern verbs switch `\w`, `\d`, `\s` and `\b` to Unicode
+        // semantics; ASCII mode has already expanded the shorthands.
+        if self.flags_directive {
+            // Unicode mode is the default, so re-parsing restores it.
+            let letters = Flags { unicode: false, ..self.flags.clone() }.to_flag_string();
+            if !letters.is_empty() {
+                writeln!(out, "%flags {}", letters)?;
+            }
+        } else if self.writes_unicode_verbs() {
+            out.write_str("(*UTF)(*UCP)")?;
+        }
+        if self.anchored == Anchored::None {
+            return self.write_node(ir, out);
+        }
+        // A top-level alternation must be grouped so the anchors bind to
+        // every branch rather than only the first and last.
+        let grouped = matches!(ir, IROp::Alt(_));
+        if self.anchored != Anchored::End {
+            out.write_str("\\A")?;
+        }
+        if grouped {
+            out.write_str("(?:")?;
+        }
+        self.write_node(ir, out)?;
+        if grouped {
+            out.write_char(')')?;
+        }
+        if self.anchored != Anchored::Start {
+            out.write_str("\\z")?;
+        }
+        Ok(())
+    }
+
+    /// Emit a `(?x)` pattern laid out over several lines for reading
+    ///
+    /// Alternation branches go on their own lines, and groups and
+    /// lookarounds that contain further structure put their body on
+    /// indented lines between the opener and the `)`. Sequence parts are
+    /// separated by spaces. Literal whitespace and `#` are escaped, so the
+    /// result matches exactly what `emit` does. Character classes are
+    /// kept intact, since free-spacing mode leaves their contents alone.
+    pub fn emit_extended(&self, ir: &IROp) -> String {
+        let emitter = Self { escape_trivia: true, flags_directive: false, ..self.clone() };
+        let ir = &*self.apply_capture_options(ir);
+        let anchored;
+        let ir = match self.anchored {
+            Anchored::None => ir,
+            _ => {
+                anchored = anchored_ir(ir, self.anchored);
+                &anchored
+            }
+        };
+        let mut out = String::new();
+        if emitter.writes_unicode_verbs() {
+            out.push_str("(*UTF)(*UCP)");
+        }
+        out.push_str("(?x)\n");
+        emitter.write_laid_out(ir, 0, &mut out).expect("writing to a String cannot fail");
+        out
+    }
+
+    /// Emit `node` for `emit_extended`, with continuation lines indented
+    /// `depth` levels
+    fn write_laid_out(&self, node: &IROp, depth: usize, out: &mut String) -> fmt::Result {
+        let newline = |out: &mut String, depth: usize| {
+            out.push('\n');
+            out.push_str(&"    ".repeat(depth));
+        };
+        let (opener, body) = match node {
+            IROp::Alt(alt) => {
+                for (i, branch) in alt.branches.iter().enumerate() {
+                    if i > 0 {
+                        newline(out, depth);
+                        out.push_str("| ");
+                    }
+                    self.write_laid_out(branch, depth, out)?;
+                }
+                return Ok(());
+            }
+            IROp::Seq(seq) => {
+                for (i, part) in seq.parts.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    self.write_laid_out(part, depth, out)?;
+                }
+                return Ok(());
+            }
+            IROp::Quant(quant) => {
+                self.write_laid_out(&quant.child, depth, out)?;
+                return self.write_quantifier(quant, out);
+            }
+            IROp::Group(group) if is_flat(&group.body) => return self.write_node(node, out),
+            IROp::Look(look) if is_flat(&look.body) => return self.write_node(node, out),
+            IROp::BalancingGroup(group) if is_flat(&group.body) => return self.write_node(node, out),
+            IROp::Group(group) => (group_opener(group), &*group.body),
+            IROp::Look(look) => (look_opener(look).to_string(), &*look.body),
+            IROp::BalancingGroup(group) => (balancing_opener(group), &*group.body),
+            IROp::BranchReset(reset) => {
+                out.push_str("(?|");
+                for (i, branch) in reset.branches.iter().enumerate() {
+                    newline(out, depth + 1);
+                    if i > 0 {
+                        out.push_str("| ");
+                    }
+                    self.write_laid_out(branch, depth + 1, out)?;
+                }
+                newline(out, depth);
+                out.push(')');
+                return Ok(());
+            }
+            _ => return self.write_node(node, out),
+        };
+        out.push_str(&opener);
+        newline(out, depth + 1);
+        self.write_laid_out(body, depth + 1, out)?;
+        newline(out, depth);
+        out.push(')');
+        Ok(())
     }
 
     /// Emit a single IR node
-    fn emit_node(&self, node: &IROp) -> String {
+    fn write_node<W: fmt::Write + ?Sized>(&self, node: &IROp, out: &mut W) -> fmt::Result {
         match node {
-            IROp::Lit(lit) => self.emit_literal(&lit.value),
-            IROp::Dot(_) => ".".to_string(),
-            IROp::Anchor(anchor) => match anchor.at.as_str() {
-                "Start" => "^".to_string(),
-                "End" => "$".to_string(),
-                "WordBoundary" => "\\b".to_string(),
-                "NotWordBoundary" => "\\B".to_string(),
-                "AbsoluteStart" => "\\A".to_string(),
-                "EndBeforeFinalNewline" => "\\Z".to_string(),
-                "AbsoluteEnd" => "\\z".to_string(),
-                _ => panic!("Unknown anchor type: {}", anchor.at),
-            },
+            IROp::Lit(lit) => self.write_literal(&lit.value, out),
+            IROp::Dot(dot) if dot.not_newline && self.dotnet => out.write_str("[^\\n]"),
+            IROp::Dot(dot) if dot.not_newline => out.write_str("\\N"),
+            IROp::Dot(dot) if dot.dotall && self.expand_dotall => out.write_str("[\\s\\S]"),
+            // A dotall dot outside a global `s` flag is scoped locally.
+            IROp::Dot(dot) if dot.dotall && !self.flags.dot_all => out.write_str("(?s:.)"),
+            IROp::Dot(_) => out.write_char('.'),
+            IROp::SetFlags(set) => out.write_str(&set.to_emitted()),
+            IROp::Verb(verb) => out.write_str(&verb.to_inline()),
+            IROp::Anchor(anchor) => out.write_str(match anchor.at {
+                AnchorKind::Start => "^",
+                AnchorKind::End => "$",
+                AnchorKind::WordBoundary => "\\b",
+                AnchorKind::NotWordBoundary => "\\B",
+                AnchorKind::AbsoluteStart => "\\A",
+                AnchorKind::EndBeforeFinalNewline => "\\Z",
+                AnchorKind::AbsoluteEnd => "\\z",
+                AnchorKind::PreviousMatchEnd => "\\G",
+                // PCRE2 has no GNU `\<`/`\>`; spell them as boundary plus lookaround.
+                AnchorKind::WordStart => "\\b(?=\\w)",
+                AnchorKind::WordEnd => "(?<=\\w)\\b",
+            }),
             IROp::Seq(seq) => {
-                seq.parts.iter().map(|p| self.emit_node(p)).collect::<Vec<_>>().join("")
+                for part in &seq.parts {
+                    self.write_node(part, out)?;
+                }
+                Ok(())
             }
             IROp::Alt(alt) => {
-                alt.branches.iter().map(|b| self.emit_node(b)).collect::<Vec<_>>().join("|")
+                for (i, branch) in alt.branches.iter().enumerate() {
+                    if i > 0 {
+                        out.write_char('|')?;
+                    }
+                    self.write_node(branch, out)?;
+                }
+                Ok(())
+            }
+            IROp::BranchReset(reset) => {
+                out.write_str("(?|")?;
+                for (i, branch) in reset.branches.iter().enumerate() {
+                    if i > 0 {
+                        out.write_char('|')?;
+                    }
+                    self.write_node(branch, out)?;
+                }
+                out.write_char(')')
+            }
+            // .NET has no possessive quantifiers; an atomic group is the same.
+            IROp::Quant(quant) if quant.mode == QuantMode::Possessive && self.dotnet => {
+                out.write_str("(?>")?;
+                self.write_node(&quant.child, out)?;
+                self.write_quantifier(&IRQuant { mode: QuantMode::Greedy, ..quant.clone() }, out)?;
+                out.write_char(')')
             }
             IROp::Quant(quant) => {
-                let child = self.emit_node(&quant.child);
-                let quantifier = match (&quant.max, quant.min) {
-                    (IRMaxBound::Infinite(_), 0) => "*".to_string(),
-                    (IRMaxBound::Infinite(_), 1) => "+".to_string(),
-                    (IRMaxBound::Finite(1), 0) => "?".to_string(),
-                    (IRMaxBound::Infinite(_), min) => format!("{{{},}}", min),
-                    (IRMaxBound::Finite(max), min) if min == *max => format!("{{{}}}", min),
-                    (IRMaxBound::Finite(max), min) => format!("{{{},{}}}", min, max),
-                };
-                
-                let mode_suffix = match quant.mode.as_str() {
-                    "Lazy" => "?",
-                    "Possessive" => "+",
-                    _ => "",  // Greedy has no suffix
-                };
-                
-                format!("{}{}{}", child, quantifier, mode_suffix)
+                self.write_node(&quant.child, out)?;
+                self.write_quantifier(quant, out)
             }
             IROp::Group(group) => {
-                let body = self.emit_node(&group.body);
-                if group.atomic {
-                    format!("(?>{})", body)
-                } else if let Some(name) = &group.name {
-                    format!("(?<{}>{})", name, body)
-                } else if !group.capturing {
-                    format!("(?:{})", body)
-                } else {
-                    format!("({})", body)
-                }
+                out.write_str(&group_opener(group))?;
+                self.write_node(&group.body, out)?;
+                out.write_char(')')
             }
             IROp::Look(look) => {
-                let body = self.emit_node(&look.body);
-                match (look.dir.as_str(), look.neg) {
-                    ("Ahead", false) => format!("(?={})", body),
-                    ("Ahead", true) => format!("(?!{})", body),
-                    ("Behind", false) => format!("(?<={})", body),
-                    ("Behind", true) => format!("(?<!{})", body),
-                    _ => panic!("Unknown lookaround type"),
-                }
+                out.write_str(look_opener(look))?;
+                self.write_node(&look.body, out)?;
+                out.write_char(')')
+            }
+            IROp::BalancingGroup(group) => {
+                out.write_str(&balancing_opener(group))?;
+                self.write_node(&group.body, out)?;
+                out.write_char(')')
             }
             IROp::Backref(backref) => {
                 if let Some(name) = &backref.by_name {
-                    format!("\\k<{}>", name)
+                    write!(out, "\\k<{}>", name)
+                } else if let (Some(num), true) = (backref.by_index, self.dotnet) {
+                    write!(out, "\\k<{}>", num)
                 } else if let Some(num) = backref.by_index {
-                    format!("\\{}", num)
+                    // Braced, so a following digit cannot extend the number.
+                    write!(out, "\\g{{{}}}", num)
                 } else {
                     panic!("Backref must have either name or index")
                 }
             }
+            IROp::CharClass(cc) if cc.items.iter().any(|item| matches!(item, IRClassItem::Intersection(_))) => {
+                self.write_intersecting_class(cc, out)
+            }
+            // `[]` would open a class with a literal `]`
+            IROp::CharClass(cc) if cc.items.is_empty() => out.write_str(if cc.negated { "(?s:.)" } else { "(?!)" }),
             IROp::CharClass(cc) => {
-                let mut result = String::from("[");
+                // In ASCII mode a lone negated shorthand (`\D`) becomes a
+                // negated ASCII set (`[^0-9]`).
+                if self.flags.ascii && cc.items.len() == 1 {
+                    if let IRClassItem::Esc(esc) = &cc.items[0] {
+                        if let Some(set) = ascii_shorthand(&esc.escape_type.to_lowercase()) {
+                            let negated = cc.negated != esc.escape_type.chars().all(|c| c.is_uppercase());
+                            return write!(out, "[{}{}]", if negated { "^" } else { "" }, set);
+                        }
+                    }
+                }
+                out.write_char('[')?;
                 if cc.negated {
-                    result.push('^');
+                    out.write_char('^')?;
+                }
+                let folded = if self.fold_case_in_classes && self.flags.ignore_case {
+                    case_folded_items(&cc.items, self.flags.unicode)
+                } else {
+                    Vec::new()
+                };
+                let count = cc.items.len() + folded.len();
+                for (i, item) in cc.items.iter().chain(&folded).enumerate() {
+                    let position = ClassPosition { leading: i == 0 && !cc.negated, edge: i == 0 || i + 1 == count };
+                    self.write_class_item(item, position, out)?;
                 }
-                for item in &cc.items {
-                    result.push_str(&self.emit_class_item(item));
+                out.write_char(']')
+            }
+        }
+    }
+
+    /// Emit a class containing intersections, which PCRE2 lacks, as
+    /// lookaheads: `[A&&B]` becomes `(?:(?=[A])[B])`
+    ///
+    /// Other members of the class become an alternative of their own; a
+    /// negated class asserts none of the alternatives match and then takes
+    /// any one character.
+    fn write_intersecting_class<W: fmt::Write + ?Sized>(&self, cc: &IRCharClass, out: &mut W) -> fmt::Result {
+        let mut branches = Vec::new();
+        let (intersections, others): (Vec<_>, Vec<_>) =
+            cc.items.iter().cloned().partition(|item| matches!(item, IRClassItem::Intersection(_)));
+        if !others.is_empty() {
+            let mut branch = String::new();
+            self.write_node(&IROp::CharClass(IRCharClass { negated: false, items: others, span: cc.span }), &mut branch)?;
+            branches.push(branch);
+        }
+        for item in intersections {
+            let IRClassItem::Intersection(intersection) = item else { continue };
+            let mut branch = String::new();
+            for (i, set) in intersection.sets.iter().enumerate() {
+                let last = i + 1 == intersection.sets.len();
+                if !last {
+                    branch.push_str("(?=");
+                }
+                self.write_node(&IROp::CharClass(set.clone()), &mut branch)?;
+                if !last {
+                    branch.push(')');
                 }
-                result.push(']');
-                result
             }
+            branches.push(branch);
+        }
+        if cc.negated {
+            write!(out, "(?:(?!{})(?s:.))", branches.join("|"))
+        } else {
+            write!(out, "(?:{})", branches.join("|"))
         }
     }
 
-    /// Emit a character class item
-    fn emit_class_item(&self, item: &IRClassItem) -> String {
+    /// Emit the bounds and mode suffix of a quantifier
+    fn write_quantifier<W: fmt::Write + ?Sized>(&self, quant: &IRQuant, out: &mut W) -> fmt::Result {
+        match (&quant.max, quant.min) {
+            (IRMaxBound::Infinite(_), 0) => out.write_char('*')?,
+            (IRMaxBound::Infinite(_), 1) => out.write_char('+')?,
+            (IRMaxBound::Finite(1), 0) => out.write_char('?')?,
+            (IRMaxBound::Infinite(_), min) => write!(out, "{{{},}}", min)?,
+            (IRMaxBound::Finite(max), min) if min == *max => write!(out, "{{{}}}", min)?,
+            (IRMaxBound::Finite(max), min) => write!(out, "{{{},{}}}", min, max)?,
+        }
+        out.write_str(match quant.mode {
+            QuantMode::Greedy => "",
+            QuantMode::Lazy => "?",
+            QuantMode::Possessive => "+",
+        })
+    }
+
+    /// Emit a character class item found at `position`
+    fn write_class_item<W: fmt::Write + ?Sized>(
+        &self,
+        item: &IRClassItem,
+        position: ClassPosition,
+        out: &mut W,
+    ) -> fmt::Result {
         match item {
-            IRClassItem::Char(lit) => self.escape_class_char(&lit.ch),
+            IRClassItem::Char(lit) => self.write_class_chars(&lit.ch, position, out),
             IRClassItem::Range(range) => {
-                format!("{}-{}", 
-                    self.escape_class_char(&range.from_ch),
-                    self.escape_class_char(&range.to_ch))
+                let inner = ClassPosition { leading: false, edge: false };
+                self.write_class_chars(&range.from_ch, ClassPosition { edge: false, ..position }, out)?;
+                out.write_char('-')?;
+                self.write_class_chars(&range.to_ch, inner, out)
             }
             IRClassItem::Esc(esc) => {
+                if self.flags.ascii {
+                    if let Some(set) = ascii_shorthand(&esc.escape_type) {
+                        return out.write_str(set);
+                    }
+                }
                 match esc.escape_type.as_str() {
-                    "d" => "\\d".to_string(),
-                    "D" => "\\D".to_string(),
-                    "w" => "\\w".to_string(),
-                    "W" => "\\W".to_string(),
-                    "s" => "\\s".to_string(),
-                    "S" => "\\S".to_string(),
-                    "p" => format!("\\p{{{}}}", esc.property.as_ref().unwrap_or(&"".to_string())),
-                    "P" => format!("\\P{{{}}}", esc.property.as_ref().unwrap_or(&"".to_string())),
-                    _ => format!("\\{}", esc.escape_type),
+                    "p" | "P" => write!(
+                        out,
+                        "\\{}{{{}}}",
+                        esc.escape_type,
+                        esc.property.as_deref().unwrap_or("")
+                    ),
+                    _ => write!(out, "\\{}", esc.escape_type),
                 }
             }
+            IRClassItem::Intersection(_) => unreachable!("classes with intersections are written as lookaheads"),
         }
     }
 
     /// Escape a literal string for PCRE2
-    fn emit_literal(&self, s: &str) -> String {
-        let mut result = String::new();
+    fn write_literal<W: fmt::Write + ?Sized>(&self, s: &str, out: &mut W) -> fmt::Result {
         for ch in s.chars() {
-            result.push_str(&self.escape_char(ch));
-        }
-        result
-    }
-
-    /// Escape a single character for PCRE2 pattern context
-    fn escape_char(&self, ch: char) -> String {
-        match ch {
-            '.' | '*' | '+' | '?' | '^' | '$' | '|' | '(' | ')' | '[' | ']' | '{' | '}' | '\\' => {
-                format!("\\{}", ch)
+            match ch {
+                '.' | '*' | '+' | '?' | '^' | '$' | '|' | '(' | ')' | '[' | ']' | '{' | '}' | '\\' => {
+                    out.write_char('\\')?;
+                    out.write_char(ch)?;
+                }
+                '\n' => out.write_str("\\n")?,
+                '\r' => out.write_str("\\r")?,
+                '\t' => out.write_str("\\t")?,
+                '\u{000C}' => out.write_str("\\f")?,
+                '\u{000B}' => out.write_str("\\v")?,
+                _ => self.write_plain_char(ch, out)?,
             }
-            '\n' => "\\n".to_string(),
-            '\r' => "\\r".to_string(),
-            '\t' => "\\t".to_string(),
-            '\u{000C}' => "\\f".to_string(),
-            '\u{000B}' => "\\v".to_string(),
-            _ => ch.to_string(),
         }
+        Ok(())
     }
 
-    /// Escape a character for use inside a character class
-    fn escape_class_char(&self, s: &str) -> String {
-        let mut result = String::new();
+    /// Escape characters for use inside a character class
+    ///
+    /// Escaping is minimal: `]` and `\\` are always escaped, `^` only where
+    /// it would negate the class, and `-` only where it could form a range,
+    /// i.e. anywhere but the first or last member.
+    fn write_class_chars<W: fmt::Write + ?Sized>(
+        &self,
+        s: &str,
+        position: ClassPosition,
+        out: &mut W,
+    ) -> fmt::Result {
         for ch in s.chars() {
             match ch {
-                ']' | '\\' | '^' | '-' => result.push_str(&format!("\\{}", ch)),
-                '\n' => result.push_str("\\n"),
-                '\r' => result.push_str("\\r"),
-                '\t' => result.push_str("\\t"),
-                _ => result.push(ch),
+                '^' if !position.leading => out.write_char('^')?,
+                '-' if position.edge => out.write_char('-')?,
+                ']' | '\\' | '^' | '-' => {
+                    out.write_char('\\')?;
+                    out.write_char(ch)?;
+                }
+                '\n' => out.write_str("\\n")?,
+                '\r' => out.write_str("\\r")?,
+                '\t' => out.write_str("\\t")?,
+                // Inside a class `\b` is backspace, not a word boundary.
+                '\u{0008}' => out.write_str("\\b")?,
+                _ => self.write_plain_char(ch, out)?,
             }
         }
-        result
+        Ok(())
+    }
+
+    /// Write a character PCRE2 takes literally, applying the escape policy
+    fn write_plain_char<W: fmt::Write + ?Sized>(&self, ch: char, out: &mut W) -> fmt::Result {
+        if self.escape_policy.hex_non_ascii && !ch.is_ascii() {
+            write!(out, "\\x{{{:X}}}", ch as u32)
+        } else if self.escape_policy.escape_slash && ch == '/' {
+            out.write_str("\\/")
+        } else if (self.escape_trivia || self.flags_directive && self.flags.extended) && (ch == ' ' || ch == '#') {
+            out.write_char('\\')?;
+            out.write_char(ch)
+        } else if self.escape_trivia && ch.is_whitespace() {
+            write!(out, "\\x{{{:X}}}", ch as u32)
+        } else {
+            out.write_char(ch)
+        }
     }
 
     /// Get the flags string for the pattern
     pub fn get_flags_string(&self) -> String {
-        let mut flags = String::new();
-        if self.flags.ignore_case {
-            flags.push('i');
-        }
-        if self.flags.multiline {
-            flags.push('m');
-        }
-        if self.flags.dot_all {
-            flags.push('s');
-        }
-        if self.flags.unicode {
-            flags.push('u');
-        }
-        if self.flags.extended {
-            flags.push('x');
+        self.flags.to_flag_string()
+    }
+}
+
+/// The opening of a group, e.g. `(?<name>`
+fn group_opener(group: &IRGroup) -> String {
+    if group.atomic {
+        "(?>".to_string()
+    } else if let Some(name) = &group.name {
+        format!("(?<{}>", name)
+    } else if !group.capturing {
+        "(?:".to_string()
+    } else {
+        "(".to_string()
+    }
+}
+
+/// The number of the group `backref` refers to, if it exists
+fn backref_target(backref: &IRBackref, captures: &CaptureMap) -> Option<usize> {
+    match (&backref.by_name, backref.by_index) {
+        (Some(name), _) => captures.number(name),
+        (None, Some(index)) => usize::try_from(index).ok(),
+        (None, None) => None,
+    }
+}
+
+/// Renumber the capturing groups of `op` to their position in `kept`,
+/// making the others non-capturing, and point backreferences at the new
+/// numbers; with `strip_names`, drop group names and refer by number
+fn rewrite_captures(op: &mut IROp, captures: &CaptureMap, kept: &[usize], strip_names: bool) {
+    let renumbered = |index: usize| kept.iter().position(|&k| k == index).map(|pos| pos + 1);
+    match op {
+        IROp::Group(group) if group.capturing => match renumbered(group.index) {
+            Some(index) => {
+                group.index = index;
+                if strip_names {
+                    group.name = None;
+                }
+            }
+            None => {
+                group.capturing = false;
+                group.name = None;
+                group.index = 0;
+            }
+        },
+        IROp::Backref(backref) if strip_names || backref.by_name.is_none() => {
+            if let Some(index) = backref_target(backref, captures).and_then(renumbered) {
+                backref.by_index = i32::try_from(index).ok();
+                backref.by_name = None;
+            }
         }
-        flags
+        _ => {}
+    }
+    for child in op.children_mut() {
+        rewrite_captures(child, captures, kept, strip_names);
     }
 }
 
+/// The opening of a .NET balancing group, e.g. `(?<close-open>`
+fn balancing_opener(group: &IRBalancingGroup) -> String {
+    format!("(?<{}-{}>", group.push.as_deref().unwrap_or(""), group.pop)
+}
+
+/// The opening of a lookaround, e.g. `(?<!`
+fn look_opener(look: &IRLook) -> &'static str {
+    match (look.dir, look.neg) {
+        (LookDir::Ahead, false) => "(?=",
+        (LookDir::Ahead, true) => "(?!",
+        (LookDir::Behind, false) => "(?<=",
+        (LookDir::Behind, true) => "(?<!",
+    }
+}
+
+/// Whether `op` has no alternation, group or lookaround inside, so
+/// `emit_extended` keeps it on one line
+fn is_flat(op: &IROp) -> bool {
+    match op {
+        IROp::Seq(seq) => seq.parts.iter().all(is_flat),
+        IROp::Quant(quant) => is_flat(&quant.child),
+        IROp::Alt(_) | IROp::Group(_) | IROp::Look(_) | IROp::BranchReset(_) | IROp::BalancingGroup(_) => false,
+        _ => true,
+    }
+}
+
+/// `ir` between `\A` and/or `\z`, with a top-level alternation grouped
+fn anchored_ir(ir: &IROp, anchored: Anchored) -> IROp {
+    let body = match ir {
+        IROp::Alt(_) => IROp::Group(IRGroup {
+            capturing: false,
+            name: None,
+            atomic: false,
+            index: 0,
+            body: Box::new(ir.clone()),
+            span: Default::default(),
+        }),
+        _ => ir.clone(),
+    };
+    let anchor = |at| IROp::Anchor(IRAnchor { at, span: Default::default() });
+    let mut parts = vec![body];
+    if anchored != Anchored::End {
+        parts.insert(0, anchor(AnchorKind::AbsoluteStart));
+    }
+    if anchored != Anchored::Start {
+        parts.push(anchor(AnchorKind::AbsoluteEnd));
+    }
+    IROp::Seq(IRSeq { parts, span: Default::default() })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::emitters::GroupMapping;
 
     #[test]
     fn test_emit_literal() {
         let emitter = PCRE2Emitter::new(Flags::default());
         let ir = IROp::Lit(IRLit {
-            value: "test".to_string(),
+            value: "test".into(),
+            span: Default::default(),
         });
         assert_eq!(emitter.emit(&ir), "test");
     }
@@ -217,15 +820,75 @@ mod tests {
     #[test]
     fn test_emit_dot() {
         let emitter = PCRE2Emitter::new(Flags::default());
-        let ir = IROp::Dot(IRDot {});
+        let ir = IROp::Dot(IRDot { dotall: false, not_newline: false, span: Default::default() });
         assert_eq!(emitter.emit(&ir), ".");
     }
 
+    #[test]
+    fn test_emit_dotall_dot_without_global_flag() {
+        let emitter = PCRE2Emitter::new(Flags::default());
+        let ir = IROp::Dot(IRDot { dotall: true, not_newline: false, span: Default::default() });
+        assert_eq!(emitter.emit(&ir), "(?s:.)");
+    }
+
+    #[test]
+    fn test_expand_dotall() {
+        let emit = |src: &str, expand: bool| {
+            let (flags, ast) = crate::core::parser::parse(src).unwrap();
+            let ir = crate::core::compiler::Compiler::new().with_flags(flags.clone()).compile(&ast);
+            let emitter = PCRE2Emitter::new(flags).with_expand_dotall(expand);
+            (emitter.emit(&ir), emitter.external_flags().dot_all)
+        };
+        assert_eq!(emit("%flags s\na.b", true), ("a[\\s\\S]b".to_string(), false));
+        assert_eq!(emit("%flags s\na.b", false), ("a.b".to_string(), true));
+        assert_eq!(emit("a.b", true), ("a.b".to_string(), false));
+        assert_eq!(emit("%flags s\n\\N", true), ("\\N".to_string(), false));
+
+        let re = regex::Regex::new(&emit("%flags s\na.b", true).0).unwrap();
+        assert!(re.is_match("a\nb"));
+    }
+
+    #[test]
+    fn test_capture_options() {
+        let emit = |src: &str, strip_names: bool, noncapturing: bool| {
+            let (flags, ast) = crate::core::parser::parse(src).unwrap();
+            let ir = crate::core::compiler::Compiler::new().compile(&ast);
+            PCRE2Emitter::new(flags)
+                .with_strip_names(strip_names)
+                .with_noncapturing_when_unreferenced(noncapturing)
+                .emit_with_map(&ir)
+        };
+        assert_eq!(emit("(abc)", false, true).pattern, "(?:abc)");
+        assert_eq!(emit("(abc)\\g{1}", false, true).pattern, "(abc)\\g{1}");
+        assert_eq!(emit("(abc)", false, false).pattern, "(abc)");
+
+        let result = emit("(a)(?<x>b)(c)\\k<x>", false, true);
+        assert_eq!(result.pattern, "(?:a)(?<x>b)(?:c)\\k<x>");
+        assert_eq!(result.group_map, vec![GroupMapping { index: 2, name: Some("x".to_string()), emitted_index: 1 }]);
+
+        assert_eq!(emit("(a)(?<x>b)\\k<x>", true, false).pattern, "(a)(b)\\g{2}");
+        assert_eq!(emit("(a)(?<x>b)\\k<x>", true, true).pattern, "(?:a)(b)\\g{1}");
+    }
+
+    #[test]
+    fn test_try_emit_rejects_balancing_groups() {
+        let (flags, ast) = crate::core::parser::parse("(?<open>a)(?<close-open>b)").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        let emitter = PCRE2Emitter::new(flags);
+        assert_eq!(emitter.emit(&ir), "(?<open>a)(?<close-open>b)");
+        assert_eq!(
+            emitter.try_emit(&ir),
+            Err(EmitError::Unsupported { target: "PCRE2".to_string(), construct: "balancing groups".to_string() })
+        );
+        assert_eq!(emitter.try_emit(&ir.children()[0]), Ok("(?<open>a)".to_string()));
+    }
+
     #[test]
     fn test_emit_anchor() {
         let emitter = PCRE2Emitter::new(Flags::default());
         let ir = IROp::Anchor(IRAnchor {
-            at: "Start".to_string(),
+            at: AnchorKind::Start,
+            span: Default::default(),
         });
         assert_eq!(emitter.emit(&ir), "^");
     }
@@ -235,11 +898,13 @@ mod tests {
         let emitter = PCRE2Emitter::new(Flags::default());
         let ir = IROp::Quant(IRQuant {
             child: Box::new(IROp::Lit(IRLit {
-                value: "a".to_string(),
+                value: "a".into(),
+                span: Default::default(),
             })),
             min: 0,
             max: IRMaxBound::Infinite("Inf".to_string()),
-            mode: "Greedy".to_string(),
+            mode: QuantMode::Greedy,
+            span: Default::default(),
         });
         assert_eq!(emitter.emit(&ir), "a*");
     }
@@ -251,26 +916,420 @@ mod tests {
             capturing: true,
             name: None,
             atomic: false,
+            index: 1,
             body: Box::new(IROp::Lit(IRLit {
-                value: "test".to_string(),
+                value: "test".into(),
+                span: Default::default(),
             })),
+            span: Default::default(),
         });
         assert_eq!(emitter.emit(&ir), "(test)");
     }
 
+    #[test]
+    fn test_emit_ascii_shorthands() {
+        let (flags, ast) = crate::core::parser::parse("%flags a\n\\w\\D\\s[\\d.]").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        assert_eq!(
+            PCRE2Emitter::new(flags).emit(&ir),
+            "[A-Za-z0-9_][^0-9][\\t\\n\\x0B\\f\\r ][0-9.]"
+        );
+    }
+
+    #[test]
+    fn test_emit_python_named_group_canonically() {
+        let (flags, ast) = crate::core::parser::parse("(?P<x>a)(?P=x)").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        assert_eq!(PCRE2Emitter::new(flags).emit(&ir), "(?<x>a)\\k<x>");
+    }
+
+    #[test]
+    fn test_emit_anchored_alternation() {
+        let (flags, ast) = crate::core::parser::parse("a|b").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        let pattern = PCRE2Emitter::new(flags).with_anchors(Anchored::Both).emit(&ir);
+        assert_eq!(pattern, "\\A(?:a|b)\\z");
+
+        let re = regex::Regex::new(&pattern).unwrap();
+        assert!(re.is_match("a"));
+        assert!(!re.is_match("ab"));
+    }
+
+    #[test]
+    fn test_emit_anchored_start_only() {
+        let ir = IROp::Lit(IRLit { value: "ab".into(), span: Default::default() });
+        let emitter = PCRE2Emitter::new(Flags::default()).with_anchors(Anchored::Start);
+        assert_eq!(emitter.emit(&ir), "\\Aab");
+    }
+
+    #[test]
+    fn test_emit_gnu_word_anchors() {
+        let emitter = PCRE2Emitter::new(Flags::default());
+        let start = IROp::Anchor(IRAnchor { at: AnchorKind::WordStart, span: Default::default() });
+        let end = IROp::Anchor(IRAnchor { at: AnchorKind::WordEnd, span: Default::default() });
+        assert_eq!(emitter.emit(&start), "\\b(?=\\w)");
+        assert_eq!(emitter.emit(&end), "(?<=\\w)\\b");
+    }
+
+    #[test]
+    fn test_emit_to_matches_emit() {
+        let (flags, ast) = crate::core::parser::parse("^(?<x>a|[b-]+?)\\k<x>\\d{2,}(?!\\.)$").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        for anchored in [Anchored::None, Anchored::Start, Anchored::End, Anchored::Both] {
+            let emitter = PCRE2Emitter::new(flags.clone()).with_anchors(anchored);
+            let mut out = String::from("prefix:");
+            emitter.emit_to(&ir, &mut out).unwrap();
+            assert_eq!(out, format!("prefix:{}", emitter.emit(&ir)));
+
+            let mut buffer = String::new();
+            let sink: &mut dyn fmt::Write = &mut buffer;
+            emitter.emit_to(&ir, sink).unwrap();
+            assert_eq!(buffer, emitter.emit(&ir));
+        }
+    }
+
+    #[test]
+    fn test_flags_accessors() {
+        let (flags, _) = crate::core::parser::parse("%flags imxa\na").unwrap();
+        let emitter = PCRE2Emitter::new(flags.clone());
+        assert_eq!(emitter.flags(), &flags);
+        assert!(emitter.inline_flags().extended && emitter.inline_flags().ascii);
+        assert!(!emitter.inline_flags().ignore_case);
+        assert!(emitter.external_flags().ignore_case && emitter.external_flags().multiline);
+        assert!(!emitter.external_flags().extended);
+        assert_eq!(emitter.get_flags_string(), "imuxa");
+    }
+
+    #[test]
+    fn test_unicode_flag_prefix() {
+        let (flags, ast) = crate::core::parser::parse("%flags u\na").unwrap();
+        assert_eq!(flags, Flags::default());
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        let emitter = PCRE2Emitter::new(flags).with_anchors(Anchored::Both);
+        assert_eq!(emitter.emit(&ir), "\\Aa\\z");
+        assert!(!emitter.inline_flags().unicode && emitter.external_flags().unicode);
+
+        let verbs = emitter.with_unicode_verbs(true);
+        assert_eq!(verbs.emit(&ir), "(*UTF)(*UCP)\\Aa\\z");
+        assert!(verbs.inline_flags().unicode && !verbs.external_flags().unicode);
+
+        let (flags, _) = crate::core::parser::parse("%flags ua\na").unwrap();
+        let ascii = PCRE2Emitter::new(flags).with_unicode_verbs(true);
+        assert!(!ascii.inline_flags().unicode && !ascii.external_flags().unicode);
+        assert_eq!(ascii.emit(&ir), "a");
+    }
+
+    #[test]
+    fn test_emit_previous_match_end() {
+        let emitter = PCRE2Emitter::new(Flags::default());
+        let ir = IROp::Anchor(IRAnchor { at: AnchorKind::PreviousMatchEnd, span: Default::default() });
+        assert_eq!(emitter.emit(&ir), "\\G");
+    }
+
+    #[test]
+    fn test_fold_case_in_classes() {
+        let class = |items| IROp::CharClass(IRCharClass { negated: false, items, span: Default::default() });
+        let range = |from: &str, to: &str| IRClassItem::Range(IRClassRange {
+            from_ch: from.to_string(),
+            to_ch: to.to_string(),
+        });
+        let ch = |c: &str| IRClassItem::Char(IRClassLiteral { ch: c.to_string() });
+        let flags = Flags { ignore_case: true, ..Flags::none() };
+        let emitter = PCRE2Emitter::new(flags.clone()).with_fold_case_in_classes(true);
+
+        let pattern = emitter.emit(&class(vec![range("a", "c")]));
+        assert_eq!(pattern, "[a-cA-C]");
+        let re = regex::Regex::new(&pattern).unwrap();
+        assert!(re.is_match("B") && !re.is_match("D"));
+
+        assert_eq!(emitter.emit(&class(vec![range("X", "b"), ch("é")])), "[X-béA-Bx-z]");
+        assert_eq!(emitter.emit(&class(vec![range("a", "z"), range("A", "Z")])), "[a-zA-Z]");
+
+        let unicode = PCRE2Emitter::new(Flags { unicode: true, ..flags })
+            .with_fold_case_in_classes(true);
+        assert_eq!(unicode.emit(&class(vec![ch("é")])), "[éÉ]");
+
+        // Without ignore_case the option does nothing.
+        let plain = PCRE2Emitter::new(Flags::default()).with_fold_case_in_classes(true);
+        assert_eq!(plain.emit(&class(vec![range("a", "c")])), "[a-c]");
+    }
+
+    #[test]
+    fn test_emit_backspace_in_class() {
+        let (flags, ast) = crate::core::parser::parse("[\\b]\\bword").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        assert_eq!(PCRE2Emitter::new(flags).emit(&ir), "[\\b]\\bword");
+    }
+
+    #[test]
+    fn test_emit_with_map_is_identity() {
+        let (flags, ast) = crate::core::parser::parse("(a)(?<b>c)").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        let emitter = PCRE2Emitter::new(flags);
+        let result = emitter.emit_with_map(&ir);
+        assert_eq!(result.pattern, emitter.emit(&ir));
+        let mapping = |index, name: Option<&str>| GroupMapping {
+            index,
+            name: name.map(str::to_string),
+            emitted_index: index,
+        };
+        assert_eq!(result.group_map, vec![mapping(1, None), mapping(2, Some("b"))]);
+        assert_eq!(result.source_group(2), Some(&result.group_map[1]));
+        assert_eq!(result.source_group(3), None);
+    }
+
+    #[test]
+    fn test_emit_empty_classes() {
+        let class = |negated| IROp::CharClass(IRCharClass { negated, items: Vec::new(), span: Default::default() });
+        let emitter = PCRE2Emitter::new(Flags::default());
+        assert_eq!(emitter.emit(&class(false)), "(?!)");
+        assert_eq!(emitter.emit(&class(true)), "(?s:.)");
+    }
+
+    #[test]
+    fn test_class_escaping_is_minimal() {
+        for (src, expected) in [
+            ("[-a]", "[-a]"),
+            ("[a-]", "[a-]"),
+            ("[a\\-z]", "[a\\-z]"),
+            ("[\\]a]", "[\\]a]"),
+            ("[^\\]a]", "[^\\]a]"),
+            ("[]a]", "[\\]a]"),
+            ("[^]a]", "[^\\]a]"),
+            ("[\\^a]", "[\\^a]"),
+            ("[a^]", "[a^]"),
+            ("[^^]", "[^^]"),
+            ("[\\\\\\d]", "[\\\\\\d]"),
+            ("[!--]", "[!-\\-]"),
+            ("[\\--/]", "[\\--/]"),
+        ] {
+            let (flags, ast) = crate::core::parser::parse(src).unwrap();
+            let ir = crate::core::compiler::Compiler::new().compile(&ast);
+            let pattern = PCRE2Emitter::new(flags).emit(&ir);
+            assert_eq!(pattern, expected, "{:?}", src);
+            assert!(regex::Regex::new(&pattern).is_ok(), "{:?}", pattern);
+        }
+    }
+
     #[test]
     fn test_emit_alternation() {
         let emitter = PCRE2Emitter::new(Flags::default());
         let ir = IROp::Alt(IRAlt {
             branches: vec![
                 IROp::Lit(IRLit {
-                    value: "a".to_string(),
+                    value: "a".into(),
+                    span: Default::default(),
                 }),
                 IROp::Lit(IRLit {
-                    value: "b".to_string(),
+                    value: "b".into(),
+                    span: Default::default(),
                 }),
             ],
+            span: Default::default(),
         });
         assert_eq!(emitter.emit(&ir), "a|b");
     }
+
+    #[test]
+    fn test_escape_policy() {
+        let ir = IROp::Seq(IRSeq {
+            parts: vec![
+                IROp::Lit(IRLit { value: "a/b é".into(), span: Default::default() }),
+                IROp::CharClass(IRCharClass {
+                    negated: false,
+                    items: vec![IRClassItem::Char(IRClassLiteral { ch: "/".to_string() })],
+                    span: Default::default(),
+                }),
+            ],
+            span: Default::default(),
+        });
+        assert_eq!(PCRE2Emitter::new(Flags::default()).emit(&ir), "a/b é[/]");
+
+        let slash = EscapePolicy { escape_slash: true, ..EscapePolicy::default() };
+        let emitter = PCRE2Emitter::new(Flags::default()).with_escape_policy(slash);
+        assert_eq!(emitter.emit(&ir), "a\\/b é[\\/]");
+
+        let hex = EscapePolicy { hex_non_ascii: true, ..EscapePolicy::default() };
+        let emitter = PCRE2Emitter::new(Flags::default()).with_escape_policy(hex);
+        assert_eq!(emitter.emit(&ir), "a/b \\x{E9}[/]");
+    }
+
+    #[test]
+    fn test_emit_astral_class_range() {
+        let (flags, ast) = crate::core::parser::parse("[😀-😇]").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        let pattern = PCRE2Emitter::new(flags.clone()).emit(&ir);
+        assert_eq!(pattern, "[😀-😇]");
+        let re = regex::Regex::new(&pattern).unwrap();
+        assert!(re.is_match("😃"));
+        assert!(!re.is_match("😈"));
+
+        let hex = EscapePolicy { hex_non_ascii: true, ..EscapePolicy::default() };
+        let pattern = PCRE2Emitter::new(flags).with_escape_policy(hex).emit(&ir);
+        assert_eq!(pattern, "[\\x{1F600}-\\x{1F607}]");
+    }
+
+    #[test]
+    fn test_ascii_safe_output() {
+        let (flags, ast) = crate::core::parser::parse("café[😀a-z]+|naïve").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        let raw = PCRE2Emitter::new(flags.clone()).emit(&ir);
+        let safe = PCRE2Emitter::new(flags).with_ascii_safe(true).emit(&ir);
+        assert_eq!(safe, "caf\\x{E9}[\\x{1F600}a-z]+|na\\x{EF}ve");
+        assert!(safe.is_ascii());
+
+        let (raw, safe) = (regex::Regex::new(&raw).unwrap(), regex::Regex::new(&safe).unwrap());
+        for text in ["café😀", "cafézz", "naïve", "cafe😀", "naive", "caf\\x{E9}a"] {
+            assert_eq!(raw.find(text).map(|m| m.range()), safe.find(text).map(|m| m.range()), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_relative_backref_resolved() {
+        let emit = |src: &str| {
+            let (flags, ast) = crate::core::parser::parse(src).unwrap();
+            PCRE2Emitter::new(flags).emit(&crate::core::compiler::Compiler::new().compile(&ast))
+        };
+        assert_eq!(emit("(a)(b)\\g{-1}"), "(a)(b)\\g{2}");
+        assert_eq!(emit("(a)(b(c)\\g{-2})"), "(a)(b(c)\\g{2})");
+        assert_eq!(emit("(?|(a)|(b))(c)\\g{-1}"), "(?|(a)|(b))(c)\\g{2}");
+        // The braces keep a following digit out of the group number.
+        assert_eq!(emit("(a)\\g{1}2"), "(a)\\g{1}2");
+    }
+
+    #[test]
+    fn test_class_intersection_becomes_lookahead() {
+        let emit = |src: &str| {
+            let (flags, ast) = crate::core::parser::parse(src).unwrap();
+            PCRE2Emitter::new(flags).emit(&crate::core::compiler::Compiler::new().compile(&ast))
+        };
+        assert_eq!(emit("[\\p{L}&&\\p{ASCII}]+"), "(?:(?=[\\p{L}])[\\p{ASCII}])+");
+        assert_eq!(emit("[a-z&&[^aeiou]&&b-y]"), "(?:(?=[a-z])(?=[^aeiou])[b-y])");
+        assert_eq!(emit("[^a-z&&[aeiou]]"), "(?:(?!(?=[a-z])[aeiou])(?s:.))");
+
+        // A hand-built class may mix intersections with other members.
+        let set = |ch: &str| IRCharClass {
+            negated: false,
+            items: vec![IRClassItem::Char(IRClassLiteral { ch: ch.to_string() })],
+            span: Default::default(),
+        };
+        let mixed = IROp::CharClass(IRCharClass {
+            negated: false,
+            items: vec![
+                IRClassItem::Char(IRClassLiteral { ch: "x".to_string() }),
+                IRClassItem::Intersection(IRClassIntersection { sets: vec![set("a"), set("b")] }),
+            ],
+            span: Default::default(),
+        });
+        assert_eq!(PCRE2Emitter::new(Flags::default()).emit(&mixed), "(?:[x]|(?=[a])[b])");
+    }
+
+    #[test]
+    fn test_emit_branch_reset() {
+        let (flags, ast) = crate::core::parser::parse("(?|(a)|x(b))").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        assert_eq!(PCRE2Emitter::new(flags).emit(&ir), "(?|(a)|x(b))");
+    }
+
+    #[test]
+    fn test_flags_directive_round_trip() {
+        let (flags, ast) = crate::core::parser::parse("%flags i,m\nx").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        let emitted = PCRE2Emitter::new(flags.clone()).with_flags_directive(true).emit(&ir);
+        assert_eq!(emitted, "%flags im\nx");
+        let (reparsed, _) = crate::core::parser::parse(&emitted).unwrap();
+        assert_eq!(reparsed, flags);
+
+        let plain = PCRE2Emitter::new(Flags::default()).with_flags_directive(true);
+        assert_eq!(plain.emit(&ir), "x");
+    }
+
+    #[test]
+    fn test_flags_directive_keeps_extended_literals() {
+        let (flags, ast) = crate::core::parser::parse("%flags xu\na\\ \\#b").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        let emitted = PCRE2Emitter::new(flags.clone()).with_flags_directive(true).emit(&ir);
+        assert_eq!(emitted, "%flags x\na\\ \\#b");
+        let (reparsed, reparsed_ast) = crate::core::parser::parse(&emitted).unwrap();
+        assert_eq!((reparsed, reparsed_ast), (flags, ast));
+    }
+
+    #[test]
+    fn test_inline_extended_flag_not_emitted() {
+        let emit = |src: &str| {
+            let (flags, ast) = crate::core::parser::parse(src).unwrap();
+            PCRE2Emitter::new(flags).emit(&crate::core::compiler::Compiler::new().compile(&ast))
+        };
+        // With `(?x)` kept, the engine would drop the space and read `#b`
+        // as a comment.
+        assert_eq!(emit("(?x)a\\ b"), "a b");
+        assert_eq!(emit("(?x)a\\#b"), "a#b");
+        assert_eq!(emit("(?ix)a\\ b(?-x)c"), "(?i)a bc");
+    }
+
+    #[test]
+    fn test_emit_extended_layout() {
+        let (flags, ast) = crate::core::parser::parse("ab(?:c|(?<x>d[ #]+|e# f))?(?=g)").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        let emitter = PCRE2Emitter::new(flags);
+        let extended = emitter.emit_extended(&ir);
+        assert_eq!(
+            extended,
+            "(?x)\nab (?:\n    c\n    | (?<x>\n        d [\\ \\#]+\n        | e\\#\\ f\n    )\n)? (?=g)"
+        );
+
+        // Dropping the layout whitespace gives back the compact form.
+        let mut stripped = String::new();
+        let mut chars = extended["(?x)".len()..].chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' => {
+                    let next = chars.next().unwrap();
+                    if !matches!(next, ' ' | '#') {
+                        stripped.push('\\');
+                    }
+                    stripped.push(next);
+                }
+                ' ' | '\n' => {}
+                _ => stripped.push(ch),
+            }
+        }
+        assert_eq!(stripped, emitter.emit(&ir));
+
+        // The regex crate has no lookaround, so compare matches without it.
+        let ir = crate::core::compiler::Compiler::new().compile(&crate::core::parser::parse("ab(?:c|(?<x>d[ #]+|e# f))?g").unwrap().1);
+        let (compact, laid_out) = (regex::Regex::new(&emitter.emit(&ir)).unwrap(), regex::Regex::new(&emitter.emit_extended(&ir)).unwrap());
+        for text in ["abcg", "abd #g", "abe# fg", "ab g", "abe#fg", "abg"] {
+            assert_eq!(compact.find(text).map(|m| m.range()), laid_out.find(text).map(|m| m.range()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_emit_extended_anchored() {
+        let ir = crate::core::compiler::Compiler::new().compile(&crate::core::parser::parse("a|b").unwrap().1);
+        let emitter = PCRE2Emitter::new(Flags::default()).with_anchors(Anchored::Both);
+        assert_eq!(emitter.emit_extended(&ir), "(?x)\n\\A (?:\n    a\n    | b\n) \\z");
+    }
+
+    #[test]
+    fn test_backtracking_verbs_emitted_verbatim() {
+        for src in ["a(*ACCEPT)b", "a+(*SKIP:label)b|c", "(*:m)a(*F)"] {
+            let ir = crate::core::compiler::Compiler::new().compile(&crate::core::parser::parse(src).unwrap().1);
+            assert_eq!(PCRE2Emitter::new(Flags::default()).emit(&ir), src);
+        }
+    }
+
+    #[test]
+    fn test_extended_comments_stripped() {
+        let (_, ast) = crate::core::parser::parse("%flags x\nfoo # the foo\n| bar # the bar").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        assert_eq!(PCRE2Emitter::new(Flags::default()).emit(&ir), "foo|bar");
+    }
+
+    #[test]
+    fn test_brace_quantifier_without_min_emits_canonical_form() {
+        let (_, ast) = crate::core::parser::parse("a{,3}").unwrap();
+        let ir = crate::core::compiler::Compiler::new().compile(&ast);
+        assert_eq!(PCRE2Emitter::new(Flags::default()).emit(&ir), "a{0,3}");
+    }
 }
@@ -0,0 +1,132 @@
+//! PHP Emitter - Generate delimited patterns for PHP's `preg_*` functions
+//!
+//! PHP's PCRE functions take the pattern and its options as one string:
+//! the PCRE2 pattern between two delimiters, followed by flag letters, as
+//! in `/pattern/im`. This module wraps the output of `PCRE2Emitter` in that
+//! form.
+
+use crate::core::errors::EmitError;
+use crate::core::ir::IROp;
+use crate::core::nodes::Flags;
+use crate::emitters::pcre2::PCRE2Emitter;
+
+const TARGET: &str = "PHP";
+
+/// Delimiters that mean the same escaped or not anywhere in a pattern
+const DELIMITERS: &str = "/#~%@;`";
+
+/// Emitter for PHP `preg_*` patterns such as `/a\/b/i`
+#[derive(Debug, Clone)]
+pub struct PhpEmitter {
+    inner: PCRE2Emitter,
+    delimiter: char,
+}
+
+impl PhpEmitter {
+    /// Create a new PHP emitter with the given flags, delimited by `/`
+    pub fn new(flags: Flags) -> Self {
+        Self {
+            inner: PCRE2Emitter::new(flags),
+            delimiter: '/',
+        }
+    }
+
+    /// Delimit the pattern with `delimiter` instead of `/`
+    ///
+    /// Occurrences of the delimiter in the pattern are escaped, so it must
+    /// be a character that means the same escaped or not anywhere in a
+    /// PCRE2 pattern: one of `` / # ~ % @ ; ` ``.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EmitError::Unsupported` for any other delimiter. Characters
+    /// such as `=`, `,`, `-` and `:` change meaning when escaped inside
+    /// constructs like `(?=`, `{2,3}` or `[c-e]`, and bracket pairs such as
+    /// `<` would need a different closing delimiter.
+    pub fn with_delimiter(mut self, delimiter: char) -> Result<Self, EmitError> {
+        if !DELIMITERS.contains(delimiter) {
+            return Err(EmitError::Unsupported {
+                target: TARGET.to_string(),
+                construct: format!("{:?} as a delimiter", delimiter),
+            });
+        }
+        self.delimiter = delimiter;
+        Ok(self)
+    }
+
+    /// The flags this emitter was constructed with
+    pub fn flags(&self) -> &Flags {
+        self.inner.flags()
+    }
+
+    /// Emit a delimited PHP pattern from IR
+    ///
+    /// The trailing flag letters are those PCRE2 takes as compile options:
+    /// `i`, `m`, `s`, and `u` for Unicode mode unless ASCII mode overrides
+    /// it. Other flags are already reflected in the pattern.
+    pub fn emit(&self, ir: &IROp) -> String {
+        let body = self.inner.emit(ir);
+        let mut out = String::with_capacity(body.len() + 8);
+        out.push(self.delimiter);
+        let mut chars = body.chars();
+        while let Some(ch) = chars.next() {
+            if ch == '\\' {
+                // Already escaped; the next character is taken literally.
+                out.push(ch);
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+                continue;
+            }
+            if ch == self.delimiter {
+                out.push('\\');
+            }
+            out.push(ch);
+        }
+        out.push(self.delimiter);
+        out.push_str(&self.inner.external_flags().to_flag_string());
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::compiler::Compiler;
+    use crate::core::parser::parse;
+
+    fn php(src: &str, delimiter: char) -> String {
+        let (flags, ast) = parse(src).unwrap();
+        PhpEmitter::new(flags).with_delimiter(delimiter).unwrap().emit(&Compiler::new().compile(&ast))
+    }
+
+    #[test]
+    fn test_delimiter_escaped() {
+        assert_eq!(php("a/b[/]", '/'), "/a\\/b[\\/]/u");
+        assert_eq!(php("a/b#", '#'), "#a/b\\##u");
+        assert_eq!(php("%flags x\na\\ #b", '#'), "#a #u");
+    }
+
+    #[test]
+    fn test_flag_letters() {
+        assert_eq!(php("%flags i\nab", '/'), "/ab/iu");
+        assert_eq!(php("%flags ims\na.b", '~'), "~a.b~imsu");
+        assert_eq!(php("%flags u\na", '/'), "/a/u");
+        assert_eq!(php("%flags a\na", '/'), "/a/");
+    }
+
+    #[test]
+    fn test_unsafe_delimiters_rejected() {
+        for delimiter in ['|', '=', ',', '-', ':', '<', 'a', ' ', '\\'] {
+            let result = PhpEmitter::new(Flags::default()).with_delimiter(delimiter);
+            assert!(
+                matches!(result, Err(EmitError::Unsupported { ref construct, .. }) if construct.contains("delimiter")),
+                "{:?}",
+                delimiter
+            );
+        }
+        for delimiter in DELIMITERS.chars() {
+            assert!(PhpEmitter::new(Flags::default()).with_delimiter(delimiter).is_ok(), "{:?}", delimiter);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! regex-automata Emitter - Generate patterns for `regex-automata` DFAs
+//!
+//! `regex-automata` builds its DFAs from patterns parsed by `regex-syntax`,
+//! the same parser the `regex` crate uses, so this emitter writes the syntax
+//! of `RustRegexEmitter`: named groups become `(?P<name>...)`, and constructs
+//! no finite automaton can match (backreferences, lookaround, branch reset
+//! groups, `\Z`, `\G` and backtracking verbs) are reported as an
+//! `EmitError` naming this target.
+//!
+//! DFAs report where a match ends, not what its groups captured; groups are
+//! kept so the same pattern can be rerun with a capturing engine. The lazy
+//! DFA (`hybrid::dfa::DFA`) accepts a Unicode `\b` but gives up with an error
+//! on non-ASCII haystacks, and fully compiled DFAs reject it outright; set
+//! the ASCII flag where word boundaries must work everywhere.
+
+use crate::core::errors::EmitError;
+use crate::core::ir::IROp;
+use crate::core::nodes::Flags;
+use crate::emitters::rust_regex::RustRegexEmitter;
+use crate::emitters::EmitResult;
+
+const TARGET: &str = "regex-automata";
+
+/// Emitter that generates patterns for `regex-automata` DFA builders
+#[derive(Debug, Clone)]
+pub struct RegexAutomataEmitter {
+    inner: RustRegexEmitter,
+}
+
+impl RegexAutomataEmitter {
+    /// Create a new `regex-automata` emitter with the given flags
+    pub fn new(flags: Flags) -> Self {
+        Self { inner: RustRegexEmitter::new(flags) }
+    }
+
+    /// The flags this emitter was constructed with
+    pub fn flags(&self) -> &Flags {
+        self.inner.flags()
+    }
+
+    /// Emit a `regex-automata` pattern from IR
+    ///
+    /// # Errors
+    ///
+    /// Returns `EmitError::Unsupported` naming the first construct a DFA
+    /// cannot express.
+    pub fn emit(&self, ir: &IROp) -> Result<String, EmitError> {
+        self.inner.emit(ir).map_err(retarget)
+    }
+
+    /// Emit a pattern like `emit`, with its capture group numbering, which
+    /// is the identity as for `RustRegexEmitter`
+    ///
+    /// # Errors
+    ///
+    /// As for `emit`.
+    pub fn emit_with_map(&self, ir: &IROp) -> Result<EmitResult, EmitError> {
+        self.inner.emit_with_map(ir).map_err(retarget)
+    }
+}
+
+/// Report an error from the wrapped `regex` emitter against this target
+fn retarget(err: EmitError) -> EmitError {
+    match err {
+        EmitError::Unsupported { construct, .. } => EmitError::Unsupported {
+            target: TARGET.to_string(),
+            construct,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::compiler::Compiler;
+    use crate::core::parser::parse;
+    use regex_automata::hybrid::dfa::DFA;
+    use regex_automata::Input;
+
+    fn emit(src: &str) -> Result<String, EmitError> {
+        let (flags, ast) = parse(src).unwrap();
+        let ir = Compiler::new().with_flags(flags.clone()).compile(&ast);
+        RegexAutomataEmitter::new(flags).emit(&ir)
+    }
+
+    #[test]
+    fn test_supported_pattern_builds_lazy_dfa() {
+        let pattern = emit("(?<area>\\d{3})-(?<line>\\d{4})").unwrap();
+        assert_eq!(pattern, "(?P<area>[\\d]{3})-(?P<line>[\\d]{4})");
+        let dfa = DFA::new(&pattern).unwrap();
+        let mut cache = dfa.create_cache();
+        let found = dfa.try_search_fwd(&mut cache, &Input::new("call 555-1234")).unwrap();
+        assert_eq!(found.map(|m| m.offset()), Some(13));
+        assert_eq!(dfa.try_search_fwd(&mut cache, &Input::new("555-12")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_unsupported_constructs_name_target() {
+        for (src, construct) in [
+            ("(?<x>a)\\k<x>", "backreferences"),
+            ("a(?=b)", "lookaround"),
+            ("a\\Z", "\\Z anchor"),
+        ] {
+            assert_eq!(
+                emit(src),
+                Err(EmitError::Unsupported { target: TARGET.to_string(), construct: construct.to_string() }),
+                "{:?}",
+                src
+            );
+        }
+    }
+}
@@ -0,0 +1,425 @@
+//! Rust `regex` Emitter - Generate patterns for the `regex` crate
+//!
+//! This module implements code generation for the `regex` crate. The crate
+//! guarantees linear-time matching and therefore has no backreferences,
+//! lookaround, branch reset groups or `\Z`; those constructs are reported as an `EmitError`
+//! rather than emitted. So are possessive quantifiers and atomic groups:
+//! their plain equivalents can match where they would not.
+
+use crate::core::errors::EmitError;
+use crate::core::ir::*;
+use crate::core::nodes::Flags;
+use crate::emitters::{ascii_shorthand, EmitResult};
+
+const TARGET: &str = "Rust regex";
+
+/// Emitter that generates patterns accepted by `regex::Regex::new`
+#[derive(Debug, Clone)]
+pub struct RustRegexEmitter {
+    flags: Flags,
+}
+
+impl RustRegexEmitter {
+    /// Create a new Rust `regex` emitter with the given flags
+    pub fn new(flags: Flags) -> Self {
+        Self { flags }
+    }
+
+    /// The flags this emitter was constructed with
+    pub fn flags(&self) -> &Flags {
+        &self.flags
+    }
+
+    /// The subset of `flags()` already reflected in the emitted pattern
+    ///
+    /// Every flag is: `i`, `m` and `s` are inlined, `u` is the crate's
+    /// default, and extended and ASCII modes are resolved during emission.
+    pub fn inline_flags(&self) -> Flags {
+        self.flags.clone()
+    }
+
+    /// The subset of `flags()` the caller must apply itself; always empty
+    pub fn external_flags(&self) -> Flags {
+        Flags::none()
+    }
+
+    /// Emit a `regex` crate pattern from IR
+    ///
+    /// The `i`, `m` and `s` flags are emitted as a leading inline group, since
+    /// `Regex::new` takes no separate flag argument.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EmitError::Unsupported` naming the first construct the
+    /// `regex` crate cannot express.
+    pub fn emit(&self, ir: &IROp) -> Result<String, EmitError> {
+        let flags = self.get_flags_string();
+        let body = self.emit_node(ir)?;
+        if flags.is_empty() {
+            Ok(body)
+        } else {
+            Ok(format!("(?{}){}", flags, body))
+        }
+    }
+
+    /// Emit a `regex` crate pattern like `emit`, with its capture group
+    /// numbering
+    ///
+    /// The crate numbers groups left to right as the compiler does, and
+    /// branch resets are rejected, so the map is the identity.
+    ///
+    /// # Errors
+    ///
+    /// As for `emit`.
+    pub fn emit_with_map(&self, ir: &IROp) -> Result<EmitResult, EmitError> {
+        Ok(EmitResult::numbered_as_compiled(self.emit(ir)?, ir))
+    }
+
+    /// Emit a single IR node
+    fn emit_node(&self, node: &IROp) -> Result<String, EmitError> {
+        Ok(match node {
+            IROp::Lit(lit) => self.emit_literal(&lit.value),
+            IROp::Dot(dot) if dot.not_newline => "[^\\n]".to_string(),
+            IROp::Dot(dot) if dot.dotall && !self.flags.dot_all => "(?s:.)".to_string(),
+            IROp::Dot(_) => ".".to_string(),
+            IROp::SetFlags(set) => set.to_emitted(),
+            IROp::Verb(_) => return Err(unsupported("backtracking control verbs")),
+            IROp::Anchor(anchor) => match anchor.at {
+                AnchorKind::Start => "^".to_string(),
+                AnchorKind::End => "$".to_string(),
+                AnchorKind::WordBoundary => "\\b".to_string(),
+                AnchorKind::NotWordBoundary => "\\B".to_string(),
+                AnchorKind::AbsoluteStart => "\\A".to_string(),
+                AnchorKind::AbsoluteEnd => "\\z".to_string(),
+                AnchorKind::WordStart => "\\<".to_string(),
+                AnchorKind::WordEnd => "\\>".to_string(),
+                AnchorKind::EndBeforeFinalNewline => return Err(unsupported("\\Z anchor")),
+                AnchorKind::PreviousMatchEnd => return Err(unsupported("\\G anchor")),
+            },
+            IROp::Seq(seq) => seq
+                .parts
+                .iter()
+                .map(|p| self.emit_node(p))
+                .collect::<Result<Vec<_>, _>>()?
+                .join(""),
+            IROp::Alt(alt) => alt
+                .branches
+                .iter()
+                .map(|b| self.emit_node(b))
+                .collect::<Result<Vec<_>, _>>()?
+                .join("|"),
+            IROp::Quant(quant) => {
+                let child = self.emit_node(&quant.child)?;
+                let quantifier = match (&quant.max, quant.min) {
+                    (IRMaxBound::Infinite(_), 0) => "*".to_string(),
+                    (IRMaxBound::Infinite(_), 1) => "+".to_string(),
+                    (IRMaxBound::Finite(1), 0) => "?".to_string(),
+                    (IRMaxBound::Infinite(_), min) => format!("{{{},}}", min),
+                    (IRMaxBound::Finite(max), min) if min == *max => format!("{{{}}}", min),
+                    (IRMaxBound::Finite(max), min) => format!("{{{},{}}}", min, max),
+                };
+
+                let mode_suffix = match quant.mode {
+                    QuantMode::Lazy => "?",
+                    QuantMode::Greedy => "",
+                    QuantMode::Possessive => return Err(unsupported("possessive quantifiers")),
+                };
+
+                format!("{}{}{}", child, quantifier, mode_suffix)
+            }
+            IROp::Group(group) if group.atomic => return Err(unsupported("atomic groups")),
+            IROp::Group(group) => {
+                let body = self.emit_node(&group.body)?;
+                if let Some(name) = &group.name {
+                    format!("(?P<{}>{})", name, body)
+                } else if group.capturing {
+                    format!("({})", body)
+                } else {
+                    format!("(?:{})", body)
+                }
+            }
+            IROp::BranchReset(_) => return Err(unsupported("branch reset groups")),
+            IROp::BalancingGroup(_) => return Err(unsupported("balancing groups")),
+            IROp::Look(_) => return Err(unsupported("lookaround")),
+            IROp::Backref(_) => return Err(unsupported("backreferences")),
+            IROp::CharClass(cc) => self.emit_class(cc),
+        })
+    }
+
+    /// Emit a character class
+    fn emit_class(&self, cc: &IRCharClass) -> String {
+        match cc.items.as_slice() {
+            [IRClassItem::Esc(esc)] if self.flags.ascii => {
+                if let Some(set) = ascii_shorthand(&esc.escape_type.to_lowercase()) {
+                    let negated = cc.negated != esc.escape_type.chars().all(|c| c.is_uppercase());
+                    return format!("[{}{}]", if negated { "^" } else { "" }, set);
+                }
+            }
+            // The crate rejects `[]`; spell the empty class as a set
+            // with nothing outside it.
+            [] => return if cc.negated { "[\\s\\S]" } else { "[^\\s\\S]" }.to_string(),
+            // A lone intersection needs no brackets of its own.
+            [IRClassItem::Intersection(intersection)] => {
+                return format!("[{}{}]", if cc.negated { "^" } else { "" }, self.emit_intersection(intersection));
+            }
+            _ => {}
+        }
+        let mut result = String::from("[");
+        if cc.negated {
+            result.push('^');
+        }
+        for item in &cc.items {
+            result.push_str(&self.emit_class_item(item));
+        }
+        result.push(']');
+        result
+    }
+
+    /// Emit the operands of a class intersection joined by `&&`
+    fn emit_intersection(&self, intersection: &IRClassIntersection) -> String {
+        intersection.sets.iter().map(|set| self.emit_class(set)).collect::<Vec<_>>().join("&&")
+    }
+
+    /// Emit a character class item
+    fn emit_class_item(&self, item: &IRClassItem) -> String {
+        match item {
+            IRClassItem::Char(lit) => self.escape_class_char(&lit.ch),
+            IRClassItem::Range(range) => {
+                format!("{}-{}",
+                    self.escape_class_char(&range.from_ch),
+                    self.escape_class_char(&range.to_ch))
+            }
+            IRClassItem::Esc(esc) => {
+                if self.flags.ascii {
+                    if let Some(set) = ascii_shorthand(&esc.escape_type) {
+                        return set.to_string();
+                    }
+                }
+                match esc.escape_type.as_str() {
+                    "p" => format!("\\p{{{}}}", esc.property.as_deref().unwrap_or("")),
+                    "P" => format!("\\P{{{}}}", esc.property.as_deref().unwrap_or("")),
+                    other => format!("\\{}", other),
+                }
+            }
+            IRClassItem::Intersection(intersection) => format!("[{}]", self.emit_intersection(intersection)),
+        }
+    }
+
+    /// Escape a literal string for the `regex` crate
+    fn emit_literal(&self, s: &str) -> String {
+        let mut result = String::new();
+        for ch in s.chars() {
+            match ch {
+                '.' | '*' | '+' | '?' | '^' | '$' | '|' | '(' | ')' | '[' | ']' | '{' | '}' | '\\' | '#' => {
+                    result.push('\\');
+                    result.push(ch);
+                }
+                '\n' => result.push_str("\\n"),
+                '\r' => result.push_str("\\r"),
+                '\t' => result.push_str("\\t"),
+                '\u{000C}' => result.push_str("\\f"),
+                '\u{000B}' => result.push_str("\\v"),
+                _ => result.push(ch),
+            }
+        }
+        result
+    }
+
+    /// Escape a character for use inside a character class
+    ///
+    /// Besides the usual metacharacters this escapes `&`, `~` and `[`, which
+    /// the crate reserves for class set operations.
+    fn escape_class_char(&self, s: &str) -> String {
+        let mut result = String::new();
+        for ch in s.chars() {
+            match ch {
+                ']' | '[' | '\\' | '^' | '-' | '&' | '~' => {
+                    result.push('\\');
+                    result.push(ch);
+                }
+                '\n' => result.push_str("\\n"),
+                '\r' => result.push_str("\\r"),
+                '\t' => result.push_str("\\t"),
+                // The crate has no `\b` backspace escape in classes.
+                '\u{0008}' => result.push_str("\\x08"),
+                _ => result.push(ch),
+            }
+        }
+        result
+    }
+
+    /// Get the inline flags string for the pattern
+    ///
+    /// Extended mode is not forwarded: whitespace and comments were already
+    /// consumed by the parser, so the IR holds only significant characters.
+    pub fn get_flags_string(&self) -> String {
+        let mut flags = String::new();
+        if self.flags.ignore_case {
+            flags.push('i');
+        }
+        if self.flags.multiline {
+            flags.push('m');
+        }
+        if self.flags.dot_all {
+            flags.push('s');
+        }
+        flags
+    }
+}
+
+fn unsupported(construct: &str) -> EmitError {
+    EmitError::Unsupported {
+        target: TARGET.to_string(),
+        construct: construct.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::compiler::Compiler;
+    use crate::core::parser::parse;
+    use regex::Regex;
+
+    fn emit(src: &str) -> Result<String, EmitError> {
+        let (flags, ast) = parse(src).unwrap();
+        let ir = Compiler::new().with_flags(flags.clone()).compile(&ast);
+        RustRegexEmitter::new(flags).emit(&ir)
+    }
+
+    #[test]
+    fn test_output_compiles() {
+        for src in [
+            "hello",
+            "a.b*c+?",
+            "^(?:ab|cd)$",
+            "(x)(?<word>\\w+)\\s\\D",
+            "a&&b~~c#",
+            "[&~-]",
+            "%flags ims\n.",
+            "%flags a\n\\w\\S",
+        ] {
+            let pattern = emit(src).unwrap();
+            assert!(Regex::new(&pattern).is_ok(), "{} -> {}", src, pattern);
+        }
+    }
+
+    #[test]
+    fn test_emit_with_map() {
+        let (flags, ast) = parse("(a)(?:b)(?<c>c)").unwrap();
+        let ir = Compiler::new().compile(&ast);
+        let result = RustRegexEmitter::new(flags).emit_with_map(&ir).unwrap();
+        let caps = Regex::new(&result.pattern).unwrap().captures("abc").unwrap();
+        for mapping in &result.group_map {
+            assert_eq!(result.source_group(mapping.emitted_index), Some(mapping));
+        }
+        assert_eq!(&caps[result.group_map[1].emitted_index], "c");
+        assert_eq!(result.group_map[1].name.as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn test_empty_classes() {
+        let class = |negated| IROp::CharClass(IRCharClass { negated, items: Vec::new(), span: Default::default() });
+        let emitter = RustRegexEmitter::new(Flags::default());
+        let nothing = Regex::new(&emitter.emit(&class(false)).unwrap()).unwrap();
+        let anything = Regex::new(&emitter.emit(&class(true)).unwrap()).unwrap();
+        for text in ["a", "\n", "é"] {
+            assert!(!nothing.is_match(text), "{:?}", text);
+            assert!(anything.is_match(text), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_named_group_and_flags() {
+        assert_eq!(emit("%flags i\n(?<x>a)").unwrap(), "(?i)(?P<x>a)");
+    }
+
+    #[test]
+    fn test_inline_extended_flag_not_emitted() {
+        for (src, text) in [("(?x)a\\ b", "a b"), ("(?x)a\\#b", "a#b")] {
+            let pattern = emit(src).unwrap();
+            assert!(!pattern.contains("(?x)"), "{} -> {}", src, pattern);
+            assert!(Regex::new(&pattern).unwrap().is_match(text), "{}", src);
+        }
+        assert_eq!(emit("(?ix)a\\ b").unwrap(), "(?i)a b");
+    }
+
+    #[test]
+    fn test_gnu_word_anchors() {
+        let pattern = emit("\\<cat\\>").unwrap();
+        assert_eq!(pattern, "\\<cat\\>");
+        let re = Regex::new(&pattern).unwrap();
+        assert!(re.is_match("a cat sat"));
+        assert!(!re.is_match("concat"));
+    }
+
+    #[test]
+    fn test_flags_accessors() {
+        let (flags, _) = parse("%flags is\na").unwrap();
+        let emitter = RustRegexEmitter::new(flags.clone());
+        assert_eq!(emitter.flags(), &flags);
+        assert_eq!(emitter.inline_flags(), flags);
+        assert_eq!(emitter.external_flags(), Flags::none());
+    }
+
+    #[test]
+    fn test_backspace_in_class() {
+        let re = Regex::new(&emit("[\\b]").unwrap()).unwrap();
+        assert!(re.is_match("\u{0008}"));
+        assert!(!re.is_match("b"));
+
+        let re = Regex::new(&emit("\\bword").unwrap()).unwrap();
+        assert!(re.is_match("a word"));
+        assert!(!re.is_match("sword"));
+    }
+
+    #[test]
+    fn test_class_intersection_native() {
+        let pattern = emit("[\\p{L}&&\\p{ASCII}]").unwrap();
+        assert_eq!(pattern, "[[\\p{L}]&&[\\p{ASCII}]]");
+        let re = Regex::new(&format!("^{}$", pattern)).unwrap();
+        assert!(re.is_match("q") && re.is_match("Q"));
+        assert!(!re.is_match("é") && !re.is_match("1"));
+
+        let re = Regex::new(&format!("^{}+$", emit("[^a-z&&[aeiou]]").unwrap())).unwrap();
+        assert!(re.is_match("xyz1E"));
+        assert!(!re.is_match("xaz"));
+    }
+
+    #[test]
+    fn test_possessive_and_atomic_rejected() {
+        // `(?:a|ab)c` would match "abc", which `(?>a|ab)c` does not.
+        assert_eq!(
+            emit("(?>a|ab)c").unwrap_err().to_string(),
+            "STRling Emit Error: Rust regex does not support atomic groups"
+        );
+        assert_eq!(
+            emit("a*+a").unwrap_err().to_string(),
+            "STRling Emit Error: Rust regex does not support possessive quantifiers"
+        );
+        assert!(emit("(?>a*)").is_err());
+    }
+
+    #[test]
+    fn test_unsupported_constructs() {
+        assert_eq!(
+            emit("(a)(?=b)"),
+            Err(EmitError::Unsupported {
+                target: "Rust regex".to_string(),
+                construct: "lookaround".to_string(),
+            })
+        );
+        let err = emit("(?<x>a)\\k<x>").unwrap_err();
+        assert_eq!(err.to_string(), "STRling Emit Error: Rust regex does not support backreferences");
+        assert!(emit("a\\Z").is_err());
+        assert!(emit("(?|(a)|(b))").is_err());
+        assert_eq!(
+            emit("\\Ga").unwrap_err().to_string(),
+            "STRling Emit Error: Rust regex does not support \\G anchor"
+        );
+        assert_eq!(
+            emit("a(*SKIP)b").unwrap_err().to_string(),
+            "STRling Emit Error: Rust regex does not support backtracking control verbs"
+        );
+    }
+}
@@ -9,16 +9,28 @@
 //!
 //! - `core`: Core data structures including AST nodes, IR nodes, and error types
 //! - `emitters`: Target-specific code emitters
+//! - `pattern`: `Pattern`, a parsed and compiled pattern in one value,
+//!   `CompiledPattern`, an emitted one, and the one-call `compile`
+//! - `run`: Match DSL patterns directly with the `regex` crate (`regex` feature)
 
 pub mod core;
 pub mod emitters;
+pub mod pattern;
 pub mod simply;
+#[cfg(feature = "regex")]
+pub mod run;
 
 // Re-export commonly used types for convenience
-pub use core::errors::STRlingParseError;
+pub use core::analyze::{
+    self, diff, diff_ast, explain, is_nullable, lookarounds, lookbehind_is_fixed_length, match_len, AstChange,
+    AstDiff, CaptureMap, Explanation, IrDiff, LookaroundInfo,
+};
+pub use core::errors::{CompileError, EmitError, ParseErrorKind, STRlingError, STRlingParseError, STRlingWarning};
 pub use core::ir::IROp;
-pub use core::nodes::{Flags, Node};
-pub use core::parser::{parse, Parser};
+pub use core::lexer::{Token, TokenKind};
+pub use core::nodes::{AnchorKind, FlagKind, Flags, Node};
+pub use core::parser::{escape_pattern, parse, parse_delimited, Parser};
+pub use pattern::{compile, CompiledPattern, Pattern};
 
 // Re-export simply API for convenient top-level use: `use strling::simply`.
 pub use crate::simply::*;
@@ -0,0 +1,186 @@
+//! STRling Pattern - A parsed and compiled pattern in one value
+//!
+//! `Pattern` runs the parse and compile stages once and keeps every
+//! intermediate result, so callers can pass a single value around and emit
+//! or inspect it as needed. `CompiledPattern` runs the whole pipeline and
+//! keeps only what a caller of the target engine needs.
+
+use crate::core::analyze::CaptureMap;
+use crate::core::compiler::Compiler;
+use crate::core::errors::STRlingError;
+use crate::core::ir::IROp;
+use crate::core::nodes::{Flags, Node};
+use crate::core::parser::parse;
+use crate::emitters::pcre2::PCRE2Emitter;
+use crate::emitters::Flavor;
+use std::fmt;
+use std::str::FromStr;
+
+/// A DSL pattern with its flags, AST and compiled IR
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    flags: Flags,
+    ast: Node,
+    ir: IROp,
+}
+
+impl Pattern {
+    /// The flags set by the pattern's directives
+    pub fn flags(&self) -> &Flags {
+        &self.flags
+    }
+
+    /// The parsed AST
+    pub fn ast(&self) -> &Node {
+        &self.ast
+    }
+
+    /// The compiled IR
+    pub fn ir(&self) -> &IROp {
+        &self.ir
+    }
+
+    /// Emit the pattern as PCRE2 syntax
+    ///
+    /// As with `PCRE2Emitter::emit`, the `i`, `m` and `s` flags are left
+    /// for the caller to pass to the engine; see `flags()`.
+    pub fn pcre2(&self) -> String {
+        PCRE2Emitter::new(self.flags.clone()).emit(&self.ir)
+    }
+
+    /// The capturing groups, by number and name
+    pub fn captures(&self) -> CaptureMap {
+        CaptureMap::from_ir(&self.ir)
+    }
+
+    /// Number of capturing groups, i.e. the highest group number
+    pub fn capture_count(&self) -> usize {
+        self.captures().len()
+    }
+}
+
+impl TryFrom<&str> for Pattern {
+    type Error = STRlingError;
+
+    /// Parse and compile `src`
+    fn try_from(src: &str) -> Result<Self, Self::Error> {
+        let (flags, ast) = parse(src)?;
+        let ir = Compiler::new().with_flags(flags.clone()).try_compile(&ast)?;
+        Ok(Pattern { flags, ast, ir })
+    }
+}
+
+/// An emitted pattern with the flags and capture groups of its source
+///
+/// Parsing a `&str` emits PCRE2: `let pat: CompiledPattern = dsl.parse()?;`.
+/// Use `CompiledPattern::compile` for other flavors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompiledPattern {
+    pattern: String,
+    captures: CaptureMap,
+    flags: Flags,
+}
+
+impl CompiledPattern {
+    /// Parse, compile and emit `src` for `flavor`
+    ///
+    /// # Errors
+    ///
+    /// As for `compile`.
+    pub fn compile(src: &str, flavor: Flavor) -> Result<Self, STRlingError> {
+        let Pattern { flags, ir, .. } = Pattern::try_from(src)?;
+        let pattern = flavor.emit(flags.clone(), &ir)?;
+        Ok(CompiledPattern { pattern, captures: CaptureMap::from_ir(&ir), flags })
+    }
+
+    /// The emitted pattern
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The capturing groups, by number and name
+    pub fn captures(&self) -> &CaptureMap {
+        &self.captures
+    }
+
+    /// The flags set by the source's directives
+    ///
+    /// As with `Pattern::pcre2`, some of them are left for the caller to
+    /// pass to the engine.
+    pub fn flags(&self) -> &Flags {
+        &self.flags
+    }
+}
+
+impl FromStr for CompiledPattern {
+    type Err = STRlingError;
+
+    /// Parse, compile and emit `src` as PCRE2
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        CompiledPattern::compile(src, Flavor::Pcre2)
+    }
+}
+
+impl fmt::Display for CompiledPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pattern)
+    }
+}
+
+/// Parse, compile and emit `src` for `flavor` in one call
+///
+/// # Errors
+///
+/// Returns the failure of whichever stage failed first, as the matching
+/// `STRlingError` variant.
+pub fn compile(src: &str, flavor: Flavor) -> Result<String, STRlingError> {
+    let pattern = Pattern::try_from(src)?;
+    Ok(flavor.emit(pattern.flags, &pattern.ir)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_capture_pattern() {
+        let pattern = Pattern::try_from("(?<x>\\d+)").unwrap();
+        assert_eq!(pattern.capture_count(), 1);
+        assert_eq!(pattern.captures().name(1), Some("x"));
+        assert_eq!(pattern.pcre2(), "(?<x>[\\d]+)");
+        assert_eq!(pattern.flags(), &Flags::default());
+    }
+
+    #[test]
+    fn test_flags_and_errors() {
+        let pattern = Pattern::try_from("%flags i\na(b)(c)").unwrap();
+        assert!(pattern.flags().ignore_case);
+        assert_eq!(pattern.capture_count(), 2);
+        assert_eq!(pattern.pcre2(), "a(b)(c)");
+        assert!(Pattern::try_from("(a").is_err());
+    }
+
+    #[test]
+    fn test_compile_errors_by_stage() {
+        assert_eq!(compile("a+", Flavor::Pcre2).unwrap(), "a+");
+        assert!(matches!(compile("(a", Flavor::Pcre2), Err(STRlingError::Parse(_))));
+        assert!(matches!(compile("(?<x>a)(?<x>b)", Flavor::Pcre2), Err(STRlingError::Compile(_))));
+        let err = compile("(?=a)", Flavor::RustRegex).unwrap_err();
+        assert!(matches!(err, STRlingError::Emit(_)));
+        assert_eq!(err.to_string(), "STRling Emit Error: Rust regex does not support lookaround");
+    }
+
+    #[test]
+    fn test_compiled_pattern_from_str() {
+        let pat: CompiledPattern = "%flags i\n(?<year>\\d{4})-(\\d{2})".parse().unwrap();
+        assert_eq!(pat.as_str(), "(?<year>[\\d]{4})-([\\d]{2})");
+        assert_eq!(pat.to_string(), pat.as_str());
+        assert_eq!(pat.captures().number("year"), Some(1));
+        assert_eq!(pat.captures().len(), 2);
+        assert!(pat.flags().ignore_case);
+
+        assert!(matches!("(a".parse::<CompiledPattern>(), Err(STRlingError::Parse(_))));
+        let rust = CompiledPattern::compile("(?<x>a)", Flavor::RustRegex).unwrap();
+        assert_eq!(rust.as_str(), "(?P<x>a)");
+    }
+}
@@ -0,0 +1,115 @@
+//! STRling Runner - Match DSL patterns with the `regex` crate
+//!
+//! One-call helpers for checking what a DSL pattern matches without wiring
+//! up the parser, compiler and emitter by hand. Requires the `regex` feature.
+
+use crate::core::compiler::Compiler;
+use crate::core::nodes::Flags;
+use crate::core::parser::parse;
+use crate::emitters::dotnet::DotNetEmitter;
+use crate::emitters::pcre2::PCRE2Emitter;
+use crate::emitters::{EmitResult, Flavor};
+use regex::RegexBuilder;
+use std::collections::HashMap;
+
+/// Match `subject` against a DSL pattern and return its captures
+///
+/// The pattern is emitted for `flavor` and run with the `regex` crate, so
+/// the flavor's output must be syntax the crate accepts. Flags a flavor
+/// leaves to the caller (`i`, `m` and `s` for PCRE2 and .NET) are applied
+/// through `RegexBuilder`, which is Unicode-aware already. The map holds the whole match under `"0"`, each
+/// participating group under its number, and named groups additionally
+/// under their name.
+///
+/// Returns `None` if the subject does not match, or if the DSL fails to
+/// parse, emit or compile as a `regex` pattern.
+pub fn captures(dsl: &str, flavor: Flavor, subject: &str) -> Option<HashMap<String, String>> {
+    let (flags, ast) = parse(dsl).ok()?;
+    let ir = Compiler::new().with_flags(flags.clone()).compile(&ast);
+    let (emitted, external) = match flavor {
+        Flavor::Pcre2 => {
+            let emitter = PCRE2Emitter::new(flags);
+            let pattern = emitter.try_emit(&ir).ok()?;
+            (EmitResult::numbered_as_compiled(pattern, &ir), emitter.external_flags())
+        }
+        Flavor::DotNet => {
+            let emitter = DotNetEmitter::new(flags);
+            (emitter.emit_with_map(&ir).ok()?, emitter.external_flags())
+        }
+        Flavor::RustRegex | Flavor::RegexAutomata => (flavor.emit_with_map(flags, &ir).ok()?, Flags::none()),
+    };
+    let re = RegexBuilder::new(&emitted.pattern)
+        .case_insensitive(external.ignore_case)
+        .multi_line(external.multiline)
+        .dot_matches_new_line(external.dot_all)
+        .build()
+        .ok()?;
+    let caps = re.captures(subject)?;
+
+    let mut result = HashMap::new();
+    result.insert("0".to_string(), caps[0].to_string());
+    for mapping in &emitted.group_map {
+        let Some(group) = caps.get(mapping.emitted_index) else {
+            continue;
+        };
+        result.insert(mapping.index.to_string(), group.as_str().to_string());
+        if let Some(name) = &mapping.name {
+            result.insert(name.clone(), group.as_str().to_string());
+        }
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHONE: &str = "(?<area>\\d{3})[-. ]?(?<exchange>\\d{3})[-. ]?(?<line>\\d{4})";
+
+    #[test]
+    fn test_phone_number_captures() {
+        for flavor in [Flavor::Pcre2, Flavor::RustRegex] {
+            let caps = captures(PHONE, flavor, "call 555-123.4567 now").unwrap();
+            assert_eq!(caps["area"], "555");
+            assert_eq!(caps["exchange"], "123");
+            assert_eq!(caps["line"], "4567");
+            assert_eq!(caps["1"], "555");
+            assert_eq!(caps["0"], "555-123.4567");
+        }
+    }
+
+    #[test]
+    fn test_no_match_or_invalid_dsl() {
+        assert_eq!(captures(PHONE, Flavor::Pcre2, "555-12"), None);
+        assert_eq!(captures("(a", Flavor::Pcre2, "a"), None);
+        assert_eq!(captures("(?=a)", Flavor::RustRegex, "a"), None);
+    }
+
+    #[test]
+    fn test_n_never_matches_newline() {
+        assert_eq!(captures("%flags s\n.", Flavor::RustRegex, "\n").unwrap()["0"], "\n");
+        assert_eq!(captures("%flags s\n\\N", Flavor::RustRegex, "\n"), None);
+        assert_eq!(captures("%flags s\n\\N", Flavor::RustRegex, "a").unwrap()["0"], "a");
+    }
+
+    #[test]
+    fn test_caller_applied_flags() {
+        for flavor in [Flavor::Pcre2, Flavor::DotNet, Flavor::RustRegex] {
+            assert_eq!(captures("%flags i\nabc", flavor, "ABC").unwrap()["0"], "ABC", "{:?}", flavor);
+            assert_eq!(captures("%flags m\n^b", flavor, "a\nb").unwrap()["0"], "b", "{:?}", flavor);
+            assert_eq!(captures("%flags s\na.b", flavor, "a\nb").unwrap()["0"], "a\nb", "{:?}", flavor);
+        }
+    }
+
+    #[test]
+    fn test_unicode_flag_with_pcre2() {
+        assert_eq!(captures("%flags u\n\\w+", Flavor::Pcre2, "héllo").unwrap()["0"], "héllo");
+    }
+
+    #[test]
+    fn test_unmatched_optional_group_omitted() {
+        let caps = captures("a(b)?(?<c>c)", Flavor::Pcre2, "ac").unwrap();
+        assert_eq!(caps.get("1"), None);
+        assert_eq!(caps["c"], "c");
+    }
+}
@@ -9,17 +9,17 @@ use crate::core::nodes::*;
 
 /// Anchor at the start of the input.
 pub fn start() -> Node {
-    Node::Anchor(Anchor { at: "Start".into() })
+    Node::Anchor(Anchor { at: AnchorKind::Start, span: Default::default() })
 }
 
 /// Anchor at the end of the input.
 pub fn end() -> Node {
-    Node::Anchor(Anchor { at: "End".into() })
+    Node::Anchor(Anchor { at: AnchorKind::End, span: Default::default() })
 }
 
 /// A literal string.
 pub fn literal(s: &str) -> Node {
-    Node::Literal(Literal { value: s.to_string() })
+    Node::Literal(Literal { value: s.to_string(), span: Default::default() })
 }
 
 /// Helper to build a `\d` character class and repeat it exactly `count` times.
@@ -27,6 +27,7 @@ pub fn digit(count: u32) -> Node {
     let class = Node::CharacterClass(CharacterClass {
         negated: false,
         items: vec![ClassItem::Esc(ClassEscape { escape_type: "d".into(), property: None })],
+        span: Default::default(),
     });
 
     Node::Quantifier(Quantifier {
@@ -37,6 +38,7 @@ pub fn digit(count: u32) -> Node {
         greedy: true,
         lazy: false,
         possessive: false,
+        span: Default::default(),
     })
 }
 
@@ -50,17 +52,17 @@ pub fn any_of(chars: &[&str]) -> Node {
         items.push(ClassItem::Char(ClassLiteral { ch: s.to_string() }));
     }
 
-    Node::CharacterClass(CharacterClass { negated: false, items })
+    Node::CharacterClass(CharacterClass { negated: false, items, span: Default::default() })
 }
 
 /// Create a sequence (merge) from a list of nodes.
 pub fn merge(parts: Vec<Node>) -> Node {
-    Node::Sequence(Sequence { parts })
+    Node::Sequence(Sequence { parts, span: Default::default() })
 }
 
 /// Create a simple capturing group around a node.
 pub fn capture(node: Node) -> Node {
-    Node::Group(Group { capturing: true, body: Box::new(node), name: None, atomic: None })
+    Node::Group(Group { capturing: true, body: Box::new(node), name: None, atomic: None, span: Default::default() })
 }
 
 /// Create a simple non-capturing (or optional) quantifier (0..1)
@@ -73,6 +75,7 @@ pub fn optional(node: Node) -> Node {
         greedy: true,
         lazy: false,
         possessive: false,
+        span: Default::default(),
     })
 }
 
@@ -83,17 +86,17 @@ pub fn may(node: Node) -> Node {
 
 /// Dot (`.`) - any character except newline (represented as a Dot node)
 pub fn dot() -> Node {
-    Node::Dot(Dot {})
+    Node::Dot(Dot { not_newline: false, span: Default::default() })
 }
 
 /// Word boundary anchor: `\b`
 pub fn word_boundary() -> Node {
-    Node::Anchor(Anchor { at: "WordBoundary".into() })
+    Node::Anchor(Anchor { at: AnchorKind::WordBoundary, span: Default::default() })
 }
 
 /// Not-word-boundary anchor: `\B`
 pub fn not_word_boundary() -> Node {
-    Node::Anchor(Anchor { at: "NotWordBoundary".into() })
+    Node::Anchor(Anchor { at: AnchorKind::NotWordBoundary, span: Default::default() })
 }
 
 /// Negated variant of `any_of` -> build `[^...]`
@@ -104,7 +107,7 @@ pub fn not_any_of(chars: &[&str]) -> Node {
         items.push(ClassItem::Char(ClassLiteral { ch: s.to_string() }));
     }
 
-    Node::CharacterClass(CharacterClass { negated: true, items })
+    Node::CharacterClass(CharacterClass { negated: true, items, span: Default::default() })
 }
 
 /// Create ranges from a list of (from, to) tuples.
@@ -115,17 +118,17 @@ pub fn ranges(pairs: &[(&str, &str)]) -> Node {
         items.push(ClassItem::Range(ClassRange { from_ch: from.to_string(), to_ch: to.to_string() }));
     }
 
-    Node::CharacterClass(CharacterClass { negated: false, items })
+    Node::CharacterClass(CharacterClass { negated: false, items, span: Default::default() })
 }
 
 /// Unicode property helper: `\p{...}`
 pub fn prop(property: &str) -> Node {
-    Node::CharacterClass(CharacterClass { negated: false, items: vec![ClassItem::Esc(ClassEscape { escape_type: "p".into(), property: Some(property.to_string()) })] })
+    Node::CharacterClass(CharacterClass { negated: false, items: vec![ClassItem::Esc(ClassEscape { escape_type: "p".into(), property: Some(property.to_string()) })], span: Default::default() })
 }
 
 /// Build a character class containing a single class escape (e.g. `\d`, `\w`, `\s`).
 pub fn class_escape(kind: &str) -> Node {
-    Node::CharacterClass(CharacterClass { negated: false, items: vec![ClassItem::Esc(ClassEscape { escape_type: kind.to_string(), property: None })] })
+    Node::CharacterClass(CharacterClass { negated: false, items: vec![ClassItem::Esc(ClassEscape { escape_type: kind.to_string(), property: None })], span: Default::default() })
 }
 
 /// Escape helpers. These produce Literals for simple escapes.
@@ -141,7 +144,7 @@ pub fn escape(kind: &str) -> Node {
         other => format!("\\{}", other),
     };
 
-    Node::Literal(Literal { value })
+    Node::Literal(Literal { value, span: Default::default() })
 }
 
 /// Control character helper -> `\cX` style. Attempts to return the actual
@@ -155,12 +158,12 @@ pub fn control(ch: char) -> Node {
         // control code is char & 0x1F
         let code = up & 0x1F;
         if let Some(c) = std::char::from_u32(code) {
-            return Node::Literal(Literal { value: c.to_string() });
+            return Node::Literal(Literal { value: c.to_string(), span: Default::default() });
         }
     }
 
     // Fallback — leave it as an explicit textual escape.
-    Node::Literal(Literal { value: format!("\\c{}", ch) })
+    Node::Literal(Literal { value: format!("\\c{}", ch), span: Default::default() })
 }
 
 /// Hex escape `\xHH` or `\x{H...}` — returns a Literal containing the corresponding character if valid
@@ -168,67 +171,67 @@ pub fn hex(code: &str) -> Node {
     // try to parse hex; fall back to literal escape string
     if let Ok(v) = i32::from_str_radix(code.trim_matches(|c| c == '{' || c == '}').trim(), 16) {
         if let Some(ch) = std::char::from_u32(v as u32) {
-            return Node::Literal(Literal { value: ch.to_string() });
+            return Node::Literal(Literal { value: ch.to_string(), span: Default::default() });
         }
     }
 
-    Node::Literal(Literal { value: format!("\\x{{{}}}", code) })
+    Node::Literal(Literal { value: format!("\\x{{{}}}", code), span: Default::default() })
 }
 
 /// Unicode codepoint escape `\u{...}` -> produce a literal of that codepoint when possible
 pub fn unicode(code: &str) -> Node {
     if let Ok(v) = i32::from_str_radix(code.trim_matches(|c| c == '{' || c == '}').trim(), 16) {
         if let Some(ch) = std::char::from_u32(v as u32) {
-            return Node::Literal(Literal { value: ch.to_string() });
+            return Node::Literal(Literal { value: ch.to_string(), span: Default::default() });
         }
     }
 
-    Node::Literal(Literal { value: format!("\\u{{{}}}", code) })
+    Node::Literal(Literal { value: format!("\\u{{{}}}", code), span: Default::default() })
 }
 
 /// Named capturing group: `(?<name>...)`
 pub fn named_capture(name: &str, node: Node) -> Node {
-    Node::Group(Group { capturing: true, body: Box::new(node), name: Some(name.to_string()), atomic: None })
+    Node::Group(Group { capturing: true, body: Box::new(node), name: Some(name.to_string()), atomic: None, span: Default::default() })
 }
 
 /// Non-capturing group: `(?:...)`
 pub fn non_capturing(node: Node) -> Node {
-    Node::Group(Group { capturing: false, body: Box::new(node), name: None, atomic: None })
+    Node::Group(Group { capturing: false, body: Box::new(node), name: None, atomic: None, span: Default::default() })
 }
 
 /// Atomic group: `(?>...)` — keep capturing flag true by default to match existing examples
 pub fn atomic(node: Node) -> Node {
-    Node::Group(Group { capturing: true, body: Box::new(node), name: None, atomic: Some(true) })
+    Node::Group(Group { capturing: true, body: Box::new(node), name: None, atomic: Some(true), span: Default::default() })
 }
 
 /// Positive lookahead `(?=...)`
 pub fn look_ahead(node: Node) -> Node {
-    Node::Lookahead(LookaroundBody { body: Box::new(node) })
+    Node::Lookahead(LookaroundBody { body: Box::new(node), span: Default::default() })
 }
 
 /// Negative lookahead `(?!...)`
 pub fn neg_look_ahead(node: Node) -> Node {
-    Node::NegativeLookahead(LookaroundBody { body: Box::new(node) })
+    Node::NegativeLookahead(LookaroundBody { body: Box::new(node), span: Default::default() })
 }
 
 /// Positive lookbehind `(?<=...)`
 pub fn look_behind(node: Node) -> Node {
-    Node::Lookbehind(LookaroundBody { body: Box::new(node) })
+    Node::Lookbehind(LookaroundBody { body: Box::new(node), span: Default::default() })
 }
 
 /// Negative lookbehind `(?<!...)`
 pub fn neg_look_behind(node: Node) -> Node {
-    Node::NegativeLookbehind(LookaroundBody { body: Box::new(node) })
+    Node::NegativeLookbehind(LookaroundBody { body: Box::new(node), span: Default::default() })
 }
 
 /// Backreference by index (\1)
 pub fn backref_index(i: i32) -> Node {
-    Node::Backreference(Backreference { by_index: Some(i), by_name: None })
+    Node::Backreference(Backreference { by_index: Some(i), by_name: None, span: Default::default() })
 }
 
 /// Backreference by name (\k<name>)
 pub fn backref_name(name: &str) -> Node {
-    Node::Backreference(Backreference { by_index: None, by_name: Some(name.to_string()) })
+    Node::Backreference(Backreference { by_index: None, by_name: Some(name.to_string()), span: Default::default() })
 }
 
 /// Helper to construct Flags from a letters string (e.g. "imx")
@@ -238,7 +241,7 @@ pub fn flag(letters: &str) -> Flags {
 
 /// Create an alternation node from branches
 pub fn alternation(branches: Vec<Node>) -> Node {
-    Node::Alternation(Alternation { branches })
+    Node::Alternation(Alternation { branches, span: Default::default() })
 }
 
 /// Convenience: either(left, right) -> alternation with two branches
@@ -253,7 +256,7 @@ pub fn repeat(node: Node, min: i32, max: Option<i32>) -> Node {
         None => MaxBound::Infinite("Inf".to_string()),
     };
 
-    Node::Quantifier(Quantifier { target: QuantifierTarget { child: Box::new(node) }, min, max: maxbound, mode: "Greedy".to_string(), greedy: true, lazy: false, possessive: false })
+    Node::Quantifier(Quantifier { target: QuantifierTarget { child: Box::new(node) }, min, max: maxbound, mode: "Greedy".to_string(), greedy: true, lazy: false, possessive: false, span: Default::default() })
 }
 
 /// Greedy repeat helper
@@ -262,14 +265,11 @@ pub fn repeat_greedy(node: Node, min: i32, max: Option<i32>) -> Node { repeat(no
 /// Lazy repeat helper
 pub fn repeat_lazy(node: Node, min: i32, max: Option<i32>) -> Node {
     let mut n = repeat(node, min, max);
-    match &mut n {
-        Node::Quantifier(ref mut q) => {
-            q.mode = "Lazy".to_string();
-            q.greedy = false;
-            q.lazy = true;
-            q.possessive = false;
-        }
-        _ => {}
+    if let Node::Quantifier(ref mut q) = n {
+        q.mode = "Lazy".to_string();
+        q.greedy = false;
+        q.lazy = true;
+        q.possessive = false;
     }
     n
 }
@@ -283,14 +283,11 @@ pub fn lazy(node: Node) -> Node {
 /// Possessive repeat helper
 pub fn repeat_possessive(node: Node, min: i32, max: Option<i32>) -> Node {
     let mut n = repeat(node, min, max);
-    match &mut n {
-        Node::Quantifier(ref mut q) => {
-            q.mode = "Possessive".to_string();
-            q.greedy = false;
-            q.lazy = false;
-            q.possessive = true;
-        }
-        _ => {}
+    if let Node::Quantifier(ref mut q) = n {
+        q.mode = "Possessive".to_string();
+        q.greedy = false;
+        q.lazy = false;
+        q.possessive = true;
     }
     n
 }
@@ -345,7 +342,7 @@ mod tests {
 
                 // first part is start anchor
                 match seq.parts[0] {
-                    Node::Anchor(ref a) => assert_eq!(a.at, "Start"),
+                    Node::Anchor(ref a) => assert_eq!(a.at, AnchorKind::Start),
                     _ => panic!("expected start anchor"),
                 }
 
@@ -372,7 +369,7 @@ mod tests {
 
                 // fourth part is end anchor
                 match seq.parts[3] {
-                    Node::Anchor(ref a) => assert_eq!(a.at, "End"),
+                    Node::Anchor(ref a) => assert_eq!(a.at, AnchorKind::End),
                     _ => panic!("expected end anchor"),
                 }
             }
@@ -390,12 +387,12 @@ mod tests {
 
         // word boundaries
         match word_boundary() {
-            Node::Anchor(a) => assert_eq!(a.at, "WordBoundary"),
+            Node::Anchor(a) => assert_eq!(a.at, AnchorKind::WordBoundary),
             _ => panic!("expected Anchor"),
         }
 
         match not_word_boundary() {
-            Node::Anchor(a) => assert_eq!(a.at, "NotWordBoundary"),
+            Node::Anchor(a) => assert_eq!(a.at, AnchorKind::NotWordBoundary),
             _ => panic!("expected Anchor"),
         }
 
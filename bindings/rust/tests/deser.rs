@@ -1,4 +1,3 @@
-use serde_json;
 use strling::core::nodes::*;
 
 #[test]
@@ -32,7 +31,7 @@ fn quantifier_target_accepts_target_and_child() {
     let q: QuantifierTarget = serde_json::from_str(j).expect("Failed to deserialize QuantifierTarget with 'target'");
 
     match *q.child {
-        Node::Literal(Literal { value }) => assert_eq!(value, "a"),
+        Node::Literal(Literal { value, .. }) => assert_eq!(value, "a"),
         _ => panic!("expected Literal child"),
     }
 
@@ -41,7 +40,7 @@ fn quantifier_target_accepts_target_and_child() {
     let q2: QuantifierTarget = serde_json::from_str(j2).expect("Failed to deserialize QuantifierTarget with 'child'");
 
     match *q2.child {
-        Node::Literal(Literal { value }) => assert_eq!(value, "b"),
+        Node::Literal(Literal { value, .. }) => assert_eq!(value, "b"),
         _ => panic!("expected Literal child"),
     }
 }
@@ -1,8 +1,8 @@
 //! Integration tests for STRling core data structures
 
-use strling::core::nodes::{Flags, Literal, Node};
+use strling::core::nodes::{FlagKind, Flags, Literal, Node};
 use strling::core::ir::{IRLit, IROp, IROpTrait};
-use strling::core::errors::STRlingParseError;
+use strling::core::errors::{ParseErrorKind, STRlingParseError};
 
 #[test]
 fn test_flags_from_letters() {
@@ -10,10 +10,32 @@ fn test_flags_from_letters() {
     assert!(flags.ignore_case);
     assert!(flags.multiline);
     assert!(flags.dot_all);
-    assert!(!flags.unicode);
+    assert!(flags.unicode);
     assert!(!flags.extended);
 }
 
+#[test]
+fn test_flags_from_str_round_trip() {
+    let flags: Flags = "ims".parse().unwrap();
+    assert_eq!(flags, Flags::from_letters("ims"));
+    assert_eq!(flags.to_flag_string(), "imsu");
+    assert_eq!("x, a".parse::<Flags>().unwrap().to_flag_string(), "uxa");
+    assert_eq!(Flags::default().to_flag_string(), "u");
+    assert_eq!(Flags::none().to_flag_string(), "");
+    assert!("I".parse::<Flags>().is_err());
+
+    let err = "imq".parse::<Flags>().unwrap_err();
+    assert_eq!(err.message, "Unknown flag: q");
+    assert_eq!(err.kind, ParseErrorKind::InvalidFlag { span: (2, 3) });
+}
+
+#[test]
+fn test_active_flags() {
+    let (flags, _) = strling::parse("%flags i,s\na").unwrap();
+    assert_eq!(flags.active().collect::<Vec<_>>(), [FlagKind::IgnoreCase, FlagKind::DotAll, FlagKind::Unicode]);
+    assert_eq!(Flags::none().active().count(), 0);
+}
+
 #[test]
 fn test_flags_to_dict() {
     let flags = Flags::from_letters("imu");
@@ -29,6 +51,7 @@ fn test_flags_to_dict() {
 fn test_ast_node_serialization() {
     let lit_node = Node::Literal(Literal {
         value: "test".to_string(),
+        span: Default::default(),
     });
     let json = serde_json::to_value(&lit_node).unwrap();
 
@@ -39,7 +62,8 @@ fn test_ast_node_serialization() {
 #[test]
 fn test_ir_node_serialization() {
     let ir_lit = IROp::Lit(IRLit {
-        value: "test".to_string(),
+        value: "test".into(),
+        span: Default::default(),
     });
     let ir_json = ir_lit.to_dict();
 
@@ -100,6 +124,6 @@ fn test_default_flags() {
     assert!(!flags.ignore_case);
     assert!(!flags.multiline);
     assert!(!flags.dot_all);
-    assert!(!flags.unicode);
+    assert!(flags.unicode);
     assert!(!flags.extended);
 }
@@ -0,0 +1,38 @@
+//! Token Stream Tests over the Shared Spec Fixtures
+//!
+//! For every `input_dsl` in `tests/spec`, the tokens must tile exactly the
+//! text the parser consumes, with `Invalid` tokens exactly where it fails.
+
+use glob::glob;
+use strling::core::parser::Parser;
+use strling::TokenKind;
+
+#[test]
+fn tokens_cover_parsed_spec_inputs() {
+    let mut checked = 0;
+    for path in glob("../../tests/spec/*.json").expect("valid glob").flatten() {
+        let content = std::fs::read_to_string(&path).expect("Failed to read file");
+        let fixture: serde_json::Value = serde_json::from_str(&content).expect("Failed to parse fixture");
+        let Some(dsl) = fixture["input_dsl"].as_str() else { continue };
+
+        let parser = Parser::new(dsl.to_string());
+        let tokens: Vec<_> = parser.tokens().collect();
+        let mut end = 0;
+        for token in &tokens {
+            assert_eq!(token.span.0, end, "gap before {:?} in {}", token, path.display());
+            assert!(token.span.1 > token.span.0, "empty {:?} in {}", token, path.display());
+            end = token.span.1;
+        }
+
+        let invalid = tokens.iter().any(|t| t.kind == TokenKind::Invalid);
+        if parser.clone().parse().is_ok() {
+            let (_, _, consumed) = parser.clone().parse_partial().expect("parsed above");
+            assert_eq!(end, consumed, "tokens end before the parse in {}", path.display());
+            assert!(!invalid, "invalid token in {}", path.display());
+        } else {
+            assert!(invalid, "no invalid token in {}", path.display());
+        }
+        checked += 1;
+    }
+    assert!(checked > 0, "no spec fixtures found");
+}
@@ -12,7 +12,7 @@ use strling::core::nodes::*;
 use strling::core::nodes::MaxBound;
 use strling::core::ir::*;
 #[allow(unused_imports)]
-use strling::core::errors::STRlingParseError;
+use strling::core::errors::{STRlingParseError, STRlingWarning};
 use strling::emitters::pcre2::PCRE2Emitter;
 
 // ============================================================================
@@ -353,7 +353,7 @@ mod parser_tests {
         let (_flags, ast) = parser.parse().unwrap();
 
         match ast {
-            Node::Anchor(a) => assert_eq!(a.at, "Start"),
+            Node::Anchor(a) => assert_eq!(a.at, AnchorKind::Start),
             _ => panic!("Expected Anchor node"),
         }
     }
@@ -364,7 +364,7 @@ mod parser_tests {
         let (_flags, ast) = parser.parse().unwrap();
 
         match ast {
-            Node::Anchor(a) => assert_eq!(a.at, "End"),
+            Node::Anchor(a) => assert_eq!(a.at, AnchorKind::End),
             _ => panic!("Expected Anchor node"),
         }
     }
@@ -375,7 +375,7 @@ mod parser_tests {
         let (_flags, ast) = parser.parse().unwrap();
 
         match ast {
-            Node::Anchor(a) => assert_eq!(a.at, "WordBoundary"),
+            Node::Anchor(a) => assert_eq!(a.at, AnchorKind::WordBoundary),
             _ => panic!("Expected Anchor node"),
         }
     }
@@ -448,6 +448,36 @@ mod parser_tests {
         let result = parser.parse();
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_parse_round_trip_equality_and_hash() {
+        use std::collections::HashMap;
+
+        let src = "(?<x>a|b)+?\\k<x>[^c]\\d{2,4}$";
+        let first = Parser::new(src.to_string()).parse().unwrap().1;
+        let second = Parser::new(src.to_string()).parse().unwrap().1;
+        assert_eq!(first, second);
+
+        let mut by_ast: HashMap<Node, &str> = HashMap::new();
+        by_ast.insert(first, src);
+        assert_eq!(by_ast.get(&second), Some(&src));
+
+        let ir = Compiler::new().compile(&second);
+        let mut by_ir: HashMap<IROp, usize> = HashMap::new();
+        *by_ir.entry(ir.clone()).or_default() += 1;
+        *by_ir.entry(ir).or_default() += 1;
+        assert_eq!(by_ir.len(), 1);
+    }
+
+    #[test]
+    fn test_node_iter_pre_order() {
+        let (_flags, ast) = Parser::new("((a)(b))".to_string()).parse().unwrap();
+        assert_eq!(ast.iter().count(), 6);
+        let groups = ast.iter().filter(|node| matches!(node, Node::Group(_))).count();
+        assert_eq!(groups, 3);
+        assert!(matches!(ast.iter().next(), Some(Node::Group(_))));
+        assert!(matches!(ast.iter().last(), Some(Node::Literal(lit)) if lit.value == "b"));
+    }
 }
 
 // ============================================================================
@@ -469,7 +499,7 @@ mod compiler_tests {
     fn test_compile_literal() {
         let ir = compile("hello");
         match ir {
-            IROp::Lit(lit) => assert_eq!(lit.value, "hello"),
+            IROp::Lit(lit) => assert_eq!(&*lit.value, "hello"),
             _ => panic!("Expected IRLit"),
         }
     }
@@ -493,7 +523,7 @@ mod compiler_tests {
             IROp::Quant(q) => {
                 assert_eq!(q.min, 1);
                 assert_eq!(q.max, IRMaxBound::Infinite("Inf".to_string()));
-                assert_eq!(q.mode, "Greedy");
+                assert_eq!(q.mode, QuantMode::Greedy);
             }
             _ => panic!("Expected IRQuant"),
         }
@@ -528,7 +558,7 @@ mod compiler_tests {
         let ir = compile("a+?");
         match ir {
             IROp::Quant(q) => {
-                assert_eq!(q.mode, "Lazy");
+                assert_eq!(q.mode, QuantMode::Lazy);
             }
             _ => panic!("Expected IRQuant"),
         }
@@ -541,6 +571,7 @@ mod compiler_tests {
             IROp::Group(g) => {
                 assert!(g.capturing);
                 assert!(g.name.is_none());
+                assert_eq!(g.index, 1);
             }
             _ => panic!("Expected IRGroup"),
         }
@@ -553,6 +584,7 @@ mod compiler_tests {
             IROp::Group(g) => {
                 assert!(g.capturing);
                 assert_eq!(g.name, Some("foo".to_string()));
+                assert_eq!(g.index, 1);
             }
             _ => panic!("Expected IRGroup"),
         }
@@ -564,11 +596,83 @@ mod compiler_tests {
         match ir {
             IROp::Group(g) => {
                 assert!(!g.capturing);
+                assert_eq!(g.index, 0);
             }
             _ => panic!("Expected IRGroup"),
         }
     }
 
+    #[test]
+    fn test_compile_group_indices_follow_open_parens() {
+        let ir = compile("((a)(b))");
+        match ir {
+            IROp::Group(outer) => {
+                assert_eq!(outer.index, 1);
+                match *outer.body {
+                    IROp::Seq(ref seq) => {
+                        let inner: Vec<usize> = seq.parts.iter().map(|p| match p {
+                            IROp::Group(g) => g.index,
+                            _ => panic!("Expected IRGroup"),
+                        }).collect();
+                        assert_eq!(inner, vec![2, 3]);
+                    }
+                    _ => panic!("Expected IRSeq"),
+                }
+            }
+            _ => panic!("Expected IRGroup"),
+        }
+    }
+
+    #[test]
+    fn test_compiler_reuse_is_independent() {
+        let parse = |src: &str| Parser::new(src.to_string()).parse().unwrap().1;
+        let mut compiler = Compiler::new();
+
+        let first = compiler.compile_with_metadata(&parse("(a)(?<=b)\\1"));
+        let second = compiler.compile_with_metadata(&parse("(c)"));
+
+        assert_eq!(second.ir, Compiler::new().compile(&parse("(c)")));
+        match second.ir {
+            IROp::Group(g) => assert_eq!(g.index, 1),
+            _ => panic!("Expected IRGroup"),
+        }
+        assert!(first.metadata.features_used.contains(&"lookbehind".to_string()));
+        assert!(second.metadata.features_used.is_empty());
+    }
+
+    #[test]
+    fn test_compile_branch_reset_shares_indices() {
+        let index = |op: &IROp| match op {
+            IROp::Group(g) => g.index,
+            _ => panic!("Expected IRGroup"),
+        };
+        match compile("(?|(a)|(b))(c)") {
+            IROp::Seq(seq) => {
+                match &seq.parts[0] {
+                    IROp::BranchReset(reset) => {
+                        let indices: Vec<usize> = reset.branches.iter().map(index).collect();
+                        assert_eq!(indices, vec![1, 1]);
+                    }
+                    _ => panic!("Expected IRBranchReset"),
+                }
+                assert_eq!(index(&seq.parts[1]), 2);
+            }
+            _ => panic!("Expected IRSeq"),
+        }
+    }
+
+    #[test]
+    fn test_compile_noncapturing_group_takes_no_index() {
+        let ir = compile("(?:a)(b)");
+        match ir {
+            IROp::Seq(seq) => match &seq.parts[1] {
+                IROp::Group(g) => assert_eq!(g.index, 1),
+                _ => panic!("Expected IRGroup"),
+            },
+            _ => panic!("Expected IRSeq"),
+        }
+    }
+
     #[test]
     fn test_compile_alternation() {
         let ir = compile("a|b");
@@ -596,7 +700,7 @@ mod compiler_tests {
         let ir = compile("(?=a)");
         match ir {
             IROp::Look(look) => {
-                assert_eq!(look.dir, "Ahead");
+                assert_eq!(look.dir, LookDir::Ahead);
                 assert!(!look.neg);
             }
             _ => panic!("Expected IRLook"),
@@ -608,7 +712,7 @@ mod compiler_tests {
         let ir = compile("(?!a)");
         match ir {
             IROp::Look(look) => {
-                assert_eq!(look.dir, "Ahead");
+                assert_eq!(look.dir, LookDir::Ahead);
                 assert!(look.neg);
             }
             _ => panic!("Expected IRLook"),
@@ -620,7 +724,7 @@ mod compiler_tests {
         let ir = compile("(?<=a)");
         match ir {
             IROp::Look(look) => {
-                assert_eq!(look.dir, "Behind");
+                assert_eq!(look.dir, LookDir::Behind);
                 assert!(!look.neg);
             }
             _ => panic!("Expected IRLook"),
@@ -632,7 +736,7 @@ mod compiler_tests {
         let ir = compile("(?<!a)");
         match ir {
             IROp::Look(look) => {
-                assert_eq!(look.dir, "Behind");
+                assert_eq!(look.dir, LookDir::Behind);
                 assert!(look.neg);
             }
             _ => panic!("Expected IRLook"),
@@ -643,7 +747,7 @@ mod compiler_tests {
     fn test_compile_anchor_start() {
         let ir = compile("^");
         match ir {
-            IROp::Anchor(a) => assert_eq!(a.at, "Start"),
+            IROp::Anchor(a) => assert_eq!(a.at, AnchorKind::Start),
             _ => panic!("Expected IRAnchor"),
         }
     }
@@ -652,7 +756,7 @@ mod compiler_tests {
     fn test_compile_anchor_end() {
         let ir = compile("$");
         match ir {
-            IROp::Anchor(a) => assert_eq!(a.at, "End"),
+            IROp::Anchor(a) => assert_eq!(a.at, AnchorKind::End),
             _ => panic!("Expected IRAnchor"),
         }
     }
@@ -661,11 +765,20 @@ mod compiler_tests {
     fn test_compile_dot() {
         let ir = compile(".");
         match ir {
-            IROp::Dot(_) => {}
+            IROp::Dot(dot) => assert!(!dot.dotall),
             _ => panic!("Expected IRDot"),
         }
     }
 
+    #[test]
+    fn test_compile_dot_under_dotall_flag() {
+        let mut parser = Parser::new("%flags s\n.".to_string());
+        let (flags, ast) = parser.parse().unwrap();
+        let ir = Compiler::new().with_flags(flags).compile(&ast);
+        assert_eq!(ir, IROp::Dot(IRDot { dotall: true, not_newline: false, span: Default::default() }));
+        assert_eq!(ir.to_dict()["dotall"], true);
+    }
+
     #[test]
     fn test_compile_backreference() {
         let ir = compile("(a)\\1");
@@ -677,6 +790,20 @@ mod compiler_tests {
             _ => panic!("Expected IRSeq"),
         }
     }
+
+    #[test]
+    fn test_ir_iter_pre_order() {
+        let ir = compile("((a)(b))");
+        let kinds: Vec<String> = ir.iter().map(|op| match op {
+            IROp::Group(group) => format!("group {}", group.index),
+            IROp::Seq(_) => "seq".to_string(),
+            IROp::Lit(lit) => lit.value.to_string(),
+            other => panic!("unexpected op {:?}", other),
+        }).collect();
+        assert_eq!(kinds, ["group 1", "seq", "group 2", "a", "group 3", "b"]);
+        assert!(!ir.iter().any(|op| matches!(op, IROp::Backref(_))));
+        assert!(compile("(?<x>a)\\k<x>").iter().any(|op| matches!(op, IROp::Backref(_))));
+    }
 }
 
 // ============================================================================
@@ -937,5 +1064,35 @@ mod interaction_tests {
         assert!(result.contains("a-z"));
         assert!(result.contains("0-9"));
     }
-}
 
+    #[test]
+    fn test_combine_parsed_fragments() {
+        let parse = |src: &str| Parser::new(src.to_string()).parse().unwrap().1;
+        let emit = |node: Node| PCRE2Emitter::new(Flags::default()).emit(&Compiler::new().compile(&node));
+        assert_eq!(emit(parse("a").or(parse("b"))), "a|b");
+        assert_eq!(emit(parse("a|b").or(parse("c"))), "a|b|c");
+        assert_eq!(emit(parse("ab").concat(parse("c|d"))), "ab(?:c|d)");
+        assert_eq!(emit(parse("x|y").concat(parse("z"))), "(?:x|y)z");
+        assert_eq!(emit(parse("(?i)a").concat(parse("b"))), "(?:(?i)a)b");
+        assert_eq!(emit(parse("(?i)a|b").or(parse("c"))), "(?:(?i)a|b)|c");
+        assert_eq!(emit(Node::seq(vec![parse("(a)"), parse("b"), parse("(c)")])), "(a)b(c)");
+        assert_eq!(emit(Node::alt(vec![parse("a")])), "a");
+    }
+
+    #[test]
+    fn test_empty_alternation_branches_diverge_from_spec() {
+        // The shared fixtures stray_pipe and error_alternation_empty expect a
+        // parse error for these; this binding accepts them and warns.
+        for (src, branch, emitted) in [("|", 0, "|"), ("a||b", 1, "a||b")] {
+            let (_, ast) = Parser::new(src.to_string()).parse().unwrap();
+            let mut compiler = Compiler::new();
+            let ir = compiler.compile(&ast);
+            assert_eq!(PCRE2Emitter::new(Flags::default()).emit(&ir), emitted);
+            assert!(
+                compiler.warnings().iter().any(|w| matches!(w, STRlingWarning::EmptyAlternationBranch { branch: b, .. } if *b == branch)),
+                "{}",
+                src
+            );
+        }
+    }
+}